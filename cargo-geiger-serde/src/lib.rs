@@ -12,7 +12,10 @@ mod source;
 
 pub use package_id::PackageId;
 pub use report::{
-    Count, CounterBlock, DependencyKind, PackageInfo, QuickReportEntry,
-    QuickSafetyReport, ReportEntry, SafetyReport, UnsafeInfo,
+    Count, CounterBlock, DependencyKind, FileErrorKind, FileWithError,
+    FunctionMetrics, Hotspot, LineColumn, LintLevel, PackageInfo,
+    QuickReportEntry, QuickSafetyReport, ReportEntry, SafetyCommentCount,
+    SafetyReport, TargetUnsafeInfo, UnitUnsafeInfo, UnsafeFinding, UnsafeInfo,
+    UnsafeKind,
 };
 pub use source::Source;
@@ -1,7 +1,7 @@
 use crate::PackageId;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     ops::{Add, AddAssign},
     path::PathBuf,
 };
@@ -41,19 +41,33 @@ impl PackageInfo {
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct QuickReportEntry {
     pub package: PackageInfo,
-    /// Whether this package forbids the use of `unsafe`
+    /// Whether this package forbids the use of `unsafe`, same as
+    /// `unsafe_code_lint` being `Forbid`
     pub forbids_unsafe: bool,
+    /// Level of the `unsafe_code` lint declared by the package
+    #[serde(default)]
+    pub unsafe_code_lint: LintLevel,
+    /// Level of the `unsafe_op_in_unsafe_fn` lint declared by the package
+    #[serde(default)]
+    pub unsafe_op_in_unsafe_fn_lint: LintLevel,
 }
 
 /// Report generated from scanning for packages that forbid the use of `unsafe`
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct QuickSafetyReport {
+    /// Root packages of the scanned dependency trees, all the scanned
+    /// members when scanning a workspace
+    #[serde(default)]
+    pub roots: Vec<PackageId>,
     /// Packages that were scanned successfully
     #[serde(with = "entry_serde")]
     pub packages: HashMap<PackageId, QuickReportEntry>,
     /// Packages that were not scanned successfully
     #[serde(serialize_with = "set_serde::serialize")]
     pub packages_without_metrics: HashSet<PackageId>,
+    /// Source files that could not be analysed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files_with_errors: Vec<FileWithError>,
 }
 
 /// Entry of the report generated from scanning for the use of `unsafe`
@@ -67,12 +81,29 @@ pub struct ReportEntry {
 /// Report generated from scanning for the use of `unsafe`
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct SafetyReport {
+    /// Root packages of the scanned dependency trees, all the scanned
+    /// members when scanning a workspace
+    #[serde(default)]
+    pub roots: Vec<PackageId>,
     #[serde(with = "entry_serde")]
     pub packages: HashMap<PackageId, ReportEntry>,
     #[serde(serialize_with = "set_serde::serialize")]
     pub packages_without_metrics: HashSet<PackageId>,
     #[serde(serialize_with = "set_serde::serialize")]
     pub used_but_not_scanned_files: HashSet<PathBuf>,
+    /// Functions with the most unsafe expressions in code used by the
+    /// project, ranked across all packages. Only collected on request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hotspots: Vec<Hotspot>,
+    /// Source files that could not be analysed, their unsafe usage is
+    /// missing from the report
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files_with_errors: Vec<FileWithError>,
+    /// The files used by the build were approximated from the module tree
+    /// of each build target without building, so the split into used and
+    /// unused code is approximate
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub used_files_approximate: bool,
 }
 
 /// Unsafety usage in a package
@@ -82,8 +113,100 @@ pub struct UnsafeInfo {
     pub used: CounterBlock,
     /// Unsafe usage statistics for code not used by the project
     pub unused: CounterBlock,
-    /// Whether this package forbids the use of `unsafe`
+    /// Whether this package forbids the use of `unsafe`, same as
+    /// `unsafe_code_lint` being `Forbid`
     pub forbids_unsafe: bool,
+    /// Level of the `unsafe_code` lint declared by the package
+    #[serde(default)]
+    pub unsafe_code_lint: LintLevel,
+    /// Level of the `unsafe_op_in_unsafe_fn` lint declared by the package
+    #[serde(default)]
+    pub unsafe_op_in_unsafe_fn_lint: LintLevel,
+    /// Locations of `unsafe` usage in code used by the project
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<UnsafeFinding>,
+    /// Unsafe usage only present after macro expansion, e.g. generated by
+    /// `macro_rules!`, proc-macros or `include!`d build script output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub macro_expanded: Option<CounterBlock>,
+    /// Unsafe usage in code used by the project that is disabled by a
+    /// `#[cfg(...)]` attribute for the scanned target and features
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cfg_disabled: Option<CounterBlock>,
+    /// Safety documentation of the `unsafe` blocks, impls and public
+    /// functions in code used by the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_comments: Option<SafetyCommentCount>,
+    /// Some source files of the package could not be parsed, their unsafe
+    /// usage was counted from their tokens and is approximate
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub approximate: bool,
+    /// Unsafe usage statistics of the build of each target when several
+    /// targets were scanned, `used` and `unused` are then the ones of the
+    /// first target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, TargetUnsafeInfo>,
+    /// Unsafe usage statistics of each unit of the build of the package when
+    /// it was built as several units, e.g. a library and a binary
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub units: Vec<UnitUnsafeInfo>,
+}
+
+/// Unsafe usage in a package for the build of a single target
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TargetUnsafeInfo {
+    /// Unsafe usage statistics for code used by the build of the target
+    pub used: CounterBlock,
+    /// Unsafe usage statistics for code not used by the build of the target
+    pub unused: CounterBlock,
+}
+
+/// Unsafe usage in a package for a single unit of the build
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct UnitUnsafeInfo {
+    /// Kind of the build target of the unit, e.g. `lib`, `bin` or
+    /// `build-script`
+    pub target_kind: String,
+    /// Name of the build target of the unit
+    pub target_name: String,
+    /// Unsafe usage statistics for code used by the unit
+    pub used: CounterBlock,
+    /// Unsafe usage statistics for code of the package not used by the unit
+    pub unused: CounterBlock,
+}
+
+/// Level of a lint declared by a package, with `#![forbid(..)]` style crate
+/// attributes or in the `[lints]` table of its manifest. Ordered from the
+/// least to the most strict level
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub enum LintLevel {
+    /// The lint is allowed, also when it is not declared at all
+    Allow,
+    /// The lint emits warnings
+    Warn,
+    /// The lint emits errors, but can be allowed again inside the crate
+    Deny,
+    /// The lint emits errors and can not be allowed inside the crate
+    Forbid,
+}
+
+// `#[default]` on enum variants needs Rust 1.62.
+#[allow(clippy::derivable_impls)]
+impl Default for LintLevel {
+    fn default() -> Self {
+        LintLevel::Allow
+    }
 }
 
 /// Kind of dependency for a package
@@ -97,6 +220,17 @@ pub enum DependencyKind {
     Build,
 }
 
+/// Number of `unsafe` blocks and impls with and without a `// SAFETY:`
+/// comment, including public `unsafe fn`s with and without a `# Safety` doc
+/// section
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SafetyCommentCount {
+    /// Number of documented `unsafe` usages
+    pub documented: u64,
+    /// Number of undocumented `unsafe` usages
+    pub undocumented: u64,
+}
+
 /// Statistics about the use of `unsafe`
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Count {
@@ -115,6 +249,14 @@ impl Count {
             self.safe += 1;
         }
     }
+
+    /// Subtracts both counters, stopping at zero
+    pub fn saturating_sub(&self, other: &Count) -> Count {
+        Count {
+            safe: self.safe.saturating_sub(other.safe),
+            unsafe_: self.unsafe_.saturating_sub(other.unsafe_),
+        }
+    }
 }
 
 impl Add for Count {
@@ -134,7 +276,9 @@ impl AddAssign for Count {
     }
 }
 
-/// Unsafe usage metrics collection.
+/// Unsafe usage metrics collection. The counters after `macros` are only
+/// reported, they do not count towards `has_unsafe`, policy budgets or the
+/// diff of two reports.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct CounterBlock {
     pub functions: Count,
@@ -142,15 +286,76 @@ pub struct CounterBlock {
     pub item_impls: Count,
     pub item_traits: Count,
     pub methods: Count,
+    /// Macro invocations and definitions with the `unsafe` keyword in their
+    /// tokens. Detected heuristically, since `syn` does not parse macro input
+    #[serde(default)]
+    pub macros: Count,
+    /// Functions, statics and types declared in `extern` blocks, all of
+    /// them are unsafe to use
+    #[serde(default)]
+    pub foreign_items: Count,
+    /// `static` items, where `static mut` ones are counted as unsafe
+    #[serde(default)]
+    pub statics: Count,
+    /// `union` definitions, reading their fields is unsafe
+    #[serde(default)]
+    pub unions: Count,
+    /// Structs and unions, where `#[repr(packed)]` ones are counted as unsafe
+    #[serde(default)]
+    pub repr_packed: Count,
+    /// `asm!` and `global_asm!` invocations
+    #[serde(default)]
+    pub asm: Count,
+    /// Items with a `#[link_section]` attribute
+    #[serde(default)]
+    pub link_sections: Count,
+    /// Calls to `transmute`
+    #[serde(default)]
+    pub transmutes: Count,
+    /// Dereferences, the ones of raw pointers inside unsafe scopes are
+    /// counted as unsafe. Without type information raw pointers are only
+    /// recognized from casts, `ptr` functions, `as_ptr()` and bindings with a
+    /// raw pointer type, other raw pointer dereferences are counted as safe
+    #[serde(default)]
+    pub derefs_in_unsafe: Count,
 }
 
 impl CounterBlock {
+    /// Whether any of the counters up to and including `macros` counts
+    /// unsafe usage
     pub fn has_unsafe(&self) -> bool {
         self.functions.unsafe_ > 0
             || self.exprs.unsafe_ > 0
             || self.item_impls.unsafe_ > 0
             || self.item_traits.unsafe_ > 0
             || self.methods.unsafe_ > 0
+            || self.macros.unsafe_ > 0
+    }
+
+    /// Subtracts every counter, stopping at zero
+    pub fn saturating_sub(&self, other: &CounterBlock) -> CounterBlock {
+        CounterBlock {
+            functions: self.functions.saturating_sub(&other.functions),
+            exprs: self.exprs.saturating_sub(&other.exprs),
+            item_impls: self.item_impls.saturating_sub(&other.item_impls),
+            item_traits: self.item_traits.saturating_sub(&other.item_traits),
+            methods: self.methods.saturating_sub(&other.methods),
+            macros: self.macros.saturating_sub(&other.macros),
+            foreign_items: self
+                .foreign_items
+                .saturating_sub(&other.foreign_items),
+            statics: self.statics.saturating_sub(&other.statics),
+            unions: self.unions.saturating_sub(&other.unions),
+            repr_packed: self.repr_packed.saturating_sub(&other.repr_packed),
+            asm: self.asm.saturating_sub(&other.asm),
+            link_sections: self
+                .link_sections
+                .saturating_sub(&other.link_sections),
+            transmutes: self.transmutes.saturating_sub(&other.transmutes),
+            derefs_in_unsafe: self
+                .derefs_in_unsafe
+                .saturating_sub(&other.derefs_in_unsafe),
+        }
     }
 }
 
@@ -164,6 +369,15 @@ impl Add for CounterBlock {
             item_impls: self.item_impls + other.item_impls,
             item_traits: self.item_traits + other.item_traits,
             methods: self.methods + other.methods,
+            macros: self.macros + other.macros,
+            foreign_items: self.foreign_items + other.foreign_items,
+            statics: self.statics + other.statics,
+            unions: self.unions + other.unions,
+            repr_packed: self.repr_packed + other.repr_packed,
+            asm: self.asm + other.asm,
+            link_sections: self.link_sections + other.link_sections,
+            transmutes: self.transmutes + other.transmutes,
+            derefs_in_unsafe: self.derefs_in_unsafe + other.derefs_in_unsafe,
         }
     }
 }
@@ -174,6 +388,149 @@ impl AddAssign for CounterBlock {
     }
 }
 
+/// Kind of `unsafe` usage found at a source location
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub enum UnsafeKind {
+    /// Free-standing `unsafe fn`
+    Function,
+    /// Free-standing function made unsafe by `#[no_mangle]` or
+    /// `#[export_name]`
+    ExportedFunction,
+    /// `unsafe { }` block
+    Block,
+    /// `unsafe impl`
+    ItemImpl,
+    /// `unsafe trait`
+    ItemTrait,
+    /// `unsafe fn` inside an `impl` block or declared in a trait
+    Method,
+    /// `unsafe` keyword inside the tokens of a macro invocation or
+    /// `macro_rules!` definition
+    Macro,
+}
+
+/// Position in a source file. `line` is 1-indexed and `column` is 0-indexed,
+/// counted in UTF-8 characters.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A single `unsafe` usage and where it was found
+#[derive(
+    Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub struct UnsafeFinding {
+    /// Path to the source file, empty if the source was not read from a file
+    pub file: PathBuf,
+    /// Start of the `unsafe` item or block
+    pub start: LineColumn,
+    /// End of the `unsafe` item or block
+    pub end: LineColumn,
+    pub kind: UnsafeKind,
+    /// Path of the enclosing items, e.g. `module::Type::method`
+    pub item_path: String,
+    /// Whether the `unsafe` block or impl is preceded by a `// SAFETY:`
+    /// comment, or the public `unsafe fn` has a `# Safety` doc section.
+    /// `None` for other kinds of `unsafe` usage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documented: Option<bool>,
+}
+
+/// Unsafe usage inside the body of a single function or method
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub struct FunctionMetrics {
+    /// Path to the source file, empty if the source was not read from a file
+    pub file: PathBuf,
+    /// Start of the function
+    pub start: LineColumn,
+    /// Path of the function, e.g. `module::Type::method`
+    pub item_path: String,
+    /// Whether the function itself is unsafe, i.e. an `unsafe fn` or a
+    /// function exported with `#[no_mangle]` or `#[export_name]`
+    pub unsafe_fn: bool,
+    /// Number of unsafe expressions in the body of the function, not
+    /// including nested functions
+    pub unsafe_exprs: u64,
+}
+
+/// Kind of error that prevented a source file from being analysed
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+pub enum FileErrorKind {
+    /// The file or the directory containing it could not be read
+    Io,
+    /// The file is not valid UTF-8
+    Utf8,
+    /// The file could not be parsed as Rust source
+    Parse,
+}
+
+/// A source file that could not be analysed and why
+#[derive(
+    Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub struct FileWithError {
+    pub file: PathBuf,
+    pub kind: FileErrorKind,
+    pub message: String,
+    /// Position of the parse error, `None` for other kinds of errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<LineColumn>,
+}
+
+/// A function of a package ranked by its number of unsafe expressions
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Hotspot {
+    pub package: PackageId,
+    pub function: FunctionMetrics,
+}
+
 trait Entry {
     fn package_id(&self) -> &PackageId;
 }
@@ -3,7 +3,11 @@ use crate::format::print_config::OutputFormat;
 use cargo::core::shell::ColorChoice;
 use cargo::{CliResult, Config};
 use pico_args::Arguments;
+use semver::Version;
+use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
+use std::str::FromStr;
+use strum_macros::EnumString;
 
 /// Constant `&str` containing help text
 pub const HELP: &str =
@@ -11,17 +15,43 @@ pub const HELP: &str =
 
 USAGE:
     cargo geiger [OPTIONS]
+    cargo geiger hotspots [OPTIONS]
+    cargo geiger cache-prune
+    cargo geiger diff [--fail-on-increase] <OLD_REPORT> <NEW_REPORT>
 
 OPTIONS:
     -p, --package <SPEC>          Package to be used as the root of the tree.
+        --workspace               Scan all members of the workspace, the
+                                  default for a virtual manifest.
+        --exclude <SPEC>          Exclude a workspace member from the scan,
+                                  can be repeated.
+        --crate <NAME@VERSION>    Scan a published crate instead of the current
+                                  project, resolved from the local registry
+                                  cache with --offline. --features and
+                                  --no-default-features select its features.
         --features <FEATURES>     Space-separated list of features to activate.
         --all-features            Activate all available features.
         --no-default-features     Do not activate the `default` feature.
-        --target <TARGET>         Set the target triple.
-        --all-targets             Return dependencies for all targets. By
-                                  default only the host target is matched.
+        --target <TARGET>         Set the target triple, can be repeated to
+                                  scan the build of each target and list the
+                                  crates using unsafe code only on some of
+                                  them.
+        --all-targets             Analyse all build targets of the package,
+                                  including its tests, benchmarks and
+                                  examples, and return dependencies for all
+                                  platforms. By default only the host target
+                                  is matched.
+        --lib                     Only analyse the library of the package.
+        --bin <NAME>              Only analyse the named binary, can be
+                                  repeated.
+        --example <NAME>          Only analyse the named example, can be
+                                  repeated.
+        --tests                   Analyse all tests.
+        --benches                 Analyse all benchmarks.
         --manifest-path <PATH>    Path to Cargo.toml.
     -i, --invert                  Invert the tree direction.
+    -j, --jobs <N>                Number of parallel jobs, defaults to # of
+                                  CPUs.
         --no-indent               Display the dependencies as a list (rather
                                   than a tree).
         --prefix-depth            Display the dependencies as a list (rather
@@ -31,14 +61,15 @@ OPTIONS:
     --format <FORMAT>             Format string used for printing dependencies
                                   [default: {p}].
     --output-format               Output format for the report: Ascii, GitHubMarkdown,
-                                  Json, Utf8, Ratio [default: Utf8]
+                                  Json, Sarif, Utf8, Ratio [default: Utf8]
     --update-readme               Writes output to ./README.md. Looks for a Safety
                                   Report section, replaces if found, adds if not.
                                   Throws an error if no README.md exists.
         --readme-path <PATH>      Path of README.md file to be written to.
         --section-name <NAME>     The section name in the README.md to be written
                                   to.
-    -v, --verbose                 Use verbose output (-vv very verbose/build.rs
+    -v, --verbose                 Use verbose output, listing the location of
+                                  each unsafe usage (-vv very verbose/build.rs
                                   output).
     -q, --quiet                   No output printed to stdout other than the
                                   tree.
@@ -48,6 +79,10 @@ OPTIONS:
         --offline                 Run without accessing the network.
     -Z \"<FLAG>...\"                Unstable (nightly-only) flags to Cargo.
         --include-tests           Count unsafe usage in tests.
+        --no-cache                Scan all files again instead of reusing the
+                                  results cached in the cargo home, or in
+                                  $CARGO_GEIGER_CACHE_DIR, for files that did
+                                  not change.
         --build-dependencies      Also analyze build dependencies.
         --dev-dependencies        Also analyze dev dependencies.
         --all-dependencies        Analyze all dependencies, including build and
@@ -56,37 +91,95 @@ OPTIONS:
                                   entry point .rs source files for.
                                   forbid(unsafe_code) flags. This is
                                   significantly faster than the default
-                                  scanning mode. TODO: Add ability to combine
-                                  this with a whitelist for use in CI.
+                                  scanning mode.
+        --no-build                Don't build anything, approximate the files
+                                  used by the build from the module tree of
+                                  each build target. Much faster than the
+                                  default scanning mode, but the split into
+                                  used and unused code is approximate.
+        --expand-macros           Also scan the macro expanded source of each
+                                  crate and report unsafe code generated by
+                                  macros or build scripts separately. Requires
+                                  a nightly toolchain.
+        --policy <PATH>           Path to a policy file declaring the unsafe
+                                  code each crate is allowed to use. Defaults
+                                  to geiger.toml next to the root manifest or
+                                  [package.metadata.geiger]. Exits with an
+                                  error listing the crates violating it.
+        --undocumented-unsafe <MODE>
+                                  List the unsafe blocks and impls without a
+                                  `// SAFETY:` comment and the pub unsafe fns
+                                  without a `# Safety` doc section in the
+                                  workspace members, with the documented and
+                                  undocumented counts of each: List, Deny.
+                                  Deny exits with an error if there are any.
+        --strict                  Exit with an error if any source file could
+                                  not be analysed, or could not be parsed and
+                                  was only scanned approximately.
+        --top <N>                 List the N functions with the most unsafe
+                                  expressions in the dependency tree after
+                                  the report.
     -h, --help                    Prints help information.
     -V, --version                 Prints version information.
+
+DIFF OPTIONS:
+        --fail-on-increase        Exit with an error if the used unsafe code of
+                                  any crate increased.
+
+The hotspots subcommand only lists the functions with the most unsafe
+expressions in the dependency tree, the top 10 unless `--top` is given.
+
+The cache-prune subcommand removes the cached scan results of other geiger
+versions, and the cached scan results and the target directories of `--crate`
+scans that have not been used for 30 days.
+
+The diff subcommand compares two reports created with `--output-format Json`
+and lists the crates that were added, removed or changed.
 ";
 
+/// Number of functions listed by the hotspots subcommand without `--top`
+pub const DEFAULT_HOTSPOT_COUNT: usize = 10;
+
 #[derive(Default)]
 pub struct Args {
     pub all: bool,
+    pub build_target_args: BuildTargetArgs,
     pub color: Option<String>,
+    pub crate_spec: Option<CrateSpec>,
     pub deps_args: DepsArgs,
+    pub diff_args: Option<DiffArgs>,
+    pub exclude: Vec<String>,
+    pub expand_macros: bool,
     pub features_args: FeaturesArgs,
     pub forbid_only: bool,
     pub format: String,
     pub frozen: bool,
     pub help: bool,
+    pub hotspots: bool,
     pub include_tests: bool,
     pub invert: bool,
+    pub jobs: Option<usize>,
     pub locked: bool,
     pub manifest_path: Option<PathBuf>,
+    pub no_build: bool,
+    pub no_cache: bool,
     pub no_indent: bool,
     pub offline: bool,
     pub output_format: OutputFormat,
     pub package: Option<String>,
+    pub policy: Option<PathBuf>,
     pub prefix_depth: bool,
+    pub prune_cache: bool,
     pub quiet: bool,
     pub readme_args: ReadmeArgs,
+    pub strict: bool,
     pub target_args: TargetArgs,
+    pub top: Option<usize>,
+    pub undocumented_unsafe: Option<UndocumentedUnsafe>,
     pub unstable_flags: Vec<String>,
     pub verbose: u32,
     pub version: bool,
+    pub workspace: bool,
 }
 
 impl Args {
@@ -100,14 +193,29 @@ impl Args {
     pub fn parse_args(
         mut raw_args: Arguments,
     ) -> Result<Args, Box<dyn std::error::Error>> {
+        // Selects every build target like cargo's `--all-targets`, and also
+        // matches the dependencies of all platforms.
+        let all_targets = raw_args.contains("--all-targets");
         let mut args = Args {
             all: raw_args.contains(["-a", "--all"]),
+            build_target_args: BuildTargetArgs {
+                all_targets,
+                benches: raw_args.contains("--benches"),
+                bins: raw_args.values_from_str("--bin")?,
+                examples: raw_args.values_from_str("--example")?,
+                lib: raw_args.contains("--lib"),
+                tests: raw_args.contains("--tests"),
+            },
             color: raw_args.opt_value_from_str("--color")?,
+            crate_spec: raw_args.opt_value_from_str("--crate")?,
             deps_args: DepsArgs {
                 all_deps: raw_args.contains("--all-dependencies"),
                 build_deps: raw_args.contains("--build-dependencies"),
                 dev_deps: raw_args.contains("--dev-dependencies"),
             },
+            diff_args: None,
+            exclude: raw_args.values_from_str("--exclude")?,
+            expand_macros: raw_args.contains("--expand-macros"),
             features_args: FeaturesArgs {
                 all_features: raw_args.contains("--all-features"),
                 features: parse_features(
@@ -121,24 +229,34 @@ impl Args {
                 .unwrap_or_else(|| "{p}".to_string()),
             frozen: raw_args.contains("--frozen"),
             help: raw_args.contains(["-h", "--help"]),
+            hotspots: false,
             include_tests: raw_args.contains("--include-tests"),
             invert: raw_args.contains(["-i", "--invert"]),
+            jobs: raw_args.opt_value_from_str(["-j", "--jobs"])?,
             locked: raw_args.contains("--locked"),
             manifest_path: raw_args.opt_value_from_str("--manifest-path")?,
+            no_build: raw_args.contains("--no-build"),
+            no_cache: raw_args.contains("--no-cache"),
             no_indent: raw_args.contains("--no-indent"),
             offline: raw_args.contains("--offline"),
             package: raw_args.opt_value_from_str(["-p", "--package"])?,
+            policy: raw_args.opt_value_from_str("--policy")?,
             prefix_depth: raw_args.contains("--prefix-depth"),
+            prune_cache: false,
             quiet: raw_args.contains(["-q", "--quiet"]),
             readme_args: ReadmeArgs {
                 readme_path: raw_args.opt_value_from_str("--readme-path")?,
                 section_name: raw_args.opt_value_from_str("--section-name")?,
                 update_readme: raw_args.contains("--update-readme"),
             },
+            strict: raw_args.contains("--strict"),
             target_args: TargetArgs {
-                all_targets: raw_args.contains("--all-targets"),
-                target: raw_args.opt_value_from_str("--target")?,
+                all_targets,
+                targets: raw_args.values_from_str("--target")?,
             },
+            top: raw_args.opt_value_from_str("--top")?,
+            undocumented_unsafe: raw_args
+                .opt_value_from_str("--undocumented-unsafe")?,
             unstable_flags: raw_args
                 .opt_value_from_str("-Z")?
                 .map(|s: String| s.split(' ').map(|s| s.to_owned()).collect())
@@ -152,11 +270,18 @@ impl Args {
                 (true, _) => 2,
             },
             version: raw_args.contains(["-V", "--version"]),
+            workspace: raw_args.contains("--workspace"),
             output_format: raw_args
                 .opt_value_from_str("--output-format")?
                 .unwrap_or(OutputFormat::Utf8),
         };
 
+        let fail_on_increase = raw_args.contains("--fail-on-increase");
+        let free_args = raw_args.finish();
+        args.hotspots = is_subcommand(&free_args, "hotspots");
+        args.prune_cache = is_subcommand(&free_args, "cache-prune");
+        args.diff_args = parse_diff_args(free_args, fail_on_increase)?;
+
         if args.readme_args.update_readme
             && args.output_format != OutputFormat::GitHubMarkdown
         {
@@ -171,6 +296,16 @@ impl Args {
         Ok(args)
     }
 
+    /// The number of functions to rank by their unsafe expressions, `None`
+    /// unless requested with `--top` or the hotspots subcommand
+    pub fn hotspot_count(&self) -> Option<usize> {
+        match (self.top, self.hotspots) {
+            (Some(top), _) => Some(top),
+            (None, true) => Some(DEFAULT_HOTSPOT_COUNT),
+            (None, false) => None,
+        }
+    }
+
     /// Update `cargo::util::Config` with values from `Args` struct, and set the shell
     /// colour choice
     /// ```
@@ -206,6 +341,61 @@ impl Args {
     }
 }
 
+/// The build targets of the package to analyse, all the default targets
+/// of `cargo check` when none are selected.
+#[derive(Debug, Default)]
+pub struct BuildTargetArgs {
+    pub all_targets: bool,
+    pub benches: bool,
+    pub bins: Vec<String>,
+    pub examples: Vec<String>,
+    pub lib: bool,
+    pub tests: bool,
+}
+
+impl BuildTargetArgs {
+    /// Whether any build target was selected, rather than the default
+    /// targets of `cargo check`.
+    pub fn is_specific(&self) -> bool {
+        self.all_targets
+            || self.benches
+            || !self.bins.is_empty()
+            || !self.examples.is_empty()
+            || self.lib
+            || self.tests
+    }
+}
+
+/// A published crate to scan, given as `<NAME>@<VERSION>`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CrateSpec {
+    pub name: String,
+    pub version: Version,
+}
+
+impl FromStr for CrateSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, version) = s
+            .split_once('@')
+            .ok_or_else(|| format!("expected <NAME>@<VERSION>, got {}", s))?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("invalid crate name: {}", name));
+        }
+        let version = Version::parse(version)
+            .map_err(|e| format!("invalid version {}: {}", version, e))?;
+        Ok(CrateSpec {
+            name: name.to_string(),
+            version,
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct DepsArgs {
     pub all_deps: bool,
@@ -213,6 +403,13 @@ pub struct DepsArgs {
     pub dev_deps: bool,
 }
 
+#[derive(Debug)]
+pub struct DiffArgs {
+    pub fail_on_increase: bool,
+    pub old_report_path: PathBuf,
+    pub new_report_path: PathBuf,
+}
+
 #[derive(Debug, Default)]
 pub struct FeaturesArgs {
     pub all_features: bool,
@@ -223,7 +420,17 @@ pub struct FeaturesArgs {
 #[derive(Debug, Default)]
 pub struct TargetArgs {
     pub all_targets: bool,
-    pub target: Option<String>,
+    pub targets: Vec<String>,
+}
+
+/// What to do with `unsafe` usages lacking a `// SAFETY:` comment or a
+/// `# Safety` doc section.
+#[derive(Clone, Copy, Debug, EnumString, Eq, PartialEq)]
+pub enum UndocumentedUnsafe {
+    /// List them after the report.
+    List,
+    /// List them and exit with an error if there are any.
+    Deny,
 }
 
 #[derive(Debug, Default)]
@@ -233,6 +440,49 @@ pub struct ReadmeArgs {
     pub update_readme: bool,
 }
 
+/// Whether the free arguments left after all options have been consumed
+/// start with `subcommand`.
+fn is_subcommand(free_args: &[OsString], subcommand: &str) -> bool {
+    skip_geiger_arg(free_args).next() == Some(OsStr::new(subcommand))
+}
+
+/// When invoked as `cargo geiger`, cargo passes `geiger` as the first
+/// argument.
+fn skip_geiger_arg(
+    free_args: &[OsString],
+) -> impl Iterator<Item = &OsStr> + '_ {
+    let mut free_args = free_args.iter().map(OsString::as_os_str).peekable();
+    if free_args.peek() == Some(&OsStr::new("geiger")) {
+        free_args.next();
+    }
+    free_args
+}
+
+/// Parses the free arguments left after all options have been consumed.
+fn parse_diff_args(
+    free_args: Vec<OsString>,
+    fail_on_increase: bool,
+) -> Result<Option<DiffArgs>, Box<dyn std::error::Error>> {
+    let mut free_args = skip_geiger_arg(&free_args);
+
+    if free_args.next() != Some(OsStr::new("diff")) {
+        return Ok(None);
+    }
+
+    match (free_args.next(), free_args.next(), free_args.next()) {
+        (Some(old_report_path), Some(new_report_path), None) => {
+            Ok(Some(DiffArgs {
+                fail_on_increase,
+                old_report_path: PathBuf::from(old_report_path),
+                new_report_path: PathBuf::from(new_report_path),
+            }))
+        }
+        _ => Err("diff requires exactly two report paths: \
+             cargo geiger diff <OLD_REPORT> <NEW_REPORT>"
+            .into()),
+    }
+}
+
 fn parse_features(raw_features: Option<String>) -> Vec<String> {
     raw_features
         .as_ref()
@@ -319,6 +569,209 @@ pub mod args_tests {
         assert_eq!(args.verbose, expected_verbose)
     }
 
+    #[rstest(
+        input_argument_vector,
+        expected_hotspot_count,
+        case(vec![], None),
+        case(vec!["--top", "3"], Some(3)),
+        case(vec!["geiger", "hotspots"], Some(DEFAULT_HOTSPOT_COUNT)),
+        case(vec!["hotspots", "--top", "5"], Some(5))
+    )]
+    fn parse_args_test_hotspot_count(
+        input_argument_vector: Vec<&str>,
+        expected_hotspot_count: Option<usize>,
+    ) {
+        let args = parse_args(input_argument_vector);
+
+        assert_eq!(args.hotspot_count(), expected_hotspot_count);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_no_build,
+        expected_no_cache,
+        expected_prune_cache,
+        case(vec![], false, false, false),
+        case(vec!["--no-build"], true, false, false),
+        case(vec!["--no-cache"], false, true, false),
+        case(vec!["geiger", "cache-prune"], false, false, true)
+    )]
+    fn parse_args_test_flags(
+        input_argument_vector: Vec<&str>,
+        expected_no_build: bool,
+        expected_no_cache: bool,
+        expected_prune_cache: bool,
+    ) {
+        let args = parse_args(input_argument_vector);
+
+        assert_eq!(args.no_build, expected_no_build);
+        assert_eq!(args.no_cache, expected_no_cache);
+        assert_eq!(args.prune_cache, expected_prune_cache);
+    }
+
+    #[rstest(
+        input_crate_spec,
+        expected_crate_spec,
+        case(
+            "serde@1.0.130",
+            Ok(CrateSpec {
+                name: String::from("serde"),
+                version: Version::new(1, 0, 130),
+            })
+        ),
+        case(
+            "serde",
+            Err(String::from("expected <NAME>@<VERSION>, got serde"))
+        ),
+        case("se rde@1.0.130", Err(String::from("invalid crate name: se rde"))),
+        case(
+            "serde@1.0",
+            Err(String::from(
+                "invalid version 1.0: unexpected end of input while parsing minor version number"
+            ))
+        )
+    )]
+    fn crate_spec_from_str_test(
+        input_crate_spec: &str,
+        expected_crate_spec: Result<CrateSpec, String>,
+    ) {
+        assert_eq!(CrateSpec::from_str(input_crate_spec), expected_crate_spec);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_workspace,
+        expected_exclude,
+        expected_targets,
+        case(vec![], false, vec![], vec![]),
+        case(vec!["--workspace"], true, vec![], vec![]),
+        case(
+            vec!["--workspace", "--exclude", "a", "--exclude", "b"],
+            true,
+            vec!["a", "b"],
+            vec![]
+        ),
+        case(
+            vec!["--target", "wasm32-unknown-unknown"],
+            false,
+            vec![],
+            vec!["wasm32-unknown-unknown"]
+        ),
+        case(
+            vec![
+                "--target",
+                "wasm32-unknown-unknown",
+                "--target",
+                "aarch64-unknown-linux-gnu"
+            ],
+            false,
+            vec![],
+            vec!["wasm32-unknown-unknown", "aarch64-unknown-linux-gnu"]
+        )
+    )]
+    fn parse_args_test_workspace_and_targets(
+        input_argument_vector: Vec<&str>,
+        expected_workspace: bool,
+        expected_exclude: Vec<&str>,
+        expected_targets: Vec<&str>,
+    ) {
+        let args = parse_args(input_argument_vector);
+
+        assert_eq!(args.workspace, expected_workspace);
+        assert_eq!(args.exclude, expected_exclude);
+        assert_eq!(args.target_args.targets, expected_targets);
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_lib,
+        expected_bins,
+        expected_examples,
+        expected_all_targets,
+        case(vec![], false, vec![], vec![], false),
+        case(
+            vec!["--lib", "--bin", "a", "--bin", "b", "--example", "c"],
+            true,
+            vec!["a", "b"],
+            vec!["c"],
+            false
+        ),
+        case(vec!["--all-targets"], false, vec![], vec![], true)
+    )]
+    fn parse_args_test_build_targets(
+        input_argument_vector: Vec<&str>,
+        expected_lib: bool,
+        expected_bins: Vec<&str>,
+        expected_examples: Vec<&str>,
+        expected_all_targets: bool,
+    ) {
+        let args = parse_args(input_argument_vector);
+
+        assert_eq!(args.build_target_args.lib, expected_lib);
+        assert_eq!(args.build_target_args.bins, expected_bins);
+        assert_eq!(args.build_target_args.examples, expected_examples);
+        assert!(!args.build_target_args.tests);
+        assert!(!args.build_target_args.benches);
+        assert_eq!(args.build_target_args.all_targets, expected_all_targets);
+        assert_eq!(args.target_args.all_targets, expected_all_targets);
+        assert_eq!(
+            args.build_target_args.is_specific(),
+            expected_lib || expected_all_targets
+        );
+    }
+
+    #[rstest(
+        input_argument_vector,
+        expected_diff_report_paths,
+        case(vec![], None),
+        case(vec!["geiger"], None),
+        case(
+            vec!["geiger", "diff", "old.json", "new.json"],
+            Some((PathBuf::from("old.json"), PathBuf::from("new.json")))
+        ),
+        case(
+            vec!["diff", "old.json", "new.json"],
+            Some((PathBuf::from("old.json"), PathBuf::from("new.json")))
+        )
+    )]
+    fn parse_diff_args_test(
+        input_argument_vector: Vec<&str>,
+        expected_diff_report_paths: Option<(PathBuf, PathBuf)>,
+    ) {
+        let diff_args = parse_diff_args(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            diff_args.map(|d| (d.old_report_path, d.new_report_path)),
+            expected_diff_report_paths
+        );
+    }
+
+    #[rstest(
+        input_argument_vector,
+        case(vec!["diff"]),
+        case(vec!["geiger", "diff", "old.json"]),
+        case(vec!["diff", "old.json", "new.json", "extra.json"])
+    )]
+    fn parse_diff_args_test_missing_report_paths(
+        input_argument_vector: Vec<&str>,
+    ) {
+        assert!(parse_diff_args(
+            input_argument_vector
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+            false,
+        )
+        .is_err());
+    }
+
     #[rstest(
         input_raw_features,
         expected_features,
@@ -442,4 +895,11 @@ pub mod args_tests {
         assert_eq!(config.offline(), offline);
         assert!(config.target_dir().unwrap().is_none());
     }
+
+    fn parse_args(argument_vector: Vec<&str>) -> Args {
+        Args::parse_args(Arguments::from_vec(
+            argument_vector.into_iter().map(OsString::from).collect(),
+        ))
+        .unwrap()
+    }
 }
@@ -6,19 +6,172 @@
 // TODO: Investigate how cargo-clippy is implemented. Is it using syn?  Is is
 // using rustc? Is it implementing a compiler plugin?
 
-use crate::args::Args;
+use crate::args::{Args, CrateSpec, FeaturesArgs};
+use crate::mapping::QueryResolve;
+use crate::scan::cache::crate_target_root;
 
 // TODO: Consider making this a lib.rs (again) and expose a full API, excluding
 // only the terminal output..? That API would be dependent on cargo.
 use cargo::core::Workspace;
-use cargo::util::{important_paths, CargoResult};
+use cargo::util::{important_paths, CargoResult, Filesystem};
 use cargo::Config;
-use cargo_metadata::{CargoOpt, Metadata, MetadataCommand};
+use cargo_metadata::{CargoOpt, Metadata, MetadataCommand, PackageId};
 use cargo_platform::Cfg;
+use filetime::FileTime;
 use krates::Builder as KratesBuilder;
-use krates::Krates;
+use krates::{Krates, PkgSpec};
+use std::fs;
 use std::path::PathBuf;
 use std::str::{self, FromStr};
+use tempfile::TempDir;
+
+/// Name of the package of the manifest generated to scan a published crate.
+const CRATE_SCAN_PACKAGE_NAME: &str = "geiger-crate-scan";
+
+/// A manifest generated to scan a published crate. It is written to a
+/// temporary directory that is removed when this is dropped, so that every
+/// scan resolves the crate afresh.
+pub struct CrateManifest {
+    pub manifest_path: PathBuf,
+    /// Target directory of the scans of this crate version, so that repeated
+    /// scans reuse the builds of its dependencies without contending with
+    /// the scans of other crates for the lock of a shared one.
+    pub target_dir: Filesystem,
+    _dir: TempDir,
+}
+
+impl CrateManifest {
+    /// Creates the target directory and marks it as used, `cache-prune`
+    /// removes the ones that have not been used for a while.
+    pub fn touch_target_dir(&self) -> CargoResult<()> {
+        let target_dir = self.target_dir.as_path_unlocked();
+        fs::create_dir_all(target_dir)?;
+        filetime::set_file_mtime(target_dir, FileTime::now())?;
+        Ok(())
+    }
+}
+
+/// Writes a manifest with a single dependency on the crate given with
+/// `--crate`, so that it is resolved like any other dependency, from the
+/// local registry cache when offline.
+pub fn create_crate_manifest(
+    args: &Args,
+    config: &Config,
+) -> CargoResult<Option<CrateManifest>> {
+    let crate_spec = match &args.crate_spec {
+        Some(crate_spec) => crate_spec,
+        None => return Ok(None),
+    };
+    if args.manifest_path.is_some() || args.package.is_some() || args.workspace
+    {
+        anyhow::bail!(
+            "--crate can not be combined with --manifest-path, --package or --workspace"
+        );
+    }
+    if args.features_args.all_features {
+        anyhow::bail!(
+            "--all-features can not be used with --crate, \
+             list the features of the crate with --features instead"
+        );
+    }
+
+    let dir = tempfile::Builder::new()
+        .prefix(&format!("{}-{}-", crate_spec.name, crate_spec.version))
+        .tempdir()?;
+    fs::create_dir_all(dir.path().join("src"))?;
+    fs::write(dir.path().join("src").join("lib.rs"), "")?;
+    let manifest_path = dir.path().join("Cargo.toml");
+    fs::write(
+        &manifest_path,
+        crate_manifest(crate_spec, &args.features_args),
+    )?;
+    Ok(Some(CrateManifest {
+        manifest_path,
+        target_dir: crate_target_root(config)
+            .join(format!("{}-{}", crate_spec.name, crate_spec.version)),
+        _dir: dir,
+    }))
+}
+
+/// The manifest of an empty package depending on exactly the given version
+/// of the crate, with the given features. It declares its own workspace, so
+/// that it is never mistaken for a member of an enclosing one.
+fn crate_manifest(
+    crate_spec: &CrateSpec,
+    features_args: &FeaturesArgs,
+) -> String {
+    let mut dependency = toml::value::Table::new();
+    dependency.insert(
+        String::from("version"),
+        toml::Value::String(format!("={}", crate_spec.version)),
+    );
+    if !features_args.features.is_empty() {
+        dependency.insert(
+            String::from("features"),
+            toml::Value::Array(
+                features_args
+                    .features
+                    .iter()
+                    .cloned()
+                    .map(toml::Value::String)
+                    .collect(),
+            ),
+        );
+    }
+    if features_args.no_default_features {
+        dependency.insert(
+            String::from("default-features"),
+            toml::Value::Boolean(false),
+        );
+    }
+    let mut package = toml::value::Table::new();
+    package.insert(
+        String::from("name"),
+        toml::Value::String(String::from(CRATE_SCAN_PACKAGE_NAME)),
+    );
+    package.insert(
+        String::from("version"),
+        toml::Value::String(String::from("0.0.0")),
+    );
+    package.insert(
+        String::from("edition"),
+        toml::Value::String(String::from("2018")),
+    );
+    package.insert(String::from("publish"), toml::Value::Boolean(false));
+    let mut dependencies = toml::value::Table::new();
+    dependencies
+        .insert(crate_spec.name.clone(), toml::Value::Table(dependency));
+    let mut manifest = toml::value::Table::new();
+    manifest.insert(String::from("package"), toml::Value::Table(package));
+    manifest.insert(
+        String::from("workspace"),
+        toml::Value::Table(toml::value::Table::new()),
+    );
+    manifest.insert(
+        String::from("dependencies"),
+        toml::Value::Table(dependencies),
+    );
+    toml::to_string(&manifest).unwrap()
+}
+
+/// The package of the crate scanned with `--crate`, the only dependency of
+/// the generated manifest.
+fn get_crate_package_id(cargo_metadata: &Metadata) -> CargoResult<PackageId> {
+    cargo_metadata
+        .root_package()
+        .and_then(|root_package| {
+            cargo_metadata
+                .resolve
+                .as_ref()?
+                .nodes
+                .iter()
+                .find(|node| node.id == root_package.id)
+        })
+        .and_then(|node| node.dependencies.first().cloned())
+        .ok_or_else(|| {
+            anyhow::anyhow!("the crate given with --crate was not resolved")
+        })
+}
 
 pub fn get_cargo_metadata(
     args: &Args,
@@ -54,15 +207,15 @@ pub fn get_cargo_metadata(
 /// compilation (`cargo_platform::Cfg` instances).
 pub fn get_cfgs(
     config: &Config,
-    target: &Option<String>,
+    target: Option<&str>,
     workspace: &Workspace,
 ) -> CargoResult<Option<Vec<Cfg>>> {
     let mut process = cargo_util::ProcessBuilder::new(
         &config.load_global_rustc(Some(workspace))?.path,
     );
     process.arg("--print=cfg").env_remove("RUST_LOG");
-    if let Some(ref s) = *target {
-        process.arg("--target").arg(s);
+    if let Some(target) = target {
+        process.arg("--target").arg(target);
     }
     let output = match process.exec_with_output() {
         Ok(output) => output,
@@ -82,6 +235,66 @@ pub fn get_krates(cargo_metadata: &Metadata) -> CargoResult<Krates> {
         .build_with_metadata(cargo_metadata.clone(), |_| ())?)
 }
 
+/// The packages to use as the roots of the scanned dependency trees: all
+/// members of the workspace, except the `--exclude`d ones, with `--workspace`
+/// or for a virtual manifest, otherwise the `--package` or the root package.
+/// Only the crate itself when scanning a published crate with `--crate`.
+pub fn get_root_package_ids(
+    args: &Args,
+    cargo_metadata: &Metadata,
+    krates: &Krates,
+) -> CargoResult<Vec<PackageId>> {
+    if args.crate_spec.is_some() {
+        return Ok(vec![get_crate_package_id(cargo_metadata)?]);
+    }
+    if !args.workspace {
+        let package_id = args
+            .package
+            .as_ref()
+            .and_then(|package_query| krates.query_resolve(package_query))
+            .or_else(|| {
+                cargo_metadata
+                    .root_package()
+                    .map(|root_package| root_package.id.clone())
+            });
+        if let Some(package_id) = package_id {
+            if !args.exclude.is_empty() {
+                anyhow::bail!(
+                    "--exclude can only be used together with --workspace"
+                );
+            }
+            return Ok(vec![package_id]);
+        }
+    }
+    get_workspace_member_ids(cargo_metadata, &args.exclude)
+}
+
+/// The members of the workspace that do not match any of the `exclude`
+/// package specs.
+pub fn get_workspace_member_ids(
+    cargo_metadata: &Metadata,
+    exclude: &[String],
+) -> CargoResult<Vec<PackageId>> {
+    let exclude_package_specs = exclude
+        .iter()
+        .map(|package_spec| PkgSpec::from_str(package_spec))
+        .collect::<Result<Vec<PkgSpec>, _>>()?;
+    let workspace_member_ids = cargo_metadata
+        .workspace_members
+        .iter()
+        .filter(|package_id| {
+            !exclude_package_specs.iter().any(|package_spec| {
+                package_spec.matches(&cargo_metadata[package_id])
+            })
+        })
+        .cloned()
+        .collect::<Vec<PackageId>>();
+    if workspace_member_ids.is_empty() {
+        anyhow::bail!("all members of the workspace are excluded");
+    }
+    Ok(workspace_member_ids)
+}
+
 pub fn get_workspace(
     config: &Config,
     manifest_path: Option<PathBuf>,
@@ -113,12 +326,11 @@ mod cli_tests {
     #[rstest]
     fn get_cfgs_test() {
         let config = Config::default().unwrap();
-        let target: Option<String> = None;
         let root =
             important_paths::find_root_manifest_for_wd(config.cwd()).unwrap();
         let workspace = Workspace::new(&root, &config).unwrap();
 
-        let cfgs = get_cfgs(&config, &target, &workspace);
+        let cfgs = get_cfgs(&config, None, &workspace);
 
         assert!(cfgs.is_ok());
         let cfg_vec_option = cfgs.unwrap();
@@ -146,6 +358,124 @@ mod cli_tests {
         assert!(krates_result.is_ok());
     }
 
+    #[rstest(
+        input_exclude,
+        expected_member_names,
+        case(vec![], vec!["cargo-geiger", "cargo-geiger-serde", "geiger"]),
+        case(vec!["geiger"], vec!["cargo-geiger", "cargo-geiger-serde"]),
+        case(
+            vec!["cargo-geiger", "cargo-geiger-serde"],
+            vec!["geiger"]
+        )
+    )]
+    fn get_workspace_member_ids_test(
+        input_exclude: Vec<&str>,
+        expected_member_names: Vec<&str>,
+    ) {
+        let args = Args::default();
+        let config = Config::default().unwrap();
+        let cargo_metadata = get_cargo_metadata(&args, &config).unwrap();
+        let exclude = input_exclude
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        let mut member_names =
+            get_workspace_member_ids(&cargo_metadata, &exclude)
+                .unwrap()
+                .iter()
+                .map(|package_id| cargo_metadata[package_id].name.clone())
+                .collect::<Vec<String>>();
+        member_names.sort();
+
+        assert_eq!(member_names, expected_member_names);
+    }
+
+    #[rstest]
+    fn get_workspace_member_ids_test_all_excluded() {
+        let args = Args::default();
+        let config = Config::default().unwrap();
+        let cargo_metadata = get_cargo_metadata(&args, &config).unwrap();
+        let exclude = vec![
+            String::from("cargo-geiger"),
+            String::from("cargo-geiger-serde"),
+            String::from("geiger"),
+        ];
+
+        assert!(get_workspace_member_ids(&cargo_metadata, &exclude).is_err());
+    }
+
+    #[rstest(
+        input_features_args,
+        expected_dependency,
+        case(
+            FeaturesArgs::default(),
+            "version = \"=1.0.130\""
+        ),
+        case(
+            FeaturesArgs {
+                all_features: false,
+                features: vec![String::from("derive")],
+                no_default_features: true,
+            },
+            "default-features = false\nfeatures = [\"derive\"]\nversion = \"=1.0.130\""
+        )
+    )]
+    fn crate_manifest_test(
+        input_features_args: FeaturesArgs,
+        expected_dependency: &str,
+    ) {
+        let crate_spec = CrateSpec::from_str("serde@1.0.130").unwrap();
+
+        let manifest: toml::Value =
+            toml::from_str(&crate_manifest(&crate_spec, &input_features_args))
+                .unwrap();
+
+        assert_eq!(
+            manifest["package"]["name"].as_str(),
+            Some("geiger-crate-scan")
+        );
+        assert!(manifest["workspace"].is_table());
+        assert_eq!(
+            manifest["dependencies"]["serde"],
+            toml::from_str::<toml::Value>(expected_dependency).unwrap()
+        );
+    }
+
+    #[rstest]
+    fn create_crate_manifest_test_removed_on_drop() {
+        let args = Args {
+            crate_spec: Some(CrateSpec::from_str("serde@1.0.130").unwrap()),
+            ..Default::default()
+        };
+        let config = Config::default().unwrap();
+
+        let crate_manifest =
+            create_crate_manifest(&args, &config).unwrap().unwrap();
+        let manifest_path = crate_manifest.manifest_path.clone();
+        assert!(manifest_path.exists());
+        assert!(!manifest_path.starts_with(config.home().as_path_unlocked()));
+        assert!(crate_manifest
+            .target_dir
+            .as_path_unlocked()
+            .ends_with("crates/serde-1.0.130"));
+
+        drop(crate_manifest);
+        assert!(!manifest_path.exists());
+    }
+
+    #[rstest]
+    fn create_crate_manifest_test_conflicting_args() {
+        let args = Args {
+            crate_spec: Some(CrateSpec::from_str("serde@1.0.130").unwrap()),
+            workspace: true,
+            ..Default::default()
+        };
+        let config = Config::default().unwrap();
+
+        assert!(create_crate_manifest(&args, &config).is_err());
+    }
+
     #[rstest]
     fn get_workspace_test() {
         let config = Config::default().unwrap();
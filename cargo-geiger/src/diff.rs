@@ -0,0 +1,421 @@
+use crate::args::DiffArgs;
+
+use cargo::{CliError, CliResult};
+use cargo_geiger_serde::{Count, CounterBlock, ReportEntry, SafetyReport};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct FoundUnsafeIncreaseError {
+    pub package_count: u64,
+}
+
+impl Error for FoundUnsafeIncreaseError {}
+
+impl fmt::Display for FoundUnsafeIncreaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Found {} packages with increased unsafe usage",
+            self.package_count
+        )
+    }
+}
+
+/// Difference in the number of unsafe items of two `CounterBlock`s, for every
+/// counter including the informational ones, e.g. `statics` or `transmutes`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CounterBlockDelta {
+    pub functions: i64,
+    pub exprs: i64,
+    pub item_impls: i64,
+    pub item_traits: i64,
+    pub methods: i64,
+    pub macros: i64,
+    pub foreign_items: i64,
+    pub statics: i64,
+    pub unions: i64,
+    pub repr_packed: i64,
+    pub asm: i64,
+    pub link_sections: i64,
+    pub transmutes: i64,
+    pub derefs_in_unsafe: i64,
+}
+
+impl CounterBlockDelta {
+    pub fn new(old: &CounterBlock, new: &CounterBlock) -> Self {
+        let delta =
+            |old: &Count, new: &Count| new.unsafe_ as i64 - old.unsafe_ as i64;
+        CounterBlockDelta {
+            functions: delta(&old.functions, &new.functions),
+            exprs: delta(&old.exprs, &new.exprs),
+            item_impls: delta(&old.item_impls, &new.item_impls),
+            item_traits: delta(&old.item_traits, &new.item_traits),
+            methods: delta(&old.methods, &new.methods),
+            macros: delta(&old.macros, &new.macros),
+            foreign_items: delta(&old.foreign_items, &new.foreign_items),
+            statics: delta(&old.statics, &new.statics),
+            unions: delta(&old.unions, &new.unions),
+            repr_packed: delta(&old.repr_packed, &new.repr_packed),
+            asm: delta(&old.asm, &new.asm),
+            link_sections: delta(&old.link_sections, &new.link_sections),
+            transmutes: delta(&old.transmutes, &new.transmutes),
+            derefs_in_unsafe: delta(
+                &old.derefs_in_unsafe,
+                &new.derefs_in_unsafe,
+            ),
+        }
+    }
+
+    pub fn has_increase(&self) -> bool {
+        self.counters().iter().any(|(_, delta)| *delta > 0)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.counters().iter().all(|(_, delta)| *delta == 0)
+    }
+
+    fn counters(&self) -> [(&'static str, i64); 14] {
+        [
+            ("functions", self.functions),
+            ("exprs", self.exprs),
+            ("item_impls", self.item_impls),
+            ("item_traits", self.item_traits),
+            ("methods", self.methods),
+            ("macros", self.macros),
+            ("foreign_items", self.foreign_items),
+            ("statics", self.statics),
+            ("unions", self.unions),
+            ("repr_packed", self.repr_packed),
+            ("asm", self.asm),
+            ("link_sections", self.link_sections),
+            ("transmutes", self.transmutes),
+            ("derefs_in_unsafe", self.derefs_in_unsafe),
+        ]
+    }
+}
+
+impl fmt::Display for CounterBlockDelta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changed = self
+            .counters()
+            .iter()
+            .filter(|(_, delta)| *delta != 0)
+            .map(|(counter, delta)| format!("{} {:+}", counter, delta))
+            .collect::<Vec<String>>();
+
+        match changed.is_empty() {
+            true => write!(f, "unchanged"),
+            false => write!(f, "{}", changed.join(", ")),
+        }
+    }
+}
+
+/// Change of a single crate between two reports. A crate is added when
+/// `old_version` is `None`, and removed when `new_version` is `None`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageDiff {
+    pub name: String,
+    pub old_version: Option<Version>,
+    pub new_version: Option<Version>,
+    pub used: CounterBlockDelta,
+    pub unused: CounterBlockDelta,
+}
+
+impl fmt::Display for PackageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.old_version, &self.new_version) {
+            (None, Some(new_version)) => {
+                write!(f, "+ {} {}", self.name, new_version)?
+            }
+            (Some(old_version), None) => {
+                write!(f, "- {} {}", self.name, old_version)?
+            }
+            (Some(old_version), Some(new_version))
+                if old_version != new_version =>
+            {
+                write!(f, "~ {} {} -> {}", self.name, old_version, new_version)?
+            }
+            (Some(version), Some(_)) => {
+                write!(f, "~ {} {}", self.name, version)?
+            }
+            (None, None) => write!(f, "~ {}", self.name)?,
+        }
+        write!(f, " (used: {}; unused: {})", self.used, self.unused)
+    }
+}
+
+/// Reads the two reports in `diff_args`, prints the crates that differ and
+/// fails if requested and the unsafe usage of any crate increased.
+pub fn diff_reports(diff_args: &DiffArgs) -> CliResult {
+    let old_report = read_safety_report(&diff_args.old_report_path)?;
+    let new_report = read_safety_report(&diff_args.new_report_path)?;
+
+    let package_diffs = diff_safety_reports(&old_report, &new_report);
+    for package_diff in &package_diffs {
+        println!("{}", package_diff);
+    }
+
+    let increased_count = package_diffs
+        .iter()
+        .filter(|package_diff| package_diff.used.has_increase())
+        .count() as u64;
+
+    if diff_args.fail_on_increase && increased_count > 0 {
+        return Err(CliError::new(
+            anyhow::Error::new(FoundUnsafeIncreaseError {
+                package_count: increased_count,
+            }),
+            1,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Matches the crates of both reports by name and compares their unsafe
+/// counters. When a crate is present with a single version in both reports
+/// the version change is reported as one entry, otherwise every version is
+/// matched separately. Crates without any change are left out.
+pub fn diff_safety_reports(
+    old_report: &SafetyReport,
+    new_report: &SafetyReport,
+) -> Vec<PackageDiff> {
+    let mut name_to_entries =
+        BTreeMap::<&str, (Vec<&ReportEntry>, Vec<&ReportEntry>)>::new();
+    for entry in old_report.packages.values() {
+        name_to_entries
+            .entry(&entry.package.id.name)
+            .or_default()
+            .0
+            .push(entry);
+    }
+    for entry in new_report.packages.values() {
+        name_to_entries
+            .entry(&entry.package.id.name)
+            .or_default()
+            .1
+            .push(entry);
+    }
+
+    let mut package_diffs = Vec::new();
+    for (name, (mut old_entries, mut new_entries)) in name_to_entries {
+        old_entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+        new_entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+
+        let mut unmatched_old_entries = Vec::new();
+        for old_entry in old_entries {
+            match new_entries.iter().position(|new_entry| {
+                new_entry.package.id.version == old_entry.package.id.version
+            }) {
+                Some(index) => package_diffs.push(package_diff(
+                    name,
+                    Some(old_entry),
+                    Some(new_entries.remove(index)),
+                )),
+                None => unmatched_old_entries.push(old_entry),
+            }
+        }
+
+        if unmatched_old_entries.len() == 1 && new_entries.len() == 1 {
+            package_diffs.push(package_diff(
+                name,
+                unmatched_old_entries.pop(),
+                new_entries.pop(),
+            ));
+            continue;
+        }
+        for old_entry in unmatched_old_entries {
+            package_diffs.push(package_diff(name, Some(old_entry), None));
+        }
+        for new_entry in new_entries {
+            package_diffs.push(package_diff(name, None, Some(new_entry)));
+        }
+    }
+
+    package_diffs.retain(|package_diff| {
+        package_diff.old_version != package_diff.new_version
+            || !package_diff.used.is_zero()
+            || !package_diff.unused.is_zero()
+    });
+    package_diffs.sort_by(|a, b| {
+        (&a.name, a.old_version.as_ref().or(a.new_version.as_ref()))
+            .cmp(&(&b.name, b.old_version.as_ref().or(b.new_version.as_ref())))
+    });
+    package_diffs
+}
+
+fn package_diff(
+    name: &str,
+    old_entry: Option<&ReportEntry>,
+    new_entry: Option<&ReportEntry>,
+) -> PackageDiff {
+    let empty_counter_block = CounterBlock::default();
+    let counter_blocks = |entry: Option<&ReportEntry>| match entry {
+        Some(entry) => {
+            (entry.unsafety.used.clone(), entry.unsafety.unused.clone())
+        }
+        None => (empty_counter_block.clone(), empty_counter_block.clone()),
+    };
+    let (old_used, old_unused) = counter_blocks(old_entry);
+    let (new_used, new_unused) = counter_blocks(new_entry);
+
+    PackageDiff {
+        name: name.to_string(),
+        old_version: old_entry.map(|e| e.package.id.version.clone()),
+        new_version: new_entry.map(|e| e.package.id.version.clone()),
+        used: CounterBlockDelta::new(&old_used, &new_used),
+        unused: CounterBlockDelta::new(&old_unused, &new_unused),
+    }
+}
+
+fn read_safety_report(path: &Path) -> Result<SafetyReport, CliError> {
+    let file = File::open(path).map_err(|e| {
+        eprintln!("Failed to open report: {}", path.display());
+        anyhow::Error::from(e)
+    })?;
+
+    let report =
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            eprintln!("Failed to parse report: {}", path.display());
+            anyhow::Error::from(e)
+        })?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    use cargo_geiger_serde::{PackageId, PackageInfo, Source, UnsafeInfo};
+    use rstest::*;
+    use url::Url;
+
+    #[rstest]
+    fn diff_safety_reports_test() {
+        let old_report = create_safety_report(vec![
+            ("removed", "1.0.0", 1),
+            ("unchanged", "1.0.0", 2),
+            ("bumped", "1.0.0", 3),
+            ("grown", "1.0.0", 4),
+        ]);
+        let new_report = create_safety_report(vec![
+            ("added", "0.1.0", 5),
+            ("unchanged", "1.0.0", 2),
+            ("bumped", "1.1.0", 1),
+            ("grown", "1.0.0", 6),
+        ]);
+
+        let package_diffs = diff_safety_reports(&old_report, &new_report);
+
+        assert_eq!(
+            package_diffs
+                .iter()
+                .map(|package_diff| package_diff.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "+ added 0.1.0 (used: exprs +5; unused: unchanged)",
+                "~ bumped 1.0.0 -> 1.1.0 (used: exprs -2; unused: unchanged)",
+                "~ grown 1.0.0 (used: exprs +2; unused: unchanged)",
+                "- removed 1.0.0 (used: exprs -1; unused: unchanged)",
+            ]
+        );
+        assert_eq!(
+            package_diffs
+                .iter()
+                .filter(|package_diff| package_diff.used.has_increase())
+                .count(),
+            2
+        );
+    }
+
+    #[rstest]
+    fn diff_safety_reports_test_multiple_versions() {
+        let old_report = create_safety_report(vec![
+            ("dual", "0.1.0", 1),
+            ("dual", "0.2.0", 1),
+        ]);
+        let new_report = create_safety_report(vec![
+            ("dual", "0.2.0", 1),
+            ("dual", "0.3.0", 1),
+            ("dual", "0.4.0", 1),
+        ]);
+
+        let package_diffs = diff_safety_reports(&old_report, &new_report);
+
+        assert_eq!(
+            package_diffs
+                .iter()
+                .map(|package_diff| package_diff.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "- dual 0.1.0 (used: exprs -1; unused: unchanged)",
+                "+ dual 0.3.0 (used: exprs +1; unused: unchanged)",
+                "+ dual 0.4.0 (used: exprs +1; unused: unchanged)",
+            ]
+        );
+    }
+
+    #[rstest]
+    fn diff_safety_reports_test_informational_counters() {
+        let old_report = create_safety_report(vec![("grown", "1.0.0", 1)]);
+        let mut new_report = create_safety_report(vec![("grown", "1.0.0", 1)]);
+        for entry in new_report.packages.values_mut() {
+            entry.unsafety.used.statics = Count {
+                safe: 0,
+                unsafe_: 1,
+            };
+            entry.unsafety.used.transmutes = Count {
+                safe: 0,
+                unsafe_: 2,
+            };
+        }
+
+        let package_diffs = diff_safety_reports(&old_report, &new_report);
+
+        assert_eq!(
+            package_diffs
+                .iter()
+                .map(|package_diff| package_diff.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "~ grown 1.0.0 (used: statics +1, transmutes +2; unused: unchanged)"
+            ]
+        );
+        assert!(package_diffs[0].used.has_increase());
+    }
+
+    fn create_safety_report(packages: Vec<(&str, &str, u64)>) -> SafetyReport {
+        let mut report = SafetyReport::default();
+        for (name, version, unsafe_exprs) in packages {
+            let package_id = PackageId {
+                name: String::from(name),
+                version: Version::parse(version).unwrap(),
+                source: Source::Path(
+                    Url::parse(&format!("file:///{}", name)).unwrap(),
+                ),
+            };
+            let entry = ReportEntry {
+                package: PackageInfo::new(package_id.clone()),
+                unsafety: UnsafeInfo {
+                    used: CounterBlock {
+                        exprs: Count {
+                            safe: 0,
+                            unsafe_: unsafe_exprs,
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            };
+            report.packages.insert(package_id, entry);
+        }
+        report
+    }
+}
@@ -1,11 +1,13 @@
 pub mod emoji_symbols;
 pub mod pattern;
 pub mod print_config;
+pub mod sarif;
 pub mod table;
 
 mod display;
 mod parse;
 
+use cargo_geiger_serde::UnsafeKind;
 use cargo_metadata::DependencyKind;
 use std::fmt;
 use std::str::{self, FromStr};
@@ -49,6 +51,7 @@ impl FromStr for Charset {
 #[derive(Debug, Clone, EnumIter, PartialEq)]
 pub enum CrateDetectionStatus {
     NoneDetectedForbidsUnsafe,
+    NoneDetectedDeniesUnsafe,
     NoneDetectedAllowsUnsafe,
     UnsafeDetected,
 }
@@ -65,6 +68,7 @@ pub enum SymbolKind {
     Lock = 0,
     QuestionMark = 1,
     Rads = 2,
+    LockWithKey = 3,
 }
 
 #[derive(Debug)]
@@ -91,6 +95,18 @@ pub fn get_kind_group_name(dep_kind: DependencyKind) -> Option<&'static str> {
     }
 }
 
+pub fn get_unsafe_kind_name(unsafe_kind: UnsafeKind) -> &'static str {
+    match unsafe_kind {
+        UnsafeKind::Function => "unsafe fn",
+        UnsafeKind::ExportedFunction => "#[no_mangle]/#[export_name] fn",
+        UnsafeKind::Block => "unsafe block",
+        UnsafeKind::ItemImpl => "unsafe impl",
+        UnsafeKind::ItemTrait => "unsafe trait",
+        UnsafeKind::Method => "unsafe method",
+        UnsafeKind::Macro => "unsafe in macro",
+    }
+}
+
 #[cfg(test)]
 mod format_tests {
     use super::*;
@@ -4,8 +4,8 @@ use crate::format::{CrateDetectionStatus, SymbolKind};
 use colored::ColoredString;
 
 pub struct EmojiSymbols {
-    emojis: [&'static str; 3],
-    fallbacks: [ColoredString; 3],
+    emojis: [&'static str; 4],
+    fallbacks: [ColoredString; 4],
     output_format: OutputFormat,
 }
 
@@ -21,7 +21,7 @@ impl EmojiSymbols {
 
     pub fn new(output_format: OutputFormat) -> EmojiSymbols {
         Self {
-            emojis: ["🔒", "❓", "☢️"],
+            emojis: ["🔒", "❓", "☢️", "🔐"],
             fallbacks: [
                 colorize(
                     &CrateDetectionStatus::NoneDetectedForbidsUnsafe,
@@ -38,6 +38,11 @@ impl EmojiSymbols {
                     output_format,
                     String::from("!"),
                 ),
+                colorize(
+                    &CrateDetectionStatus::NoneDetectedDeniesUnsafe,
+                    output_format,
+                    String::from(":|"),
+                ),
             ],
             output_format,
         }
@@ -22,6 +22,7 @@ pub enum OutputFormat {
     Json,
     GitHubMarkdown,
     Ratio,
+    Sarif,
     Utf8,
 }
 
@@ -36,7 +37,6 @@ pub struct PrintConfig {
     /// Don't truncate dependencies that have already been displayed.
     pub all: bool,
 
-    pub allow_partial_results: bool,
     pub direction: EdgeDirection,
 
     // Is anyone using this? This is a carry-over from cargo-tree.
@@ -51,9 +51,6 @@ pub struct PrintConfig {
 
 impl PrintConfig {
     pub fn new(args: &Args) -> Result<Self, CliError> {
-        // TODO: Add command line flag for this and make it default to false?
-        let allow_partial_results = true;
-
         let direction = match args.invert {
             true => EdgeDirection::Incoming,
             false => EdgeDirection::Outgoing,
@@ -87,7 +84,6 @@ impl PrintConfig {
 
         Ok(PrintConfig {
             all: args.all,
-            allow_partial_results,
             direction,
             format,
             include_tests,
@@ -102,7 +98,6 @@ impl Default for PrintConfig {
     fn default() -> Self {
         PrintConfig {
             all: false,
-            allow_partial_results: false,
             direction: Direction::Outgoing,
             format: Pattern::try_build("p").unwrap(),
             include_tests: IncludeTests::Yes,
@@ -122,6 +117,7 @@ pub fn colorize(
         OutputFormat::GitHubMarkdown => ColoredString::from(string.as_str()),
         _ => match crate_detection_status {
             CrateDetectionStatus::NoneDetectedForbidsUnsafe => string.green(),
+            CrateDetectionStatus::NoneDetectedDeniesUnsafe => string.green(),
             CrateDetectionStatus::NoneDetectedAllowsUnsafe => string.normal(),
             CrateDetectionStatus::UnsafeDetected => string.red().bold(),
         },
@@ -276,6 +272,7 @@ mod print_config_tests {
         case("Ascii", Ok(OutputFormat::Ascii)),
         case("Json", Ok(OutputFormat::Json)),
         case("GitHubMarkdown", Ok(OutputFormat::GitHubMarkdown)),
+        case("Sarif", Ok(OutputFormat::Sarif)),
         case("Utf8", Ok(OutputFormat::Utf8)),
         case("unknown_variant", Err(strum::ParseError::VariantNotFound))
     )]
@@ -296,6 +293,11 @@ mod print_config_tests {
             OutputFormat::Ascii,
             String::from("string_value").green()
         ),
+        case(
+            CrateDetectionStatus::NoneDetectedDeniesUnsafe,
+            OutputFormat::Utf8,
+            String::from("string_value").green()
+        ),
         case(
             CrateDetectionStatus::NoneDetectedAllowsUnsafe,
             OutputFormat::Utf8,
@@ -0,0 +1,296 @@
+use crate::format::get_unsafe_kind_name;
+
+use cargo_geiger_serde::{PackageId, SafetyReport, UnsafeFinding, UnsafeKind};
+use serde::Serialize;
+use url::Url;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+const TOOL_INFORMATION_URI: &str =
+    "https://github.com/rust-secure-code/cargo-geiger";
+const TOOL_NAME: &str = "cargo-geiger";
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The order of the rules in the SARIF output, results refer to their rule
+/// by index into this list.
+const RULE_KINDS: [UnsafeKind; 7] = [
+    UnsafeKind::Function,
+    UnsafeKind::ExportedFunction,
+    UnsafeKind::Block,
+    UnsafeKind::ItemImpl,
+    UnsafeKind::ItemTrait,
+    UnsafeKind::Method,
+    UnsafeKind::Macro,
+];
+
+/// Top level object of a SARIF 2.1.0 document, see
+/// <https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html>
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Run {
+    pub tool: Tool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Tool {
+    pub driver: ToolComponent,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolComponent {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub information_uri: &'static str,
+    pub rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingDescriptor {
+    pub id: &'static str,
+    pub short_description: Message,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: &'static str,
+    pub rule_index: usize,
+    pub level: &'static str,
+    pub message: Message,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    pub region: Region,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ArtifactLocation {
+    pub uri: String,
+}
+
+/// Lines and columns are 1-indexed, `end_column` points at the character
+/// after the end of the region.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl SarifLog {
+    /// Converts the findings of every package in the report into SARIF
+    /// results, ordered by package and location.
+    pub fn from_safety_report(report: &SafetyReport) -> SarifLog {
+        let mut entries = report.packages.values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+
+        let results =
+            entries
+                .into_iter()
+                .flat_map(|entry| {
+                    entry.unsafety.findings.iter().map(move |finding| {
+                        to_result(&entry.package.id, finding)
+                    })
+                })
+                .collect();
+
+        SarifLog {
+            schema: SARIF_SCHEMA,
+            version: SARIF_VERSION,
+            runs: vec![Run {
+                tool: Tool {
+                    driver: ToolComponent {
+                        name: TOOL_NAME,
+                        version: TOOL_VERSION,
+                        information_uri: TOOL_INFORMATION_URI,
+                        rules: RULE_KINDS
+                            .iter()
+                            .map(|kind| ReportingDescriptor {
+                                id: rule_id(*kind),
+                                short_description: Message {
+                                    text: format!(
+                                        "Usage of {}",
+                                        get_unsafe_kind_name(*kind)
+                                    ),
+                                },
+                            })
+                            .collect(),
+                    },
+                },
+                results,
+            }],
+        }
+    }
+}
+
+fn rule_id(unsafe_kind: UnsafeKind) -> &'static str {
+    match unsafe_kind {
+        UnsafeKind::Function => "unsafe-fn",
+        UnsafeKind::ExportedFunction => "exported-fn",
+        UnsafeKind::Block => "unsafe-block",
+        UnsafeKind::ItemImpl => "unsafe-impl",
+        UnsafeKind::ItemTrait => "unsafe-trait",
+        UnsafeKind::Method => "unsafe-method",
+        UnsafeKind::Macro => "unsafe-in-macro",
+    }
+}
+
+fn to_result(package_id: &PackageId, finding: &UnsafeFinding) -> SarifResult {
+    let uri = Url::from_file_path(&finding.file)
+        .map(String::from)
+        .unwrap_or_else(|_| finding.file.display().to_string());
+
+    SarifResult {
+        rule_id: rule_id(finding.kind),
+        rule_index: RULE_KINDS
+            .iter()
+            .position(|kind| *kind == finding.kind)
+            .unwrap(),
+        level: "warning",
+        message: Message {
+            text: format!(
+                "{} in `{}` ({} {})",
+                get_unsafe_kind_name(finding.kind),
+                finding.item_path,
+                package_id.name,
+                package_id.version
+            ),
+        },
+        locations: vec![Location {
+            physical_location: PhysicalLocation {
+                artifact_location: ArtifactLocation { uri },
+                region: Region {
+                    start_line: finding.start.line,
+                    start_column: finding.start.column + 1,
+                    end_line: finding.end.line,
+                    end_column: finding.end.column + 1,
+                },
+            },
+        }],
+    }
+}
+
+#[cfg(test)]
+mod sarif_tests {
+    use super::*;
+
+    use cargo_geiger_serde::{
+        LineColumn, PackageInfo, ReportEntry, Source, UnsafeInfo,
+    };
+    use rstest::*;
+    use semver::Version;
+    use std::path::PathBuf;
+
+    #[rstest]
+    fn from_safety_report_test() {
+        let package_id = PackageId {
+            name: String::from("package"),
+            version: Version::new(1, 2, 3),
+            source: Source::Path(Url::parse("file:///package").unwrap()),
+        };
+        let mut report = SafetyReport::default();
+        report.packages.insert(
+            package_id.clone(),
+            ReportEntry {
+                package: PackageInfo::new(package_id),
+                unsafety: UnsafeInfo {
+                    findings: vec![UnsafeFinding {
+                        file: PathBuf::from("/package/src/lib.rs"),
+                        start: LineColumn {
+                            line: 8,
+                            column: 32,
+                        },
+                        end: LineColumn {
+                            line: 10,
+                            column: 5,
+                        },
+                        kind: UnsafeKind::Block,
+                        item_path: String::from("g"),
+                        documented: None,
+                    }],
+                    ..Default::default()
+                },
+            },
+        );
+
+        let sarif_log = SarifLog::from_safety_report(&report);
+
+        assert_eq!(sarif_log.version, "2.1.0");
+        assert_eq!(sarif_log.runs.len(), 1);
+        let run = &sarif_log.runs[0];
+        assert_eq!(run.tool.driver.rules.len(), RULE_KINDS.len());
+        assert_eq!(
+            run.results,
+            vec![SarifResult {
+                rule_id: "unsafe-block",
+                rule_index: 2,
+                level: "warning",
+                message: Message {
+                    text: String::from("unsafe block in `g` (package 1.2.3)")
+                },
+                locations: vec![Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation {
+                            uri: String::from("file:///package/src/lib.rs")
+                        },
+                        region: Region {
+                            start_line: 8,
+                            start_column: 33,
+                            end_line: 10,
+                            end_column: 6,
+                        },
+                    },
+                }],
+            }]
+        );
+    }
+
+    #[rstest(
+        input_unsafe_kind,
+        expected_rule_index,
+        case(UnsafeKind::Function, 0),
+        case(UnsafeKind::ExportedFunction, 1),
+        case(UnsafeKind::Block, 2),
+        case(UnsafeKind::ItemImpl, 3),
+        case(UnsafeKind::ItemTrait, 4),
+        case(UnsafeKind::Method, 5),
+        case(UnsafeKind::Macro, 6)
+    )]
+    fn rule_index_matches_rule_id_test(
+        input_unsafe_kind: UnsafeKind,
+        expected_rule_index: usize,
+    ) {
+        let sarif_log = SarifLog::from_safety_report(&Default::default());
+        let rules = &sarif_log.runs[0].tool.driver.rules;
+
+        assert_eq!(rules[expected_rule_index].id, rule_id(input_unsafe_kind));
+    }
+}
@@ -5,7 +5,7 @@ use crate::format::emoji_symbols::EmojiSymbols;
 use crate::format::print_config::{colorize, OutputFormat, PrintConfig};
 use crate::format::CrateDetectionStatus;
 use crate::mapping::CargoMetadataParameters;
-use crate::scan::{GeigerContext, ScanResult};
+use crate::scan::{GeigerContext, RsFilesUsed, ScanResult};
 use crate::tree::TextTreeLine;
 
 use handle_text_tree_line::{
@@ -17,7 +17,6 @@ use total_package_counts::TotalPackageCounts;
 use cargo_geiger_serde::{Count, CounterBlock};
 use colored::ColoredString;
 use std::collections::HashSet;
-use std::path::PathBuf;
 
 // TODO: use a table library, or factor the tableness out in a smarter way. This
 // is probably easier now when the tree formatting is separated from the tree
@@ -31,10 +30,12 @@ pub const UNSAFE_COUNTERS_HEADER: [&str; 6] = [
     "Dependency",
 ];
 
+/// Creates one table of the lines of each tree, e.g. of each workspace member,
+/// followed by a footer with the totals of all the packages in the trees.
 pub fn create_table_from_text_tree_lines(
     cargo_metadata_parameters: &CargoMetadataParameters,
     table_parameters: &TableParameters,
-    text_tree_lines: Vec<TextTreeLine>,
+    text_trees: Vec<Vec<TextTreeLine>>,
 ) -> ScanResult {
     let mut table_lines = Vec::<String>::new();
     let mut total_package_counts = TotalPackageCounts::new();
@@ -48,28 +49,33 @@ pub fn create_table_from_text_tree_lines(
         warning_count: &mut warning_count,
     };
 
-    for text_tree_line in text_tree_lines {
-        match text_tree_line {
-            TextTreeLine::ExtraDepsGroup {
-                kind: dep_kind,
-                tree_vines,
-            } => handle_text_tree_line_extra_deps_group(
-                dep_kind,
-                &mut table_lines,
-                tree_vines,
-            ),
-            TextTreeLine::Package {
-                id: package_id,
-                tree_vines,
-            } => handle_text_tree_line_package(
-                cargo_metadata_parameters,
-                &emoji_symbols,
-                &mut handle_package_parameters,
-                package_id,
-                &mut table_lines,
-                table_parameters,
-                tree_vines,
-            ),
+    for (tree_index, text_tree_lines) in text_trees.into_iter().enumerate() {
+        if tree_index > 0 {
+            table_lines.push(String::new());
+        }
+        for text_tree_line in text_tree_lines {
+            match text_tree_line {
+                TextTreeLine::ExtraDepsGroup {
+                    kind: dep_kind,
+                    tree_vines,
+                } => handle_text_tree_line_extra_deps_group(
+                    dep_kind,
+                    &mut table_lines,
+                    tree_vines,
+                ),
+                TextTreeLine::Package {
+                    id: package_id,
+                    tree_vines,
+                } => handle_text_tree_line_package(
+                    cargo_metadata_parameters,
+                    &emoji_symbols,
+                    &mut handle_package_parameters,
+                    package_id,
+                    &mut table_lines,
+                    table_parameters,
+                    tree_vines,
+                ),
+            }
         }
     }
 
@@ -92,13 +98,16 @@ pub fn create_table_from_text_tree_lines(
     ScanResult {
         scan_output_lines: table_lines,
         warning_count,
+        policy_violations: vec![],
+        undocumented_unsafe: vec![],
+        files_with_errors: vec![],
     }
 }
 
 pub struct TableParameters<'a> {
     pub geiger_context: &'a GeigerContext,
     pub print_config: &'a PrintConfig,
-    pub rs_files_used: &'a HashSet<PathBuf>,
+    pub rs_files_used: &'a RsFilesUsed,
 }
 
 fn table_footer_unsafe_counts(
@@ -249,7 +258,7 @@ mod table_tests {
     use geiger::RsFileMetrics;
     use rstest::*;
     use std::collections::HashMap;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use strum::IntoEnumIterator;
 
     #[rstest(
@@ -341,21 +350,25 @@ mod table_tests {
 
     #[rstest(
         input_none_detected_forbids_unsafe,
+        input_none_detected_denies_unsafe,
         input_none_detected_allows_unsafe,
         input_unsafe_detected,
         expected_crate_detection_status,
-        case(0, 0, 1, CrateDetectionStatus::UnsafeDetected),
-        case(1, 0, 0, CrateDetectionStatus::NoneDetectedForbidsUnsafe),
-        case(4, 1, 0, CrateDetectionStatus::NoneDetectedAllowsUnsafe)
+        case(0, 0, 0, 1, CrateDetectionStatus::UnsafeDetected),
+        case(1, 0, 0, 0, CrateDetectionStatus::NoneDetectedForbidsUnsafe),
+        case(1, 2, 0, 0, CrateDetectionStatus::NoneDetectedDeniesUnsafe),
+        case(4, 0, 1, 0, CrateDetectionStatus::NoneDetectedAllowsUnsafe)
     )]
     fn total_package_counts_get_total_detection_status_tests(
         input_none_detected_forbids_unsafe: i32,
+        input_none_detected_denies_unsafe: i32,
         input_none_detected_allows_unsafe: i32,
         input_unsafe_detected: i32,
         expected_crate_detection_status: CrateDetectionStatus,
     ) {
         let total_detection_status = TotalPackageCounts {
             none_detected_forbids_unsafe: input_none_detected_forbids_unsafe,
+            none_detected_denies_unsafe: input_none_detected_denies_unsafe,
             none_detected_allows_unsafe: input_none_detected_allows_unsafe,
             unsafe_detected: input_unsafe_detected,
             total_counter_block: CounterBlock::default(),
@@ -376,8 +389,16 @@ mod table_tests {
             metrics: RsFileMetrics {
                 counters: create_counter_block(),
                 forbids_unsafe,
+                findings: vec![],
+                cfg_disabled: Default::default(),
+                approximate: false,
+                parse_error: None,
+                unsafe_code_lint: None,
+                unsafe_op_in_unsafe_fn_lint: None,
+                functions: vec![],
             },
             is_crate_entry_point,
+            module_path: None,
         }
     }
 
@@ -403,6 +424,7 @@ mod table_tests {
                 safe: 9,
                 unsafe_: 10,
             },
+            ..Default::default()
         }
     }
 }
@@ -8,6 +8,7 @@ use super::total_package_counts::TotalPackageCounts;
 use super::TableParameters;
 use super::{table_row, table_row_empty};
 
+use cargo_geiger_serde::LintLevel;
 use cargo_metadata::{DependencyKind, PackageId};
 use colored::ColoredString;
 use std::collections::HashSet;
@@ -60,8 +61,10 @@ pub fn handle_text_tree_line_package(
             return;
         }
     };
-    let unsafe_info =
-        unsafe_stats(package_metrics, table_parameters.rs_files_used);
+    let unsafe_info = unsafe_stats(
+        package_metrics,
+        &table_parameters.rs_files_used.for_package(&package_id),
+    );
     if package_is_new {
         handle_package_parameters
             .total_package_counts
@@ -71,11 +74,10 @@ pub fn handle_text_tree_line_package(
             .total_unused_counter_block += unsafe_info.unused.clone();
     }
     let unsafe_found = unsafe_info.used.has_unsafe();
-    let crate_forbids_unsafe = unsafe_info.forbids_unsafe;
     let total_inc = package_is_new as i32;
     let crate_detection_status =
         get_crate_detection_status_and_update_package_counts(
-            crate_forbids_unsafe,
+            unsafe_info.unsafe_code_lint,
             handle_package_parameters,
             total_inc,
             unsafe_found,
@@ -85,6 +87,9 @@ pub fn handle_text_tree_line_package(
         CrateDetectionStatus::NoneDetectedForbidsUnsafe => {
             emoji_symbols.emoji(SymbolKind::Lock)
         }
+        CrateDetectionStatus::NoneDetectedDeniesUnsafe => {
+            emoji_symbols.emoji(SymbolKind::LockWithKey)
+        }
         CrateDetectionStatus::NoneDetectedAllowsUnsafe => {
             emoji_symbols.emoji(SymbolKind::QuestionMark)
         }
@@ -167,19 +172,25 @@ fn construct_package_text_tree_line(
 }
 
 fn get_crate_detection_status_and_update_package_counts(
-    crate_forbids_unsafe: bool,
+    unsafe_code_lint: LintLevel,
     handle_package_parameters: &mut HandlePackageParameters,
     total_inc: i32,
     unsafe_found: bool,
 ) -> CrateDetectionStatus {
-    match (crate_forbids_unsafe, unsafe_found) {
-        (true, false) => {
+    match (unsafe_code_lint, unsafe_found) {
+        (LintLevel::Forbid, false) => {
             handle_package_parameters
                 .total_package_counts
                 .none_detected_forbids_unsafe += total_inc;
             CrateDetectionStatus::NoneDetectedForbidsUnsafe
         }
-        (false, false) => {
+        (LintLevel::Deny, false) => {
+            handle_package_parameters
+                .total_package_counts
+                .none_detected_denies_unsafe += total_inc;
+            CrateDetectionStatus::NoneDetectedDeniesUnsafe
+        }
+        (LintLevel::Allow | LintLevel::Warn, false) => {
             handle_package_parameters
                 .total_package_counts
                 .none_detected_allows_unsafe += total_inc;
@@ -297,7 +308,7 @@ mod handle_text_tree_line_tests {
     }
 
     #[rstest(
-        input_crate_forbids_unsafe,
+        input_unsafe_code_lint,
         input_total_inc,
         input_unsafe_found,
         expected_crate_detection_status,
@@ -305,7 +316,7 @@ mod handle_text_tree_line_tests {
         expected_none_detected_allows_unsafe,
         expected_unsafe_detected,
         case(
-            true,
+            LintLevel::Forbid,
             1,
             false,
             CrateDetectionStatus::NoneDetectedForbidsUnsafe,
@@ -314,7 +325,7 @@ mod handle_text_tree_line_tests {
             0
         ),
         case(
-            true,
+            LintLevel::Forbid,
             0,
             false,
             CrateDetectionStatus::NoneDetectedForbidsUnsafe,
@@ -323,7 +334,7 @@ mod handle_text_tree_line_tests {
             0
         ),
         case(
-            false,
+            LintLevel::Allow,
             1,
             false,
             CrateDetectionStatus::NoneDetectedAllowsUnsafe,
@@ -332,7 +343,7 @@ mod handle_text_tree_line_tests {
             0
         ),
         case(
-            false,
+            LintLevel::Warn,
             0,
             false,
             CrateDetectionStatus::NoneDetectedAllowsUnsafe,
@@ -340,11 +351,27 @@ mod handle_text_tree_line_tests {
             0,
             0
         ),
-        case(false, 1, true, CrateDetectionStatus::UnsafeDetected, 0, 0, 1),
-        case(false, 0, true, CrateDetectionStatus::UnsafeDetected, 0, 0, 0)
+        case(
+            LintLevel::Allow,
+            1,
+            true,
+            CrateDetectionStatus::UnsafeDetected,
+            0,
+            0,
+            1
+        ),
+        case(
+            LintLevel::Deny,
+            0,
+            true,
+            CrateDetectionStatus::UnsafeDetected,
+            0,
+            0,
+            0
+        )
     )]
     fn get_crate_detection_status_and_update_package_counts_test(
-        input_crate_forbids_unsafe: bool,
+        input_unsafe_code_lint: LintLevel,
         input_total_inc: i32,
         input_unsafe_found: bool,
         expected_crate_detection_status: CrateDetectionStatus,
@@ -355,6 +382,7 @@ mod handle_text_tree_line_tests {
         let mut handle_package_parameters = HandlePackageParameters {
             total_package_counts: &mut TotalPackageCounts {
                 none_detected_forbids_unsafe: 0,
+                none_detected_denies_unsafe: 0,
                 none_detected_allows_unsafe: 0,
                 unsafe_detected: 0,
                 total_counter_block: Default::default(),
@@ -366,7 +394,7 @@ mod handle_text_tree_line_tests {
 
         let crate_detection_status =
             get_crate_detection_status_and_update_package_counts(
-                input_crate_forbids_unsafe,
+                input_unsafe_code_lint,
                 &mut handle_package_parameters,
                 input_total_inc,
                 input_unsafe_found,
@@ -395,4 +423,32 @@ mod handle_text_tree_line_tests {
             expected_unsafe_detected
         );
     }
+
+    #[rstest]
+    fn get_crate_detection_status_and_update_package_counts_test_deny() {
+        let mut handle_package_parameters = HandlePackageParameters {
+            total_package_counts: &mut TotalPackageCounts::new(),
+            visited_package_ids: &mut Default::default(),
+            warning_count: &mut 0,
+        };
+
+        let crate_detection_status =
+            get_crate_detection_status_and_update_package_counts(
+                LintLevel::Deny,
+                &mut handle_package_parameters,
+                1,
+                false,
+            );
+
+        assert_eq!(
+            crate_detection_status,
+            CrateDetectionStatus::NoneDetectedDeniesUnsafe
+        );
+        assert_eq!(
+            handle_package_parameters
+                .total_package_counts
+                .none_detected_denies_unsafe,
+            1
+        );
+    }
 }
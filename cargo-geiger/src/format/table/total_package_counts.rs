@@ -4,6 +4,7 @@ use cargo_geiger_serde::CounterBlock;
 
 pub struct TotalPackageCounts {
     pub none_detected_forbids_unsafe: i32,
+    pub none_detected_denies_unsafe: i32,
     pub none_detected_allows_unsafe: i32,
     pub unsafe_detected: i32,
     pub total_counter_block: CounterBlock,
@@ -14,6 +15,7 @@ impl TotalPackageCounts {
     pub fn new() -> TotalPackageCounts {
         TotalPackageCounts {
             none_detected_forbids_unsafe: 0,
+            none_detected_denies_unsafe: 0,
             none_detected_allows_unsafe: 0,
             unsafe_detected: 0,
             total_counter_block: CounterBlock::default(),
@@ -24,13 +26,17 @@ impl TotalPackageCounts {
     pub fn get_total_detection_status(&self) -> CrateDetectionStatus {
         match (
             self.none_detected_forbids_unsafe > 0,
+            self.none_detected_denies_unsafe > 0,
             self.none_detected_allows_unsafe > 0,
             self.unsafe_detected > 0,
         ) {
-            (_, _, true) => CrateDetectionStatus::UnsafeDetected,
-            (true, false, false) => {
+            (_, _, _, true) => CrateDetectionStatus::UnsafeDetected,
+            (true, false, false, false) => {
                 CrateDetectionStatus::NoneDetectedForbidsUnsafe
             }
+            (_, true, false, false) => {
+                CrateDetectionStatus::NoneDetectedDeniesUnsafe
+            }
             _ => CrateDetectionStatus::NoneDetectedAllowsUnsafe,
         }
     }
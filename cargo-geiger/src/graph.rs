@@ -26,36 +26,48 @@ pub struct Graph {
 
 // Almost unmodified compared to the original in cargo-tree, should be fairly
 // simple to move this and the dependency graph structure out to a library.
-/// Function to build a graph of packages dependencies
+/// Function to build a graph of packages dependencies, combining the
+/// dependencies of all the root packages
 pub fn build_graph<'a>(
     args: &Args,
     cargo_metadata_parameters: &'a CargoMetadataParameters,
     config: &Config,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     workspace: &Workspace,
 ) -> CargoResult<Graph> {
     let config_host = config.load_global_rustc(Some(workspace))?.host;
-    let (extra_deps, target) = build_graph_prerequisites(
+    let (extra_deps, targets) = build_graph_prerequisites(
         &config_host,
         &args.deps_args,
         &args.target_args,
     );
-    let cfgs = get_cfgs(config, &args.target_args.target, workspace)?;
+    let targets = match targets {
+        Some(targets) => Some(
+            targets
+                .into_iter()
+                .map(|target| {
+                    Ok((target, get_cfgs(config, Some(target), workspace)?))
+                })
+                .collect::<CargoResult<Vec<TargetCfgs>>>()?,
+        ),
+        None => None,
+    };
 
     let mut graph = Graph {
         graph: petgraph::Graph::new(),
         nodes: HashMap::new(),
     };
-    graph.nodes.insert(
-        root_package_id.clone(),
-        graph.graph.add_node(root_package_id.clone()),
-    );
+    for root_package_id in root_package_ids {
+        graph.nodes.insert(
+            root_package_id.clone(),
+            graph.graph.add_node(root_package_id.clone()),
+        );
+    }
 
-    let mut pending_packages = vec![root_package_id];
+    let mut pending_packages = root_package_ids.to_vec();
 
     let graph_configuration = GraphConfiguration {
-        target,
-        cfgs: cfgs.as_deref(),
+        targets,
         extra_deps,
     };
 
@@ -72,9 +84,13 @@ pub fn build_graph<'a>(
     Ok(graph)
 }
 
+/// A target triple and its cfgs, `None` when they could not be determined
+type TargetCfgs<'a> = (&'a str, Option<Vec<Cfg>>);
+
 struct GraphConfiguration<'a> {
-    target: Option<&'a str>,
-    cfgs: Option<&'a [Cfg]>,
+    /// The targets whose dependencies are added, with their cfgs. `None`
+    /// adds the dependencies of all targets
+    targets: Option<Vec<TargetCfgs<'a>>>,
     extra_deps: ExtraDeps,
 }
 
@@ -147,7 +163,7 @@ fn build_graph_prerequisites<'a>(
     config_host: &'a InternedString,
     deps_args: &'a DepsArgs,
     target_args: &'a TargetArgs,
-) -> (ExtraDeps, Option<&'a str>) {
+) -> (ExtraDeps, Option<Vec<&'a str>>) {
     let extra_deps = if deps_args.all_deps {
         ExtraDeps::All
     } else if deps_args.build_deps {
@@ -158,13 +174,15 @@ fn build_graph_prerequisites<'a>(
         ExtraDeps::NoMore
     };
 
-    let target = if target_args.all_targets {
+    let targets = if target_args.all_targets {
         None
+    } else if target_args.targets.is_empty() {
+        Some(vec![config_host.as_str()])
     } else {
-        Some(target_args.target.as_deref().unwrap_or(config_host))
+        Some(target_args.targets.iter().map(String::as_str).collect())
     };
 
-    (extra_deps, target)
+    (extra_deps, targets)
 }
 
 fn filter_dependencies<'a>(
@@ -188,12 +206,13 @@ fn filter_dependencies<'a>(
             d.target
                 .as_ref()
                 .and_then(|p| {
-                    graph_configuration.target.map(
-                        |t| match graph_configuration.cfgs {
+                    // A dependency of any of the scanned targets.
+                    graph_configuration.targets.as_ref().map(|targets| {
+                        targets.iter().any(|(t, cfgs)| match cfgs {
                             None => false,
                             Some(cfgs) => p.matches(t, cfgs),
-                        },
-                    )
+                        })
+                    })
                 })
                 .unwrap_or(true)
         })
@@ -259,41 +278,51 @@ mod graph_tests {
 
     #[rstest(
         input_target_args,
-        expected_target,
+        expected_targets,
         case(
             TargetArgs {
                 all_targets: true,
-                target: None
+                targets: vec![]
             },
             None
         ),
         case(
             TargetArgs {
                 all_targets: false,
-                target: None
+                targets: vec![]
+            },
+            Some(vec!["default_config_host"])),
+        case(
+            TargetArgs {
+                all_targets: false,
+                targets: vec![String::from("provided_config_host")],
             },
-            Some("default_config_host")),
+            Some(vec!["provided_config_host"])
+        ),
         case(
             TargetArgs {
                 all_targets: false,
-                target: Some(String::from("provided_config_host")),
+                targets: vec![
+                    String::from("first_config_host"),
+                    String::from("second_config_host")
+                ],
             },
-            Some("provided_config_host")
+            Some(vec!["first_config_host", "second_config_host"])
         )
     )]
     fn build_graph_prerequisites_all_targets_test(
         input_target_args: TargetArgs,
-        expected_target: Option<&str>,
+        expected_targets: Option<Vec<&str>>,
     ) {
         let config_host = InternedString::new("default_config_host");
         let deps_args = DepsArgs::default();
 
-        let (_, target) = build_graph_prerequisites(
+        let (_, targets) = build_graph_prerequisites(
             &config_host,
             &deps_args,
             &input_target_args,
         );
 
-        assert_eq!(target, expected_target);
+        assert_eq!(targets, expected_targets);
     }
 }
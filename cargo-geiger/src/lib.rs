@@ -11,6 +11,8 @@
 pub mod args;
 /// Bootstrapping functions for structs required by the CLI
 pub mod cli;
+/// Comparison of two safety reports
+pub mod diff;
 /// Construction of the dependency graph
 pub mod graph;
 /// Mapping functionality from `cargo::core` to `cargo_metadata`
@@ -12,20 +12,28 @@ extern crate petgraph;
 extern crate strum;
 extern crate strum_macros;
 
-use cargo_geiger::args::{Args, HELP};
-use cargo_geiger::cli::{get_cargo_metadata, get_krates, get_workspace};
+use cargo_geiger::args::{Args, FeaturesArgs, UndocumentedUnsafe, HELP};
+use cargo_geiger::cli::{
+    create_crate_manifest, get_cargo_metadata, get_krates,
+    get_root_package_ids, get_workspace,
+};
+use cargo_geiger::diff::diff_reports;
 use cargo_geiger::graph::build_graph;
-use cargo_geiger::mapping::{CargoMetadataParameters, QueryResolve};
+use cargo_geiger::mapping::CargoMetadataParameters;
 use cargo_geiger::readme::create_or_replace_section_in_readme;
-use cargo_geiger::scan::{scan, FoundWarningsError, ScanResult};
+use cargo_geiger::scan::cache::prune_cache;
+use cargo_geiger::scan::policy::PolicyViolationsError;
+use cargo_geiger::scan::{
+    scan, FilesWithErrorsError, FoundWarningsError, ScanResult,
+    UndocumentedUnsafeError,
+};
 
 use cargo::core::shell::Shell;
-use cargo::util::important_paths;
 use cargo::{CliError, CliResult, Config};
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 
-fn real_main(args: &Args, config: &mut Config) -> CliResult {
+fn real_main(args: &mut Args, config: &mut Config) -> CliResult {
     if args.version {
         println!("cargo-geiger {}", VERSION.unwrap_or("unknown version"));
         return Ok(());
@@ -35,8 +43,23 @@ fn real_main(args: &Args, config: &mut Config) -> CliResult {
         return Ok(());
     }
 
+    if let Some(diff_args) = &args.diff_args {
+        return diff_reports(diff_args);
+    }
+
+    if args.prune_cache {
+        return prune_cache(config);
+    }
+
     args.update_config(config)?;
 
+    let crate_manifest = create_crate_manifest(args, config)?;
+    if let Some(crate_manifest) = &crate_manifest {
+        args.manifest_path = Some(crate_manifest.manifest_path.clone());
+        // The features are those of the crate, set in the generated manifest.
+        args.features_args = FeaturesArgs::default();
+    }
+
     let cargo_metadata = get_cargo_metadata(args, config)?;
     let krates = get_krates(&cargo_metadata)?;
 
@@ -45,50 +68,35 @@ fn real_main(args: &Args, config: &mut Config) -> CliResult {
         krates: &krates,
     };
 
-    let workspace = get_workspace(config, args.manifest_path.clone())?;
-
-    let cargo_metadata_root_package_id;
-
-    if let Some(cargo_metadata_root_package) = cargo_metadata.root_package() {
-        cargo_metadata_root_package_id = cargo_metadata_root_package.id.clone();
-    } else {
-        eprintln!(
-            "manifest path `{}` is a virtual manifest, but this command requires running against an actual package in this workspace",
-            match args.manifest_path.clone() {
-                Some(path) => path,
-                None => important_paths::find_root_manifest_for_wd(config.cwd())?,
-            }.as_os_str().to_str().unwrap()
-        );
-
-        return CliResult::Err(CliError::code(1));
+    let mut workspace = get_workspace(config, args.manifest_path.clone())?;
+    if let Some(crate_manifest) = &crate_manifest {
+        crate_manifest.touch_target_dir()?;
+        workspace.set_target_dir(crate_manifest.target_dir.clone());
     }
 
+    let root_package_ids =
+        get_root_package_ids(args, &cargo_metadata, &krates)?;
+
     let graph = build_graph(
         args,
         &cargo_metadata_parameters,
         config,
-        cargo_metadata_root_package_id.clone(),
+        &root_package_ids,
         &workspace,
     )?;
 
-    let query_resolve_root_package_id = args.package.as_ref().map_or(
-        cargo_metadata_root_package_id.clone(),
-        |package_query| {
-            krates
-                .query_resolve(package_query)
-                .map_or(cargo_metadata_root_package_id, |package_id| package_id)
-        },
-    );
-
     let ScanResult {
         scan_output_lines,
         warning_count,
+        policy_violations,
+        undocumented_unsafe,
+        files_with_errors,
     } = scan(
         args,
         &cargo_metadata_parameters,
         config,
         &graph,
-        query_resolve_root_package_id,
+        &root_package_ids,
         &workspace,
     )?;
 
@@ -103,6 +111,38 @@ fn real_main(args: &Args, config: &mut Config) -> CliResult {
         }
     }
 
+    if !policy_violations.is_empty() {
+        for policy_violation in &policy_violations {
+            eprintln!("POLICY VIOLATION: {}", policy_violation);
+        }
+        return Err(CliError::new(
+            anyhow::Error::new(PolicyViolationsError {
+                violation_count: policy_violations.len() as u64,
+            }),
+            1,
+        ));
+    }
+
+    if args.undocumented_unsafe == Some(UndocumentedUnsafe::Deny)
+        && !undocumented_unsafe.is_empty()
+    {
+        return Err(CliError::new(
+            anyhow::Error::new(UndocumentedUnsafeError {
+                site_count: undocumented_unsafe.len() as u64,
+            }),
+            1,
+        ));
+    }
+
+    if args.strict && !files_with_errors.is_empty() {
+        return Err(CliError::new(
+            anyhow::Error::new(FilesWithErrorsError {
+                file_count: files_with_errors.len() as u64,
+            }),
+            1,
+        ));
+    }
+
     if warning_count > 0 {
         return Err(CliError::new(
             anyhow::Error::new(FoundWarningsError { warning_count }),
@@ -121,8 +161,8 @@ fn main() {
             cargo::exit_with_error(e.into(), &mut shell)
         }
     };
-    let args = Args::parse_args(pico_args::Arguments::from_env()).unwrap();
-    if let Err(e) = real_main(&args, &mut config) {
+    let mut args = Args::parse_args(pico_args::Arguments::from_env()).unwrap();
+    if let Err(e) = real_main(&mut args, &mut config) {
         let mut shell = Shell::new();
         cargo::exit_with_error(e, &mut shell)
     }
@@ -1,31 +1,46 @@
+/// On-disk cache of the metrics of scanned files
+pub mod cache;
+
 mod default;
 mod find;
 mod forbid;
+mod lints;
 mod rs_file;
 
+/// Allowlist and unsafe budgets for gating CI builds
+pub mod policy;
+
 use crate::args::Args;
-use crate::format::print_config::PrintConfig;
+use crate::cli::get_cfgs;
+use crate::format::get_unsafe_kind_name;
+use crate::format::print_config::{OutputFormat, PrintConfig};
 use crate::graph::Graph;
 use crate::mapping::{
     CargoMetadataParameters, ToCargoGeigerDependencyKind,
     ToCargoGeigerPackageId,
 };
 
-pub use rs_file::RsFileMetricsWrapper;
+pub use rs_file::{BuildUnit, RsFileMetricsWrapper, RsFilesUsed};
 
-use default::scan_unsafe;
+use default::{scan_unsafe, scan_unsafe_for_targets};
 use forbid::scan_forbid_unsafe;
+use lints::package_lint_levels;
+use policy::{Policy, PolicyViolation};
 
 use cargo::core::Workspace;
+use cargo::util::CargoResult;
 use cargo::{CliError, Config};
 use cargo_geiger_serde::{
-    CounterBlock, DependencyKind, PackageInfo, UnsafeInfo,
+    CounterBlock, DependencyKind, FileErrorKind, FileWithError, Hotspot,
+    PackageInfo, SafetyCommentCount, UnitUnsafeInfo, UnsafeFinding, UnsafeInfo,
 };
 use cargo_metadata::PackageId;
+use cargo_platform::Cfg;
+use geiger::{find_unsafe_in_string, IncludeTests};
 use krates::NodeId;
 use petgraph::prelude::NodeIndex;
 use petgraph::visit::EdgeRef;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::path::PathBuf;
@@ -43,9 +58,44 @@ impl fmt::Display for FoundWarningsError {
     }
 }
 
+#[derive(Debug)]
+pub struct UndocumentedUnsafeError {
+    pub site_count: u64,
+}
+
+impl Error for UndocumentedUnsafeError {}
+
+impl fmt::Display for UndocumentedUnsafeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Found {} undocumented unsafe usages", self.site_count)
+    }
+}
+
+#[derive(Debug)]
+pub struct FilesWithErrorsError {
+    pub file_count: u64,
+}
+
+impl Error for FilesWithErrorsError {}
+
+impl fmt::Display for FilesWithErrorsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to analyse {} files", self.file_count)
+    }
+}
+
+#[derive(Default)]
 pub struct ScanResult {
     pub scan_output_lines: Vec<String>,
     pub warning_count: u64,
+    pub policy_violations: Vec<PolicyViolation>,
+
+    /// The unsafe usages lacking safety documentation in code used by the
+    /// build, only collected with `--undocumented-unsafe`.
+    pub undocumented_unsafe: Vec<UnsafeFinding>,
+
+    /// The files that could not be analysed.
+    pub files_with_errors: Vec<FileWithError>,
 }
 
 /// Provides a more terse and searchable name for the wrapped generic
@@ -54,6 +104,8 @@ pub struct ScanResult {
 pub struct GeigerContext {
     pub package_id_to_metrics: HashMap<PackageId, PackageMetrics>,
     pub ignored_paths: HashSet<PathBuf>,
+    /// The files that could not be analysed, sorted by path.
+    pub files_with_errors: Vec<FileWithError>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -76,6 +128,15 @@ pub struct ScanParameters<'a> {
     pub args: &'a Args,
     pub config: &'a Config,
     pub print_config: &'a PrintConfig,
+    pub policy: Option<&'a Policy>,
+
+    /// The target triple of the scanned build, `None` for the default
+    /// target of cargo.
+    pub target: Option<&'a str>,
+
+    /// The active cfg set of the scanned target, `None` when it could not be
+    /// determined and every `cfg` is assumed to be enabled.
+    pub cfgs: Option<&'a [Cfg]>,
 }
 
 pub fn scan(
@@ -83,29 +144,112 @@ pub fn scan(
     cargo_metadata_parameters: &CargoMetadataParameters,
     config: &Config,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     workspace: &Workspace,
 ) -> Result<ScanResult, CliError> {
     let print_config = PrintConfig::new(args)?;
+    let policy = Policy::load(
+        args.policy.as_deref(),
+        cargo_metadata_parameters.metadata,
+    )?;
+    let targets = &args.target_args.targets;
+    let target = targets.first().map(String::as_str);
+    let cfgs = get_cfgs(config, target, workspace)?;
 
     let scan_parameters = ScanParameters {
         args,
         config,
         print_config: &print_config,
+        policy: policy.as_ref(),
+        target,
+        cfgs: cfgs.as_deref(),
     };
 
+    if args.forbid_only && print_config.output_format == OutputFormat::Sarif {
+        return Err(CliError::new(
+            anyhow::anyhow!(
+                "--forbid-only does not record unsafe usages, \
+                 use it with --output-format Json instead of Sarif"
+            ),
+            1,
+        ));
+    }
+
+    if args.forbid_only && args.hotspot_count().is_some() {
+        return Err(CliError::new(
+            anyhow::anyhow!(
+                "--forbid-only does not scan function bodies, \
+                 it can not be combined with hotspots or --top"
+            ),
+            1,
+        ));
+    }
+
+    if args.no_build && args.expand_macros {
+        return Err(CliError::new(
+            anyhow::anyhow!(
+                "--no-build does not build anything, \
+                 it can not be combined with --expand-macros"
+            ),
+            1,
+        ));
+    }
+
+    if args.forbid_only && args.undocumented_unsafe.is_some() {
+        return Err(CliError::new(
+            anyhow::anyhow!(
+                "--forbid-only does not scan for unsafe usages, \
+                 it can not be combined with --undocumented-unsafe"
+            ),
+            1,
+        ));
+    }
+
+    if targets.len() > 1 && print_config.output_format == OutputFormat::Sarif {
+        return Err(CliError::new(
+            anyhow::anyhow!(
+                "Sarif output supports a single --target, \
+                 use Json output to scan several targets"
+            ),
+            1,
+        ));
+    }
+
+    // `--forbid-only` does not build anything, the targets only select the
+    // dependencies in the graph.
     if args.forbid_only {
         scan_forbid_unsafe(
             cargo_metadata_parameters,
             graph,
-            root_package_id,
+            root_package_ids,
             &scan_parameters,
         )
+    } else if targets.len() > 1 {
+        let target_cfgs = targets
+            .iter()
+            .map(|target| get_cfgs(config, Some(target), workspace))
+            .collect::<CargoResult<Vec<Option<Vec<Cfg>>>>>()?;
+        let target_scan_parameters = targets
+            .iter()
+            .zip(&target_cfgs)
+            .map(|(target, cfgs)| ScanParameters {
+                target: Some(target),
+                cfgs: cfgs.as_deref(),
+                ..scan_parameters
+            })
+            .collect::<Vec<ScanParameters>>();
+        scan_unsafe_for_targets(
+            cargo_metadata_parameters,
+            graph,
+            root_package_ids,
+            &target_scan_parameters,
+            workspace,
+        )
     } else {
         scan_unsafe(
             cargo_metadata_parameters,
             graph,
-            root_package_id,
+            root_package_ids,
             &scan_parameters,
             workspace,
         )
@@ -127,14 +271,29 @@ pub fn unsafe_stats(
         .iter()
         .filter(|(_, v)| v.is_crate_entry_point)
         .all(|(_, v)| v.metrics.forbids_unsafe);
+    let (unsafe_code_lint, unsafe_op_in_unsafe_fn_lint) =
+        package_lint_levels(package_metrics);
 
     let mut used = CounterBlock::default();
     let mut unused = CounterBlock::default();
+    let mut cfg_disabled = CounterBlock::default();
+    let mut safety_comments = SafetyCommentCount::default();
+    let mut approximate = false;
 
     for (path_buf, rs_file_metrics_wrapper) in
         &package_metrics.rs_path_to_metrics
     {
         let target = if rs_files_used.contains(path_buf) {
+            cfg_disabled +=
+                rs_file_metrics_wrapper.metrics.cfg_disabled.clone();
+            for finding in &rs_file_metrics_wrapper.metrics.findings {
+                match finding.documented {
+                    Some(true) => safety_comments.documented += 1,
+                    Some(false) => safety_comments.undocumented += 1,
+                    None => {}
+                }
+            }
+            approximate |= rs_file_metrics_wrapper.metrics.approximate;
             &mut used
         } else {
             &mut unused
@@ -145,12 +304,128 @@ pub fn unsafe_stats(
         used,
         unused,
         forbids_unsafe,
+        unsafe_code_lint,
+        unsafe_op_in_unsafe_fn_lint,
+        findings: vec![],
+        macro_expanded: None,
+        cfg_disabled: match cfg_disabled.has_unsafe() {
+            true => Some(cfg_disabled),
+            false => None,
+        },
+        safety_comments: match safety_comments == SafetyCommentCount::default()
+        {
+            true => None,
+            false => Some(safety_comments),
+        },
+        approximate,
+        targets: BTreeMap::new(),
+        units: vec![],
     }
 }
 
+/// The unsafe usage of each unit the package was built as, sorted by target
+/// kind and name. Empty when the package was built as a single unit, as its
+/// usage is then the one of the package.
+pub fn unit_unsafe_stats(
+    package_id: &PackageId,
+    package_metrics: &PackageMetrics,
+    rs_files_used: &RsFilesUsed,
+) -> Vec<UnitUnsafeInfo> {
+    let mut units = rs_files_used
+        .unit_to_rs_files
+        .iter()
+        .filter(|(build_unit, _)| build_unit.package_id == *package_id)
+        .map(|(build_unit, unit_rs_files)| {
+            let UnsafeInfo { used, unused, .. } =
+                unsafe_stats(package_metrics, unit_rs_files);
+            UnitUnsafeInfo {
+                target_kind: build_unit.target_kind.description().to_string(),
+                target_name: build_unit.target_name.clone(),
+                used,
+                unused,
+            }
+        })
+        .collect::<Vec<UnitUnsafeInfo>>();
+    if units.len() < 2 {
+        return vec![];
+    }
+    units.sort_by(|a, b| {
+        (&a.target_kind, &a.target_name).cmp(&(&b.target_kind, &b.target_name))
+    });
+    units
+}
+
+/// Collects the location of every `unsafe` usage found in the `.rs` files
+/// used by the build, sorted by file and position.
+pub fn unsafe_findings(
+    package_metrics: &PackageMetrics,
+    rs_files_used: &HashSet<PathBuf>,
+) -> Vec<UnsafeFinding> {
+    let mut findings = package_metrics
+        .rs_path_to_metrics
+        .iter()
+        .filter(|(path_buf, _)| rs_files_used.contains(*path_buf))
+        .flat_map(|(_, wrapper)| wrapper.metrics.findings.iter().cloned())
+        .collect::<Vec<UnsafeFinding>>();
+    findings.sort();
+    findings
+}
+
+/// Counts the unsafe usage that is only present after macro expansion. The
+/// expanded source of each unit is scanned and the counters of the original
+/// files used by that unit are subtracted, the remainder is summed per
+/// package. Units whose expanded source fails to parse are skipped.
+pub fn macro_expanded_stats(
+    geiger_context: &GeigerContext,
+    rs_files_used: &RsFilesUsed,
+) -> HashMap<PackageId, CounterBlock> {
+    let mut package_id_to_counters = HashMap::<PackageId, CounterBlock>::new();
+    for (build_unit, expanded_source) in &rs_files_used.unit_to_expanded_source
+    {
+        // The expanded source has all `cfg` attributes already evaluated, so
+        // test code is only present when the unit was built for testing.
+        let expanded_metrics =
+            match find_unsafe_in_string(expanded_source, IncludeTests::Yes) {
+                Ok(metrics) => metrics,
+                Err(e) => {
+                    eprintln!(
+                        "Failed to parse macro expanded source of {} ({}): {}",
+                        build_unit.target_name, build_unit.package_id, e
+                    );
+                    continue;
+                }
+            };
+
+        let mut original = CounterBlock::default();
+        if let (Some(package_metrics), Some(unit_rs_files)) = (
+            geiger_context
+                .package_id_to_metrics
+                .get(&build_unit.package_id),
+            rs_files_used.unit_to_rs_files.get(build_unit),
+        ) {
+            for (path_buf, wrapper) in &package_metrics.rs_path_to_metrics {
+                if unit_rs_files.contains(path_buf) {
+                    original += wrapper.metrics.counters.clone();
+                }
+            }
+        }
+
+        *package_id_to_counters
+            .entry(build_unit.package_id.clone())
+            .or_default() +=
+            expanded_metrics.counters.saturating_sub(&original);
+    }
+    package_id_to_counters
+}
+
+#[derive(Default)]
 struct ScanDetails {
-    rs_files_used: HashSet<PathBuf>,
+    rs_files_used: RsFilesUsed,
     geiger_context: GeigerContext,
+
+    /// Unsafe usage only present after macro expansion, empty unless scanning
+    /// with `--expand-macros`.
+    macro_expanded: HashMap<PackageId, CounterBlock>,
 }
 
 fn construct_rs_files_used_lines(
@@ -170,6 +445,446 @@ fn construct_rs_files_used_lines(
         .collect::<Vec<String>>()
 }
 
+fn construct_unsafe_findings_lines(
+    geiger_context: &GeigerContext,
+    rs_files_used: &RsFilesUsed,
+) -> Vec<String> {
+    let mut findings = geiger_context
+        .package_id_to_metrics
+        .iter()
+        .flat_map(|(package_id, package_metrics)| {
+            unsafe_findings(
+                package_metrics,
+                &rs_files_used.for_package(package_id),
+            )
+        })
+        .collect::<Vec<UnsafeFinding>>();
+
+    findings.sort();
+
+    findings
+        .iter()
+        .map(|f| {
+            format!(
+                "Unsafe usage (sorted): {}:{}:{} {} in `{}`",
+                f.file.display(),
+                f.start.line,
+                f.start.column + 1,
+                get_unsafe_kind_name(f.kind),
+                f.item_path
+            )
+        })
+        .collect::<Vec<String>>()
+}
+
+/// Collects the unsafe usages lacking safety documentation in the `.rs` files
+/// used by the build of the members of the workspace, sorted by file and
+/// position. Dependencies are left out, they are not ours to document.
+fn undocumented_unsafe_findings(
+    geiger_context: &GeigerContext,
+    rs_files_used: &RsFilesUsed,
+    workspace_members: &[PackageId],
+) -> Vec<UnsafeFinding> {
+    let mut findings = geiger_context
+        .package_id_to_metrics
+        .iter()
+        .filter(|(package_id, _)| workspace_members.contains(package_id))
+        .flat_map(|(package_id, package_metrics)| {
+            unsafe_findings(
+                package_metrics,
+                &rs_files_used.for_package(package_id),
+            )
+        })
+        .filter(|finding| finding.documented == Some(false))
+        .collect::<Vec<UnsafeFinding>>();
+    findings.sort();
+    findings
+}
+
+fn construct_files_with_errors_lines(
+    files_with_errors: &[FileWithError],
+) -> Vec<String> {
+    files_with_errors
+        .iter()
+        .map(|f| {
+            let position = match f.start {
+                Some(start) => format!(":{}:{}", start.line, start.column + 1),
+                None => String::new(),
+            };
+            let kind_name = match f.kind {
+                FileErrorKind::Io => "IO error",
+                FileErrorKind::Utf8 => "invalid UTF-8",
+                FileErrorKind::Parse => "parse error",
+            };
+            format!(
+                "Failed to analyse: {}{} {}: {}",
+                f.file.display(),
+                position,
+                kind_name,
+                f.message
+            )
+        })
+        .collect::<Vec<String>>()
+}
+
+/// The number of documented and undocumented unsafe usages in the `.rs` files
+/// used by the build of each of the members of the workspace that has any.
+fn construct_safety_comment_lines(
+    geiger_context: &GeigerContext,
+    rs_files_used: &RsFilesUsed,
+    workspace_members: &[PackageId],
+) -> Vec<String> {
+    let mut lines = workspace_members
+        .iter()
+        .filter_map(|package_id| {
+            let package_metrics =
+                geiger_context.package_id_to_metrics.get(package_id)?;
+            let safety_comments = unsafe_stats(
+                package_metrics,
+                &rs_files_used.for_package(package_id),
+            )
+            .safety_comments?;
+            Some(format!(
+                "Safety comments (sorted): {} {} documented, {} undocumented",
+                package_id.repr,
+                safety_comments.documented,
+                safety_comments.undocumented
+            ))
+        })
+        .collect::<Vec<String>>();
+    lines.sort();
+    lines
+}
+
+fn construct_undocumented_unsafe_lines(
+    undocumented_unsafe: &[UnsafeFinding],
+) -> Vec<String> {
+    undocumented_unsafe
+        .iter()
+        .map(|f| {
+            format!(
+                "Undocumented unsafe (sorted): {}:{}:{} {} in `{}`",
+                f.file.display(),
+                f.start.line,
+                f.start.column + 1,
+                get_unsafe_kind_name(f.kind),
+                f.item_path
+            )
+        })
+        .collect::<Vec<String>>()
+}
+
+/// Ranks the functions in the `.rs` files used by the build of the packages
+/// in the dependency tree by their number of unsafe expressions, and keeps
+/// the first `count`. Functions without unsafe expressions are left out.
+fn function_hotspots(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    geiger_context: &GeigerContext,
+    graph: &Graph,
+    rs_files_used: &RsFilesUsed,
+    count: usize,
+) -> Vec<Hotspot> {
+    let mut hotspots = Vec::new();
+    for package_id in graph.nodes.keys() {
+        let (package_metrics, package) = match (
+            geiger_context.package_id_to_metrics.get(package_id),
+            package_id
+                .to_cargo_geiger_package_id(cargo_metadata_parameters.metadata),
+        ) {
+            (Some(package_metrics), Some(package)) => {
+                (package_metrics, package)
+            }
+            _ => continue,
+        };
+        let package_rs_files_used = rs_files_used.for_package(package_id);
+        hotspots.extend(
+            package_metrics
+                .rs_path_to_metrics
+                .iter()
+                .filter(|(path_buf, _)| {
+                    package_rs_files_used.contains(*path_buf)
+                })
+                .flat_map(|(_, wrapper)| wrapper.metrics.functions.iter())
+                .filter(|function| function.unsafe_exprs > 0)
+                .map(|function| Hotspot {
+                    package: package.clone(),
+                    function: function.clone(),
+                }),
+        );
+    }
+    sort_hotspots(&mut hotspots);
+    hotspots.truncate(count);
+    hotspots
+}
+
+/// Sorts by the number of unsafe expressions, most first. Ties are ordered
+/// by package and location to keep the output stable.
+fn sort_hotspots(hotspots: &mut [Hotspot]) {
+    hotspots.sort_by(|a, b| {
+        b.function
+            .unsafe_exprs
+            .cmp(&a.function.unsafe_exprs)
+            .then_with(|| a.package.cmp(&b.package))
+            .then_with(|| a.function.cmp(&b.function))
+    });
+}
+
+fn construct_hotspot_lines(hotspots: &[Hotspot]) -> Vec<String> {
+    hotspots
+        .iter()
+        .map(|hotspot| {
+            let function = &hotspot.function;
+            format!(
+                "Unsafe hotspot (ranked): {} exprs in {}`{}` {}:{}:{} ({} {})",
+                function.unsafe_exprs,
+                match function.unsafe_fn {
+                    true => "unsafe fn ",
+                    false => "",
+                },
+                function.item_path,
+                function.file.display(),
+                function.start.line,
+                function.start.column + 1,
+                hotspot.package.name,
+                hotspot.package.version
+            )
+        })
+        .collect::<Vec<String>>()
+}
+
+fn construct_macro_expanded_lines(
+    macro_expanded: &HashMap<PackageId, CounterBlock>,
+) -> Vec<String> {
+    let mut lines = macro_expanded
+        .iter()
+        .filter(|(_, counters)| counters.has_unsafe())
+        .map(|(package_id, counters)| {
+            format!(
+                "Unsafe from macro expansion (sorted): {} {}",
+                package_id.repr,
+                unsafe_counts(counters).join(", ")
+            )
+        })
+        .collect::<Vec<String>>();
+    lines.sort();
+    lines
+}
+
+/// Lists the packages with unsafe usage in files used by the build that is
+/// disabled by a `#[cfg(...)]` attribute, and so not counted as used.
+fn construct_cfg_disabled_lines(
+    geiger_context: &GeigerContext,
+    rs_files_used: &RsFilesUsed,
+) -> Vec<String> {
+    let mut lines = geiger_context
+        .package_id_to_metrics
+        .iter()
+        .filter_map(|(package_id, package_metrics)| {
+            let cfg_disabled = unsafe_stats(
+                package_metrics,
+                &rs_files_used.for_package(package_id),
+            )
+            .cfg_disabled?;
+            Some(format!(
+                "Unsafe disabled by cfg (sorted): {} {}",
+                package_id.repr,
+                unsafe_counts(&cfg_disabled).join(", ")
+            ))
+        })
+        .collect::<Vec<String>>();
+    lines.sort();
+    lines
+}
+
+/// Lists the unsafe usage of each unit of the root packages that were built
+/// as several units, e.g. of each binary selected with `--bin`.
+fn construct_unit_unsafe_lines(
+    geiger_context: &GeigerContext,
+    rs_files_used: &RsFilesUsed,
+    root_package_ids: &[PackageId],
+) -> Vec<String> {
+    let mut lines = vec![];
+    for package_id in root_package_ids {
+        let package_metrics =
+            match geiger_context.package_id_to_metrics.get(package_id) {
+                Some(package_metrics) => package_metrics,
+                None => continue,
+            };
+        for unit in
+            unit_unsafe_stats(package_id, package_metrics, rs_files_used)
+        {
+            let counts = unsafe_counts(&unit.used);
+            lines.push(format!(
+                "Unsafe used by build target: {} {} `{}` {}",
+                package_id.repr,
+                unit.target_kind,
+                unit.target_name,
+                match counts.is_empty() {
+                    true => String::from("none"),
+                    false => counts.join(", "),
+                }
+            ));
+        }
+    }
+    lines
+}
+
+/// The non-zero unsafe counters of `counters`, e.g. `exprs 3`.
+fn unsafe_counts(counters: &CounterBlock) -> Vec<String> {
+    [
+        ("functions", counters.functions.unsafe_),
+        ("exprs", counters.exprs.unsafe_),
+        ("item_impls", counters.item_impls.unsafe_),
+        ("item_traits", counters.item_traits.unsafe_),
+        ("methods", counters.methods.unsafe_),
+        ("macros", counters.macros.unsafe_),
+    ]
+    .iter()
+    .filter(|(_, count)| *count > 0)
+    .map(|(counter, count)| format!("{} {}", counter, count))
+    .collect::<Vec<String>>()
+}
+
+/// The packages in the dependency tree whose code used by the build contains
+/// unsafe usage.
+fn packages_using_unsafe(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
+    scan_details: &ScanDetails,
+) -> HashSet<cargo_geiger_serde::PackageId> {
+    package_metrics(
+        cargo_metadata_parameters,
+        &scan_details.geiger_context,
+        graph,
+        root_package_ids,
+    )
+    .into_iter()
+    .filter_map(|(package_id, package_info, package_metrics_option)| {
+        let unsafe_info = unsafe_stats(
+            &package_metrics_option?,
+            &scan_details.rs_files_used.for_package(&package_id),
+        );
+        match unsafe_info.used.has_unsafe() {
+            true => Some(package_info.id),
+            false => None,
+        }
+    })
+    .collect()
+}
+
+/// Lists the packages using unsafe code in the builds of some, but not all,
+/// of the scanned targets, with the targets they use it on.
+fn construct_target_specific_unsafe_lines(
+    target_packages_using_unsafe: &[(
+        &str,
+        HashSet<cargo_geiger_serde::PackageId>,
+    )],
+) -> Vec<String> {
+    let mut package_id_to_targets =
+        BTreeMap::<&cargo_geiger_serde::PackageId, Vec<&str>>::new();
+    for (target, packages_using_unsafe) in target_packages_using_unsafe {
+        for package_id in packages_using_unsafe {
+            package_id_to_targets
+                .entry(package_id)
+                .or_default()
+                .push(target);
+        }
+    }
+    package_id_to_targets
+        .into_iter()
+        .filter(|(_, targets)| {
+            targets.len() < target_packages_using_unsafe.len()
+        })
+        .map(|(package_id, targets)| {
+            format!(
+                "Unsafe used only on some targets: {} {}: {}",
+                package_id.name,
+                package_id.version,
+                targets.join(", ")
+            )
+        })
+        .collect::<Vec<String>>()
+}
+
+/// Checks every package in the dependency tree against the policy. Without
+/// `rs_files_used`, as in `--forbid-only` mode, only the crate level
+/// `forbid(unsafe_code)` attribute can be checked.
+fn check_policy(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    geiger_context: &GeigerContext,
+    graph: &Graph,
+    policy: &Policy,
+    root_package_ids: &[PackageId],
+    rs_files_used: Option<&RsFilesUsed>,
+) -> Vec<PolicyViolation> {
+    let mut policy_violations = Vec::new();
+
+    for (package_id, package_info, package_metrics_option) in package_metrics(
+        cargo_metadata_parameters,
+        geiger_context,
+        graph,
+        root_package_ids,
+    ) {
+        let package_metrics = match package_metrics_option {
+            Some(m) => m,
+            None => continue,
+        };
+        let unsafe_info = unsafe_stats(
+            &package_metrics,
+            &rs_files_used
+                .map(|r| r.for_package(&package_id))
+                .unwrap_or_default(),
+        );
+        match rs_files_used {
+            Some(_) => policy_violations.append(
+                &mut policy.check_unsafe_info(&package_info.id, &unsafe_info),
+            ),
+            None => policy_violations.extend(policy.check_forbids_unsafe(
+                &package_info.id,
+                unsafe_info.forbids_unsafe,
+            )),
+        }
+    }
+
+    policy_violations.sort_by(|a, b| a.package_id.cmp(&b.package_id));
+    policy_violations
+}
+
+/// The files used by the build that could not be parsed and were scanned
+/// token by token, sorted by path.
+fn list_approximate_files(
+    geiger_context: &GeigerContext,
+    rs_files_used: &HashSet<PathBuf>,
+) -> Vec<PathBuf> {
+    let mut approximate_files = geiger_context
+        .package_id_to_metrics
+        .values()
+        .flat_map(|package_metrics| package_metrics.rs_path_to_metrics.iter())
+        .filter(|(path_buf, rs_file_metrics_wrapper)| {
+            rs_file_metrics_wrapper.metrics.approximate
+                && rs_files_used.contains(*path_buf)
+        })
+        .map(|(path_buf, _)| path_buf.clone())
+        .collect::<Vec<PathBuf>>();
+    approximate_files.sort();
+    approximate_files
+}
+
+fn construct_approximate_files_lines(
+    approximate_files: &[PathBuf],
+) -> Vec<String> {
+    approximate_files
+        .iter()
+        .map(|path_buf| {
+            format!(
+                "Approximate: {} could not be parsed, its unsafe usage was counted from its tokens",
+                path_buf.display()
+            )
+        })
+        .collect::<Vec<String>>()
+}
+
 fn list_files_used_but_not_scanned(
     geiger_context: &GeigerContext,
     rs_files_used: &HashSet<PathBuf>,
@@ -192,17 +907,37 @@ fn list_files_used_but_not_scanned(
         .collect()
 }
 
+/// The package ids of the roots of the scanned trees, as listed in the
+/// reports.
+fn report_roots(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    root_package_ids: &[PackageId],
+) -> Vec<cargo_geiger_serde::PackageId> {
+    root_package_ids
+        .iter()
+        .filter_map(|root_package_id| {
+            root_package_id
+                .to_cargo_geiger_package_id(cargo_metadata_parameters.metadata)
+        })
+        .collect()
+}
+
 fn package_metrics(
     cargo_metadata_parameters: &CargoMetadataParameters,
     geiger_context: &GeigerContext,
     graph: &Graph,
-    root_package_id: PackageId,
-) -> Vec<(PackageInfo, Option<PackageMetrics>)> {
+    root_package_ids: &[PackageId],
+) -> Vec<(PackageId, PackageInfo, Option<PackageMetrics>)> {
     let mut package_metrics =
-        Vec::<(PackageInfo, Option<PackageMetrics>)>::new();
-    let root_index = graph.nodes[&root_package_id];
-    let mut indices = vec![root_index];
-    let mut visited = HashSet::new();
+        Vec::<(PackageId, PackageInfo, Option<PackageMetrics>)>::new();
+    let mut indices = root_package_ids
+        .iter()
+        .rev()
+        .map(|root_package_id| graph.nodes[root_package_id])
+        .collect::<Vec<NodeIndex>>();
+    // Members of a workspace can depend on each other, each root is only
+    // listed once.
+    let mut visited = indices.iter().cloned().collect::<HashSet<NodeId>>();
 
     while let Some(index) = indices.pop() {
         let package_id = graph.graph[index].clone();
@@ -230,15 +965,17 @@ fn package_metrics(
             }
 
             match geiger_context.package_id_to_metrics.get(&package_id) {
-                Some(m) => {
-                    package_metrics.push((package_info, Some(m.clone())))
-                }
+                Some(m) => package_metrics.push((
+                    package_id,
+                    package_info,
+                    Some(m.clone()),
+                )),
                 None => {
                     eprintln!(
                         "WARNING: No metrics found for package: {}",
                         package_id
                     );
-                    package_metrics.push((package_info, None))
+                    package_metrics.push((package_id, package_info, None))
                 }
             }
         }
@@ -287,10 +1024,15 @@ mod scan_tests {
     use super::*;
 
     use crate::scan::PackageMetrics;
+    use geiger::RsFileMetrics;
     use rs_file::RsFileMetricsWrapper;
 
     use crate::lib_tests::construct_krates_and_metadata;
-    use cargo_geiger_serde::{Count, Source, UnsafeInfo};
+    use cargo::core::manifest::TargetKind;
+    use cargo_geiger_serde::{
+        Count, FunctionMetrics, LineColumn, LintLevel, Source, UnsafeInfo,
+        UnsafeKind,
+    };
     use rstest::*;
     use semver::{BuildMetadata, Prerelease, Version};
     use std::{collections::HashSet, path::PathBuf};
@@ -394,6 +1136,7 @@ mod scan_tests {
                 RsFileMetricsWrapper {
                     metrics: Default::default(),
                     is_crate_entry_point: false,
+                    module_path: None,
                 },
             )],
             vec![
@@ -412,17 +1155,20 @@ mod scan_tests {
                 RsFileMetricsWrapper {
                     metrics: Default::default(),
                     is_crate_entry_point: false,
+                    module_path: None,
                 }),
                 (
                 PathBuf::from("second/file/path.rs"),
                 RsFileMetricsWrapper {
                 metrics: Default::default(),
                 is_crate_entry_point: false,
+                module_path: None,
                 }),
                 (PathBuf::from("third/file/path.rs"),
                 RsFileMetricsWrapper {
                     metrics: Default::default(),
                     is_crate_entry_point: false,
+                    module_path: None,
                 }
             )],
             vec![
@@ -454,6 +1200,7 @@ mod scan_tests {
             .cloned()
             .collect(),
             ignored_paths: HashSet::new(),
+            files_with_errors: vec![],
         };
 
         let rs_files_used = input_rs_files_used_vec.iter().cloned().collect();
@@ -474,6 +1221,8 @@ mod scan_tests {
         let stats = unsafe_stats(&Default::default(), &Default::default());
         let expected = UnsafeInfo {
             forbids_unsafe: true,
+            unsafe_code_lint: LintLevel::Forbid,
+            unsafe_op_in_unsafe_fn_lint: LintLevel::Forbid,
             ..Default::default()
         };
         assert_eq!(stats, expected);
@@ -539,6 +1288,482 @@ mod scan_tests {
         assert_eq!(stats.unused.functions.unsafe_, 110);
     }
 
+    #[rstest(
+        approximate_file,
+        expected_approximate,
+        case("foo.rs", true),
+        case("bar.rs", false)
+    )]
+    fn unsafe_stats_test_approximate_only_for_used_files(
+        approximate_file: &str,
+        expected_approximate: bool,
+    ) {
+        let metrics = metrics_from_iter(vec![
+            (
+                approximate_file,
+                MetricsBuilder::default().approximate(true).build(),
+            ),
+            ("baz.rs", MetricsBuilder::default().build()),
+        ]);
+        let stats =
+            unsafe_stats(&metrics, &set_of_paths(&["foo.rs", "baz.rs"]));
+        assert_eq!(stats.approximate, expected_approximate);
+    }
+
+    #[rstest]
+    fn unit_unsafe_stats_split_the_usage_of_each_unit(package_id: PackageId) {
+        let (geiger_context, mut rs_files_used) = context_with_units(
+            &package_id,
+            TargetKind::Bin,
+            &[("b", &["b.rs"]), ("a", &["a.rs"])],
+            vec![
+                ("lib.rs", MetricsBuilder::default().functions(2, 1).build()),
+                ("a.rs", MetricsBuilder::default().functions(5, 3).build()),
+                ("b.rs", MetricsBuilder::default().functions(20, 10).build()),
+            ],
+        );
+        let metrics = &geiger_context.package_id_to_metrics[&package_id];
+
+        let units = unit_unsafe_stats(&package_id, metrics, &rs_files_used);
+        assert_eq!(
+            units
+                .iter()
+                .map(|unit| (
+                    unit.target_kind.as_str(),
+                    unit.target_name.as_str(),
+                    unit.used.functions.unsafe_,
+                    unit.unused.functions.unsafe_
+                ))
+                .collect::<Vec<_>>(),
+            vec![("bin", "a", 3, 11), ("bin", "b", 10, 4)]
+        );
+
+        rs_files_used
+            .unit_to_rs_files
+            .retain(|build_unit, _| build_unit.target_name == "a");
+        assert!(
+            unit_unsafe_stats(&package_id, metrics, &rs_files_used).is_empty()
+        );
+    }
+
+    #[rstest]
+    fn unsafe_findings_only_include_files_used_by_the_build() {
+        let metrics = metrics_from_iter(vec![
+            (
+                "foo.rs",
+                MetricsBuilder::default()
+                    .finding("foo.rs", 7, UnsafeKind::Block)
+                    .finding("foo.rs", 2, UnsafeKind::Function)
+                    .build(),
+            ),
+            (
+                "bar.rs",
+                MetricsBuilder::default()
+                    .finding("bar.rs", 1, UnsafeKind::ItemImpl)
+                    .build(),
+            ),
+        ]);
+        let findings = unsafe_findings(&metrics, &set_of_paths(&["foo.rs"]));
+
+        assert_eq!(
+            findings
+                .iter()
+                .map(|f| (f.start.line, f.kind))
+                .collect::<Vec<_>>(),
+            vec![(2, UnsafeKind::Function), (7, UnsafeKind::Block)]
+        );
+    }
+
+    #[rstest]
+    fn construct_unsafe_findings_lines_test(package_id: PackageId) {
+        let (geiger_context, rs_files_used) = context_with_units(
+            &package_id,
+            TargetKind::Lib(vec![]),
+            &[("package", &["a/path.rs"])],
+            vec![(
+                "a/path.rs",
+                MetricsBuilder::default()
+                    .finding("a/path.rs", 3, UnsafeKind::Method)
+                    .build(),
+            )],
+        );
+
+        let unsafe_findings_lines =
+            construct_unsafe_findings_lines(&geiger_context, &rs_files_used);
+
+        assert_eq!(
+            unsafe_findings_lines,
+            vec![String::from(
+                "Unsafe usage (sorted): a/path.rs:3:5 unsafe method in `S::f`"
+            )]
+        );
+    }
+
+    #[rstest]
+    fn undocumented_unsafe_test(package_id: PackageId) {
+        let metrics = MetricsBuilder::default()
+            .finding("a/path.rs", 3, UnsafeKind::Block)
+            .documented(Some(true))
+            .finding("a/path.rs", 7, UnsafeKind::ItemImpl)
+            .documented(Some(false))
+            .finding("a/path.rs", 9, UnsafeKind::ItemTrait)
+            .build();
+        let (geiger_context, rs_files_used) = context_with_units(
+            &package_id,
+            TargetKind::Lib(vec![]),
+            &[("package", &["a/path.rs"])],
+            vec![("a/path.rs", metrics)],
+        );
+
+        let unsafe_info = unsafe_stats(
+            &geiger_context.package_id_to_metrics[&package_id],
+            &set_of_paths(&["a/path.rs"]),
+        );
+        let undocumented_unsafe = undocumented_unsafe_findings(
+            &geiger_context,
+            &rs_files_used,
+            std::slice::from_ref(&package_id),
+        );
+
+        assert_eq!(
+            unsafe_info.safety_comments,
+            Some(SafetyCommentCount {
+                documented: 1,
+                undocumented: 1
+            })
+        );
+        assert_eq!(
+            construct_undocumented_unsafe_lines(&undocumented_unsafe),
+            vec![String::from(
+                "Undocumented unsafe (sorted): a/path.rs:7:5 unsafe impl in `S::f`"
+            )]
+        );
+        assert_eq!(
+            construct_safety_comment_lines(
+                &geiger_context,
+                &rs_files_used,
+                std::slice::from_ref(&package_id)
+            ),
+            vec![String::from(
+                "Safety comments (sorted): package_id 1 documented, 1 undocumented"
+            )]
+        );
+        // Dependencies are not ours to document.
+        assert!(undocumented_unsafe_findings(
+            &geiger_context,
+            &rs_files_used,
+            &[]
+        )
+        .is_empty());
+    }
+
+    #[rstest]
+    fn list_approximate_files_test() {
+        let wrapper = |approximate| RsFileMetricsWrapper {
+            metrics: RsFileMetrics {
+                approximate,
+                ..Default::default()
+            },
+            is_crate_entry_point: false,
+            module_path: None,
+        };
+        let geiger_context = GeigerContext {
+            package_id_to_metrics: vec![(
+                PackageId {
+                    repr: String::from("package"),
+                },
+                metrics_from_iter(vec![
+                    ("src/b.rs", wrapper(true)),
+                    ("src/a.rs", wrapper(true)),
+                    ("src/parsed.rs", wrapper(false)),
+                    ("src/unused.rs", wrapper(true)),
+                ]),
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let rs_files_used = ["src/a.rs", "src/b.rs", "src/parsed.rs"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<HashSet<PathBuf>>();
+
+        let approximate_files =
+            list_approximate_files(&geiger_context, &rs_files_used);
+
+        assert_eq!(
+            approximate_files,
+            vec![PathBuf::from("src/a.rs"), PathBuf::from("src/b.rs")]
+        );
+        assert_eq!(
+            construct_approximate_files_lines(&approximate_files)[0],
+            "Approximate: src/a.rs could not be parsed, its unsafe usage was counted from its tokens"
+        );
+    }
+
+    #[rstest]
+    fn construct_files_with_errors_lines_test() {
+        let files_with_errors = vec![
+            FileWithError {
+                file: PathBuf::from("src/io.rs"),
+                kind: FileErrorKind::Io,
+                message: String::from("permission denied"),
+                start: None,
+            },
+            FileWithError {
+                file: PathBuf::from("src/lib.rs"),
+                kind: FileErrorKind::Parse,
+                message: String::from("expected expression"),
+                start: Some(LineColumn {
+                    line: 2,
+                    column: 12,
+                }),
+            },
+        ];
+
+        assert_eq!(
+            construct_files_with_errors_lines(&files_with_errors),
+            vec![
+                String::from(
+                    "Failed to analyse: src/io.rs IO error: permission denied"
+                ),
+                String::from(
+                    "Failed to analyse: src/lib.rs:2:13 parse error: expected expression"
+                ),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn construct_hotspot_lines_test() {
+        let hotspot = |name: &str, line, unsafe_fn, unsafe_exprs| Hotspot {
+            package: cargo_geiger_serde::PackageId {
+                name: String::from(name),
+                version: Version::new(1, 0, 0),
+                source: Source::Path(
+                    Url::parse(&format!("file:///{}", name)).unwrap(),
+                ),
+            },
+            function: FunctionMetrics {
+                file: PathBuf::from("src/lib.rs"),
+                start: LineColumn { line, column: 0 },
+                item_path: format!("f{}", line),
+                unsafe_fn,
+                unsafe_exprs,
+            },
+        };
+        let mut hotspots = vec![
+            hotspot("b", 1, false, 2),
+            hotspot("a", 9, true, 5),
+            hotspot("a", 3, false, 2),
+        ];
+
+        sort_hotspots(&mut hotspots);
+
+        assert_eq!(
+            construct_hotspot_lines(&hotspots),
+            vec![
+                "Unsafe hotspot (ranked): 5 exprs in unsafe fn `f9` src/lib.rs:9:1 (a 1.0.0)",
+                "Unsafe hotspot (ranked): 2 exprs in `f3` src/lib.rs:3:1 (a 1.0.0)",
+                "Unsafe hotspot (ranked): 2 exprs in `f1` src/lib.rs:1:1 (b 1.0.0)",
+            ]
+        );
+    }
+
+    #[rstest]
+    fn construct_target_specific_unsafe_lines_test() {
+        let package_id = |name: &str| cargo_geiger_serde::PackageId {
+            name: String::from(name),
+            version: Version::new(1, 0, 0),
+            source: Source::Path(
+                Url::parse(&format!("file:///{}", name)).unwrap(),
+            ),
+        };
+        let target_packages_using_unsafe = vec![
+            (
+                "aarch64-unknown-linux-gnu",
+                vec![package_id("a"), package_id("b")]
+                    .into_iter()
+                    .collect::<HashSet<_>>(),
+            ),
+            (
+                "wasm32-unknown-unknown",
+                vec![package_id("b"), package_id("c")]
+                    .into_iter()
+                    .collect::<HashSet<_>>(),
+            ),
+        ];
+
+        assert_eq!(
+            construct_target_specific_unsafe_lines(
+                &target_packages_using_unsafe
+            ),
+            vec![
+                "Unsafe used only on some targets: a 1.0.0: aarch64-unknown-linux-gnu",
+                "Unsafe used only on some targets: c 1.0.0: wasm32-unknown-unknown",
+            ]
+        );
+    }
+
+    #[rstest]
+    fn macro_expanded_stats_test(package_id: PackageId) {
+        let (geiger_context, mut rs_files_used) = context_with_units(
+            &package_id,
+            TargetKind::Lib(vec![]),
+            &[("package", &["src/lib.rs"])],
+            vec![(
+                "src/lib.rs",
+                MetricsBuilder::default().functions(0, 1).build(),
+            )],
+        );
+        let build_unit = |target_name: &str| {
+            build_unit(&package_id, TargetKind::Lib(vec![]), target_name)
+        };
+        rs_files_used.unit_to_expanded_source = vec![
+            (
+                build_unit("package"),
+                String::from("unsafe fn a() {} unsafe fn b() {} fn c() {}"),
+            ),
+            (build_unit("unparsable"), String::from("fn {")),
+        ]
+        .into_iter()
+        .collect();
+
+        let macro_expanded =
+            macro_expanded_stats(&geiger_context, &rs_files_used);
+
+        assert_eq!(
+            macro_expanded.get(&package_id),
+            Some(&CounterBlock {
+                functions: Count {
+                    safe: 1,
+                    unsafe_: 1
+                },
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            construct_macro_expanded_lines(&macro_expanded),
+            vec![String::from(
+                "Unsafe from macro expansion (sorted): package_id functions 1"
+            )]
+        );
+    }
+
+    #[rstest]
+    fn construct_cfg_disabled_lines_test(package_id: PackageId) {
+        let (geiger_context, rs_files_used) = context_with_units(
+            &package_id,
+            TargetKind::Lib(vec![]),
+            &[("package", &["lib.rs"])],
+            vec![
+                (
+                    "lib.rs",
+                    MetricsBuilder::default()
+                        .cfg_disabled_functions(1, 2)
+                        .build(),
+                ),
+                (
+                    "unused.rs",
+                    MetricsBuilder::default()
+                        .cfg_disabled_functions(0, 3)
+                        .build(),
+                ),
+            ],
+        );
+
+        assert_eq!(
+            construct_cfg_disabled_lines(&geiger_context, &rs_files_used),
+            vec![String::from(
+                "Unsafe disabled by cfg (sorted): package_id functions 2"
+            )]
+        );
+    }
+
+    #[rstest]
+    fn construct_unit_unsafe_lines_test(package_id: PackageId) {
+        let (geiger_context, rs_files_used) = context_with_units(
+            &package_id,
+            TargetKind::Bin,
+            &[("a", &["a.rs"]), ("b", &["b.rs"])],
+            vec![
+                ("a.rs", MetricsBuilder::default().functions(1, 2).build()),
+                ("b.rs", MetricsBuilder::default().functions(1, 0).build()),
+            ],
+        );
+
+        assert_eq!(
+            construct_unit_unsafe_lines(
+                &geiger_context,
+                &rs_files_used,
+                &[package_id]
+            ),
+            vec![
+                String::from(
+                    "Unsafe used by build target: package_id bin `a` functions 2"
+                ),
+                String::from(
+                    "Unsafe used by build target: package_id bin `b` none"
+                ),
+            ]
+        );
+    }
+
+    #[fixture]
+    fn package_id() -> PackageId {
+        PackageId {
+            repr: String::from("package_id"),
+        }
+    }
+
+    fn build_unit(
+        package_id: &PackageId,
+        target_kind: TargetKind,
+        target_name: &str,
+    ) -> BuildUnit {
+        BuildUnit {
+            package_id: package_id.clone(),
+            target_kind,
+            target_name: String::from(target_name),
+        }
+    }
+
+    /// A context with the files of a single package, where every unit of
+    /// `units`, given by its target name, uses the listed files.
+    fn context_with_units(
+        package_id: &PackageId,
+        target_kind: TargetKind,
+        units: &[(&str, &[&str])],
+        files: Vec<(&str, RsFileMetricsWrapper)>,
+    ) -> (GeigerContext, RsFilesUsed) {
+        let geiger_context = GeigerContext {
+            package_id_to_metrics: vec![(
+                package_id.clone(),
+                metrics_from_iter(files),
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let rs_files_used = RsFilesUsed {
+            unit_to_rs_files: units
+                .iter()
+                .map(|(target_name, paths)| {
+                    (
+                        build_unit(
+                            package_id,
+                            target_kind.clone(),
+                            target_name,
+                        ),
+                        set_of_paths(*paths),
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        };
+        (geiger_context, rs_files_used)
+    }
+
     fn metrics_from_iter<I, P>(it: I) -> PackageMetrics
     where
         I: IntoIterator<Item = (P, RsFileMetricsWrapper)>,
@@ -576,6 +1801,40 @@ mod scan_tests {
             self
         }
 
+        fn finding(
+            mut self,
+            file: &str,
+            line: usize,
+            kind: UnsafeKind,
+        ) -> Self {
+            self.inner.metrics.findings.push(UnsafeFinding {
+                file: PathBuf::from(file),
+                start: LineColumn { line, column: 4 },
+                end: LineColumn { line, column: 8 },
+                kind,
+                item_path: String::from("S::f"),
+                documented: None,
+            });
+            self
+        }
+
+        fn documented(mut self, documented: Option<bool>) -> Self {
+            if let Some(finding) = self.inner.metrics.findings.last_mut() {
+                finding.documented = documented;
+            }
+            self
+        }
+
+        fn cfg_disabled_functions(mut self, safe: u64, unsafe_: u64) -> Self {
+            self.inner.metrics.cfg_disabled.functions = Count { safe, unsafe_ };
+            self
+        }
+
+        fn approximate(mut self, yes: bool) -> Self {
+            self.inner.metrics.approximate = yes;
+            self
+        }
+
         fn set_is_crate_entry_point(mut self, yes: bool) -> Self {
             self.inner.is_crate_entry_point = yes;
             self
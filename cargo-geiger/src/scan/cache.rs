@@ -0,0 +1,420 @@
+//! An on-disk cache of the metrics of scanned `.rs` files, so that files that
+//! did not change since a previous run, like the immutable sources of
+//! registry dependencies, are not parsed again.
+//!
+//! Entries are stored as json in the cargo home, or in the directory set by
+//! `CARGO_GEIGER_CACHE_DIR`, one directory per `geiger` version, keyed by a
+//! hash of the file content and the scan settings.
+
+use cargo::util::{short_hash, CliError, CliResult, Filesystem};
+use cargo::Config;
+use cargo_platform::Cfg;
+use filetime::FileTime;
+use geiger::find::{
+    find_unsafe_in_file_with_cfgs, find_unsafe_in_string_with_cfgs,
+};
+use geiger::{IncludeTests, RsFileMetrics, ScanFileError};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Overrides the directory of the cache, e.g. to keep tests from using the
+/// cache in the cargo home.
+pub const CACHE_DIR_ENV: &str = "CARGO_GEIGER_CACHE_DIR";
+
+/// Part of the key of every entry. Bumped whenever the serialized
+/// `RsFileMetrics` or the way they are counted change, so that entries
+/// written by another build of the same `geiger` version are not read.
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// Entries that have not been used for this long are removed by
+/// `cargo geiger cache-prune`.
+pub const MAX_UNUSED_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Distinguishes the temporary files written concurrently by the scan
+/// threads of this process.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub struct ScanCache {
+    dir: PathBuf,
+}
+
+impl ScanCache {
+    /// The cache of the current `geiger` version in the cargo home, or in
+    /// `CARGO_GEIGER_CACHE_DIR` when set.
+    pub fn new(config: &Config) -> Self {
+        ScanCache::in_dir(cache_root(config).join(geiger::VERSION))
+    }
+
+    fn in_dir(dir: PathBuf) -> Self {
+        ScanCache { dir }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Loads the metrics stored for `key`, refreshing the time the entry was
+    /// last used. Unreadable entries are treated as missing.
+    fn load(&self, key: &str) -> Option<RsFileMetrics> {
+        let entry_path = self.entry_path(key);
+        let content = fs::read(&entry_path).ok()?;
+        let rs_file_metrics = serde_json::from_slice(&content).ok()?;
+        let _ = filetime::set_file_mtime(&entry_path, FileTime::now());
+        Some(rs_file_metrics)
+    }
+
+    /// Stores the metrics for `key`. The entry is written to a temporary
+    /// file first, so that concurrent runs never read a partial entry.
+    fn store(
+        &self,
+        key: &str,
+        rs_file_metrics: &RsFileMetrics,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let content = serde_json::to_vec(rs_file_metrics)?;
+        let temp_path = self.dir.join(format!(
+            "{}.{}.{}.tmp",
+            key,
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, content)?;
+        let result = fs::rename(&temp_path, self.entry_path(key));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+}
+
+/// Scans a single file like `find_unsafe_in_file_with_cfgs`, reusing the
+/// metrics of a previous scan of the same content when a cache is given.
+pub fn find_unsafe_in_file_cached(
+    cache: Option<&ScanCache>,
+    path: &Path,
+    include_tests: IncludeTests,
+    cfgs: Option<&[Cfg]>,
+) -> Result<RsFileMetrics, ScanFileError> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            return find_unsafe_in_file_with_cfgs(path, include_tests, cfgs)
+        }
+    };
+    let content = fs::read(path)
+        .map_err(|error| ScanFileError::Io(error, path.to_path_buf()))?;
+    let key = cache_key(&content, include_tests, cfgs);
+    if let Some(mut rs_file_metrics) = cache.load(&key) {
+        // The same content may have been scanned at another path.
+        set_file(&mut rs_file_metrics, path);
+        return Ok(rs_file_metrics);
+    }
+    let src = String::from_utf8(content)
+        .map_err(|error| ScanFileError::Utf8(error, path.to_path_buf()))?;
+    let mut rs_file_metrics =
+        find_unsafe_in_string_with_cfgs(&src, include_tests, cfgs)
+            .map_err(|error| ScanFileError::Syn(error, path.to_path_buf()))?;
+    set_file(&mut rs_file_metrics, path);
+    let _ = cache.store(&key, &rs_file_metrics);
+    Ok(rs_file_metrics)
+}
+
+/// Removes the caches of other `geiger` versions, and the entries of the
+/// current version and the target directories of `--crate` scans that have
+/// not been used for `MAX_UNUSED_AGE`.
+pub fn prune_cache(config: &Config) -> CliResult {
+    let unused_since = SystemTime::now() - MAX_UNUSED_AGE;
+    let removed_count =
+        prune_cache_root(&cache_root(config), geiger::VERSION, unused_since)
+            .map_err(|error| CliError::new(error.into(), 1))?;
+    let removed_target_dir_count = prune_crate_target_dirs(
+        crate_target_root(config).as_path_unlocked(),
+        unused_since,
+    )
+    .map_err(|error| CliError::new(error.into(), 1))?;
+    config.shell().status(
+        "Pruned",
+        format!(
+            "{} cache entries and {} crate target directories",
+            removed_count, removed_target_dir_count
+        ),
+    )?;
+    Ok(())
+}
+
+/// Directory holding the target directories of the crates scanned with
+/// `--crate`, one per crate version.
+pub fn crate_target_root(config: &Config) -> Filesystem {
+    config.home().join("geiger").join("crates")
+}
+
+fn cache_root(config: &Config) -> PathBuf {
+    match env::var_os(CACHE_DIR_ENV) {
+        Some(cache_dir) => PathBuf::from(cache_dir),
+        None => config
+            .home()
+            .as_path_unlocked()
+            .join("geiger")
+            .join("scan-cache"),
+    }
+}
+
+fn cache_key(
+    content: &[u8],
+    include_tests: IncludeTests,
+    cfgs: Option<&[Cfg]>,
+) -> String {
+    let hash_content = short_hash(&content);
+    let hash_settings = short_hash(&(
+        geiger::VERSION,
+        CACHE_FORMAT_VERSION,
+        include_tests == IncludeTests::Yes,
+        cfgs,
+    ));
+    format!("{}{}", hash_content, hash_settings)
+}
+
+fn prune_cache_root(
+    cache_root: &Path,
+    version: &str,
+    unused_since: SystemTime,
+) -> io::Result<usize> {
+    let version_dirs = match fs::read_dir(cache_root) {
+        Ok(version_dirs) => version_dirs,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+    let mut removed_count = 0;
+    for version_dir in version_dirs {
+        let version_dir = version_dir?;
+        if !version_dir.file_type()?.is_dir() {
+            continue;
+        }
+        let entries = fs::read_dir(version_dir.path())?;
+        if version_dir.file_name() != version {
+            removed_count += entries.count();
+            fs::remove_dir_all(version_dir.path())?;
+            continue;
+        }
+        for entry in entries {
+            let entry = entry?;
+            if entry.metadata()?.modified()? < unused_since {
+                fs::remove_file(entry.path())?;
+                removed_count += 1;
+            }
+        }
+    }
+    Ok(removed_count)
+}
+
+/// Removes everything in `crate_target_root` that was last used before
+/// `unused_since`. Target directories are marked as used when a scan starts.
+fn prune_crate_target_dirs(
+    crate_target_root: &Path,
+    unused_since: SystemTime,
+) -> io::Result<usize> {
+    let entries = match fs::read_dir(crate_target_root) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+    let mut removed_count = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.modified()? >= unused_since {
+            continue;
+        }
+        if metadata.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+        removed_count += 1;
+    }
+    Ok(removed_count)
+}
+
+fn set_file(rs_file_metrics: &mut RsFileMetrics, path: &Path) {
+    for finding in &mut rs_file_metrics.findings {
+        finding.file = path.to_path_buf();
+    }
+    for function in &mut rs_file_metrics.functions {
+        function.file = path.to_path_buf();
+    }
+    if let Some(parse_error) = &mut rs_file_metrics.parse_error {
+        parse_error.file = path.to_path_buf();
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    use rstest::*;
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    const FILE_CONTENT: &str = "pub fn f(p: *const u8) -> u8 {
+    unsafe { *p }
+}
+";
+
+    #[rstest]
+    fn find_unsafe_in_file_cached_test_reuses_entry() {
+        let temp_dir = tempdir().unwrap();
+        let cache = ScanCache::in_dir(temp_dir.path().join("cache"));
+        let first_path = temp_dir.path().join("first.rs");
+        let second_path = temp_dir.path().join("second.rs");
+        fs::write(&first_path, FILE_CONTENT).unwrap();
+        fs::write(&second_path, FILE_CONTENT).unwrap();
+
+        let first = find_unsafe_in_file_cached(
+            Some(&cache),
+            &first_path,
+            IncludeTests::No,
+            None,
+        )
+        .unwrap();
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), 1);
+        // Marks the stored entry, to tell it apart from a fresh scan.
+        let key = cache_key(FILE_CONTENT.as_bytes(), IncludeTests::No, None);
+        let mut stored = cache.load(&key).unwrap();
+        stored.forbids_unsafe = true;
+        cache.store(&key, &stored).unwrap();
+        let second = find_unsafe_in_file_cached(
+            Some(&cache),
+            &second_path,
+            IncludeTests::No,
+            None,
+        )
+        .unwrap();
+
+        assert!(!first.forbids_unsafe);
+        assert!(second.forbids_unsafe);
+        assert_eq!(first.counters, second.counters);
+        assert_eq!(second.findings[0].file, second_path);
+        assert_eq!(second.functions[0].file, second_path);
+    }
+
+    #[rstest]
+    fn find_unsafe_in_file_cached_test_without_cache() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("lib.rs");
+        fs::write(&path, FILE_CONTENT).unwrap();
+
+        let rs_file_metrics =
+            find_unsafe_in_file_cached(None, &path, IncludeTests::No, None)
+                .unwrap();
+
+        assert_eq!(rs_file_metrics.counters.exprs.unsafe_, 1);
+    }
+
+    #[rstest]
+    fn find_unsafe_in_file_cached_test_invalid_utf8() {
+        let temp_dir = tempdir().unwrap();
+        let cache = ScanCache::in_dir(temp_dir.path().join("cache"));
+        let path = temp_dir.path().join("lib.rs");
+        fs::write(&path, [0xff, 0xfe]).unwrap();
+
+        let result = find_unsafe_in_file_cached(
+            Some(&cache),
+            &path,
+            IncludeTests::No,
+            None,
+        );
+
+        assert!(matches!(
+            result,
+            Err(ScanFileError::Utf8(_, error_path)) if error_path == path
+        ));
+        assert!(!cache.dir.exists());
+    }
+
+    #[rstest]
+    fn cache_key_test() {
+        let cfgs = vec![Cfg::from_str("unix").unwrap()];
+        let key = cache_key(b"fn f() {}", IncludeTests::No, None);
+
+        assert_eq!(key, cache_key(b"fn f() {}", IncludeTests::No, None));
+        assert_ne!(key, cache_key(b"fn g() {}", IncludeTests::No, None));
+        assert_ne!(key, cache_key(b"fn f() {}", IncludeTests::Yes, None));
+        assert_ne!(key, cache_key(b"fn f() {}", IncludeTests::No, Some(&cfgs)));
+    }
+
+    #[rstest]
+    fn prune_cache_root_test() {
+        let temp_dir = tempdir().unwrap();
+        let old_version_dir = temp_dir.path().join("0.0.1");
+        let version_dir = temp_dir.path().join("0.0.2");
+        fs::create_dir_all(&old_version_dir).unwrap();
+        fs::create_dir_all(&version_dir).unwrap();
+        fs::write(old_version_dir.join("a.json"), "{}").unwrap();
+        fs::write(version_dir.join("unused.json"), "{}").unwrap();
+        fs::write(version_dir.join("used.json"), "{}").unwrap();
+        let now = SystemTime::now();
+        filetime::set_file_mtime(
+            version_dir.join("unused.json"),
+            FileTime::from_system_time(now - MAX_UNUSED_AGE * 2),
+        )
+        .unwrap();
+
+        let removed_count =
+            prune_cache_root(temp_dir.path(), "0.0.2", now - MAX_UNUSED_AGE)
+                .unwrap();
+
+        assert_eq!(removed_count, 2);
+        assert!(!old_version_dir.exists());
+        assert!(!version_dir.join("unused.json").exists());
+        assert!(version_dir.join("used.json").exists());
+    }
+
+    #[rstest]
+    fn prune_crate_target_dirs_test() {
+        let temp_dir = tempdir().unwrap();
+        let unused_dir = temp_dir.path().join("unused-1.0.0");
+        let used_dir = temp_dir.path().join("used-1.0.0");
+        fs::create_dir_all(unused_dir.join("debug")).unwrap();
+        fs::create_dir_all(&used_dir).unwrap();
+        let now = SystemTime::now();
+        filetime::set_file_mtime(
+            &unused_dir,
+            FileTime::from_system_time(now - MAX_UNUSED_AGE * 2),
+        )
+        .unwrap();
+
+        let removed_count =
+            prune_crate_target_dirs(temp_dir.path(), now - MAX_UNUSED_AGE)
+                .unwrap();
+
+        assert_eq!(removed_count, 1);
+        assert!(!unused_dir.exists());
+        assert!(used_dir.exists());
+        assert_eq!(
+            prune_crate_target_dirs(
+                &temp_dir.path().join("missing"),
+                now - MAX_UNUSED_AGE
+            )
+            .unwrap(),
+            0
+        );
+    }
+
+    #[rstest]
+    fn prune_cache_root_test_missing_root() {
+        let temp_dir = tempdir().unwrap();
+
+        let removed_count = prune_cache_root(
+            &temp_dir.path().join("missing"),
+            "0.0.2",
+            SystemTime::now(),
+        )
+        .unwrap();
+
+        assert_eq!(removed_count, 0);
+    }
+}
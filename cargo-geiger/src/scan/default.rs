@@ -1,53 +1,197 @@
 mod table;
 
-use crate::args::FeaturesArgs;
+use crate::args::{BuildTargetArgs, FeaturesArgs};
 use crate::format::print_config::OutputFormat;
+use crate::format::sarif::SarifLog;
 use crate::graph::Graph;
 use crate::mapping::CargoMetadataParameters;
-use crate::scan::rs_file::resolve_rs_file_deps;
+use crate::scan::rs_file::{
+    resolve_rs_file_deps, resolve_rs_files_from_module_trees, RsFilesUsed,
+};
 
 use super::find::find_unsafe;
 use super::{
-    list_files_used_but_not_scanned, package_metrics, unsafe_stats,
-    ScanDetails, ScanMode, ScanParameters, ScanResult,
+    check_policy, construct_target_specific_unsafe_lines, function_hotspots,
+    list_files_used_but_not_scanned, macro_expanded_stats, package_metrics,
+    packages_using_unsafe, report_roots, undocumented_unsafe_findings,
+    unit_unsafe_stats, unsafe_findings, unsafe_stats, ScanDetails, ScanMode,
+    ScanParameters, ScanResult,
 };
 
 use table::scan_to_table;
 
-use cargo::core::compiler::CompileMode;
+use cargo::core::compiler::{CompileKind, CompileMode};
 use cargo::core::resolver::features::CliFeatures;
 use cargo::core::Workspace;
-use cargo::ops::CompileOptions;
+use cargo::ops::{CompileFilter, CompileOptions, Packages};
 use cargo::{CliError, Config};
-use cargo_geiger_serde::{ReportEntry, SafetyReport};
+use cargo_geiger_serde::{
+    CounterBlock, ReportEntry, SafetyReport, TargetUnsafeInfo,
+};
 use cargo_metadata::PackageId;
+use geiger::RsFileMetrics;
+use std::collections::hash_map::Entry;
 
 pub fn scan_unsafe(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
     workspace: &Workspace,
 ) -> Result<ScanResult, CliError> {
+    let scan_details = scan(
+        cargo_metadata_parameters,
+        graph,
+        root_package_ids,
+        scan_parameters,
+        workspace,
+    )?;
     match scan_parameters.args.output_format {
-        OutputFormat::Json => scan_to_report(
+        OutputFormat::Json | OutputFormat::Sarif => scan_to_report(
             cargo_metadata_parameters,
             graph,
             scan_parameters.args.output_format,
-            root_package_id,
+            root_package_ids,
             scan_parameters,
-            workspace,
+            scan_details,
         ),
         _ => scan_to_table(
             cargo_metadata_parameters,
             graph,
-            root_package_id,
+            root_package_ids,
             scan_parameters,
-            workspace,
+            scan_details,
         ),
     }
 }
 
+/// Scans the build of each of several targets. The table lists the tree of
+/// each target, followed by the packages using unsafe code only on some of
+/// them. The top level counts and findings of the report cover the files
+/// used by any of the targets, and the used and unused counts of each target
+/// are listed separately.
+pub fn scan_unsafe_for_targets(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
+    target_scan_parameters: &[ScanParameters],
+    workspace: &Workspace,
+) -> Result<ScanResult, CliError> {
+    let mut combined_scan_result = ScanResult::default();
+    let mut combined_details: Option<ScanDetails> = None;
+    let mut target_reports = Vec::new();
+    let mut target_packages_using_unsafe = Vec::new();
+
+    for scan_parameters in target_scan_parameters {
+        let target = scan_parameters.target.unwrap_or_default();
+        let scan_details = scan(
+            cargo_metadata_parameters,
+            graph,
+            root_package_ids,
+            scan_parameters,
+            workspace,
+        )?;
+        let mut scan_result = match scan_parameters.args.output_format {
+            OutputFormat::Json => {
+                let (report, scan_result) = create_report(
+                    cargo_metadata_parameters,
+                    graph,
+                    root_package_ids,
+                    scan_parameters,
+                    &scan_details,
+                );
+                target_reports.push((target, report));
+                match combined_details.as_mut() {
+                    Some(combined_details) => {
+                        merge_scan_details(combined_details, scan_details)
+                    }
+                    None => combined_details = Some(scan_details),
+                }
+                scan_result
+            }
+            _ => {
+                target_packages_using_unsafe.push((
+                    target,
+                    packages_using_unsafe(
+                        cargo_metadata_parameters,
+                        graph,
+                        root_package_ids,
+                        &scan_details,
+                    ),
+                ));
+                let mut scan_result = scan_to_table(
+                    cargo_metadata_parameters,
+                    graph,
+                    root_package_ids,
+                    scan_parameters,
+                    scan_details,
+                )?;
+                combined_scan_result
+                    .scan_output_lines
+                    .push(format!("Target: {}", target));
+                combined_scan_result
+                    .scan_output_lines
+                    .append(&mut scan_result.scan_output_lines);
+                scan_result
+            }
+        };
+        combined_scan_result.warning_count += scan_result.warning_count;
+        for policy_violation in scan_result.policy_violations {
+            if !combined_scan_result
+                .policy_violations
+                .contains(&policy_violation)
+            {
+                combined_scan_result
+                    .policy_violations
+                    .push(policy_violation);
+            }
+        }
+        combined_scan_result
+            .undocumented_unsafe
+            .append(&mut scan_result.undocumented_unsafe);
+        combined_scan_result
+            .files_with_errors
+            .append(&mut scan_result.files_with_errors);
+    }
+
+    combined_scan_result.undocumented_unsafe.sort();
+    combined_scan_result.undocumented_unsafe.dedup();
+    combined_scan_result.files_with_errors.sort();
+    combined_scan_result.files_with_errors.dedup();
+
+    match target_scan_parameters[0].args.output_format {
+        OutputFormat::Json => {
+            let (report, _) = create_report(
+                cargo_metadata_parameters,
+                graph,
+                root_package_ids,
+                &target_scan_parameters[0],
+                &combined_details.unwrap_or_default(),
+            );
+            let mut combined_report =
+                add_target_reports(report, target_reports);
+            combined_report.files_with_errors =
+                combined_scan_result.files_with_errors.clone();
+            combined_scan_result.scan_output_lines =
+                vec![serde_json::to_string(&combined_report).unwrap()];
+        }
+        _ => {
+            let mut target_specific_unsafe_lines =
+                construct_target_specific_unsafe_lines(
+                    &target_packages_using_unsafe,
+                );
+            if !target_specific_unsafe_lines.is_empty() {
+                combined_scan_result
+                    .scan_output_lines
+                    .append(&mut target_specific_unsafe_lines);
+                combined_scan_result.scan_output_lines.push(String::new());
+            }
+        }
+    }
+
+    Ok(combined_scan_result)
+}
+
 /// Based on code from cargo-bloat. It seems weird that `CompileOptions` can be
 /// constructed without providing all standard cargo options, TODO: Open an issue
 /// in cargo?
@@ -69,71 +213,167 @@ fn build_compile_options<'a>(
     )
     .unwrap();
 
-    // TODO: Investigate if this is relevant to cargo-geiger.
-    //let mut bins = Vec::new();
-    //let mut examples = Vec::new();
-    // opt.release = args.release;
-    // opt.target = args.target.clone();
-    // if let Some(ref name) = args.bin {
-    //     bins.push(name.clone());
-    // } else if let Some(ref name) = args.example {
-    //     examples.push(name.clone());
-    // }
-    // if args.bin.is_some() || args.example.is_some() {
-    //     opt.filter = ops::CompileFilter::new(
-    //         false,
-    //         bins.clone(), false,
-    //         Vec::new(), false,
-    //         examples.clone(), false,
-    //         Vec::new(), false,
-    //         false,
-    //     );
-    // }
-
     compile_options
 }
 
+/// The build targets selected with `--lib`, `--bin`, `--example`, `--tests`,
+/// `--benches` and `--all-build-targets`, the default targets of
+/// `cargo check` when none are.
+fn build_compile_filter(build_target_args: &BuildTargetArgs) -> CompileFilter {
+    CompileFilter::from_raw_arguments(
+        build_target_args.lib,
+        build_target_args.bins.clone(),
+        false,
+        Vec::new(),
+        build_target_args.tests,
+        build_target_args.examples.clone(),
+        false,
+        Vec::new(),
+        build_target_args.benches,
+        build_target_args.all_targets,
+    )
+}
+
 fn scan(
     cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
     workspace: &Workspace,
 ) -> Result<ScanDetails, CliError> {
-    let compile_options = build_compile_options(
-        &scan_parameters.args.features_args,
-        scan_parameters.config,
-    );
-    let rs_files_used =
-        resolve_rs_file_deps(&compile_options, workspace).unwrap();
+    let rs_files_used = if scan_parameters.args.no_build {
+        resolve_rs_files_from_module_trees(
+            &scan_parameters.args.build_target_args,
+            cargo_metadata_parameters.metadata,
+            graph.nodes.keys(),
+            root_package_ids,
+        )
+    } else {
+        resolve_rs_files_used_by_build(
+            cargo_metadata_parameters,
+            root_package_ids,
+            scan_parameters,
+            workspace,
+        )?
+    };
     let geiger_context = find_unsafe(
         cargo_metadata_parameters,
-        scan_parameters.config,
         ScanMode::Full,
-        scan_parameters.print_config,
+        scan_parameters,
     )?;
+    let macro_expanded = macro_expanded_stats(&geiger_context, &rs_files_used);
     Ok(ScanDetails {
         rs_files_used,
         geiger_context,
+        macro_expanded,
     })
 }
 
+/// Builds the selected targets of the root packages with `cargo check` to
+/// collect the `.rs` files used by each unit of the build.
+fn resolve_rs_files_used_by_build(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
+    workspace: &Workspace,
+) -> Result<RsFilesUsed, CliError> {
+    let mut compile_options = build_compile_options(
+        &scan_parameters.args.features_args,
+        scan_parameters.config,
+    );
+    compile_options.spec = Packages::Packages(
+        root_package_ids
+            .iter()
+            .map(|package_id| {
+                let package = &cargo_metadata_parameters.metadata[package_id];
+                format!("{}:{}", package.name, package.version)
+            })
+            .collect(),
+    );
+    compile_options.filter =
+        build_compile_filter(&scan_parameters.args.build_target_args);
+    if let Some(jobs) = scan_parameters.args.jobs {
+        compile_options.build_config.jobs = jobs as u32;
+    }
+    if let Some(target) = scan_parameters.target {
+        compile_options.build_config.requested_kinds =
+            CompileKind::from_requested_targets(
+                scan_parameters.config,
+                &[target.to_string()],
+            )?;
+    }
+    Ok(resolve_rs_file_deps(
+        &compile_options,
+        scan_parameters.args.expand_macros,
+        cargo_metadata_parameters.metadata,
+        workspace,
+    )
+    .unwrap())
+}
+
 fn scan_to_report(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
     output_format: OutputFormat,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
-    workspace: &Workspace,
+    scan_details: ScanDetails,
 ) -> Result<ScanResult, CliError> {
+    let (report, mut scan_result) = create_report(
+        cargo_metadata_parameters,
+        graph,
+        root_package_ids,
+        scan_parameters,
+        &scan_details,
+    );
+    let json_string = match output_format {
+        OutputFormat::Json => serde_json::to_string(&report).unwrap(),
+        OutputFormat::Sarif => {
+            serde_json::to_string(&SarifLog::from_safety_report(&report))
+                .unwrap()
+        }
+        _ => panic!("Only implemented for OutputFormat::Json and Sarif"),
+    };
+    scan_result.scan_output_lines = vec![json_string];
+    Ok(scan_result)
+}
+
+/// The report of a scan, and the findings of the scan that are checked after
+/// printing the report.
+fn create_report(
+    cargo_metadata_parameters: &CargoMetadataParameters,
+    graph: &Graph,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
+    scan_details: &ScanDetails,
+) -> (SafetyReport, ScanResult) {
     let ScanDetails {
         rs_files_used,
         geiger_context,
-    } = scan(cargo_metadata_parameters, scan_parameters, workspace)?;
-    let mut report = SafetyReport::default();
-    for (package, package_metrics_option) in package_metrics(
+        macro_expanded,
+    } = scan_details;
+
+    let policy_violations = match scan_parameters.policy {
+        Some(policy) => check_policy(
+            cargo_metadata_parameters,
+            geiger_context,
+            graph,
+            policy,
+            root_package_ids,
+            Some(rs_files_used),
+        ),
+        None => vec![],
+    };
+    let mut report = SafetyReport {
+        roots: report_roots(cargo_metadata_parameters, root_package_ids),
+        used_files_approximate: scan_parameters.args.no_build,
+        ..Default::default()
+    };
+    for (package_id, package, package_metrics_option) in package_metrics(
         cargo_metadata_parameters,
-        &geiger_context,
+        geiger_context,
         graph,
-        root_package_id,
+        root_package_ids,
     ) {
         let package_metrics = match package_metrics_option {
             Some(m) => m,
@@ -142,32 +382,193 @@ fn scan_to_report(
                 continue;
             }
         };
-        let unsafe_info = unsafe_stats(&package_metrics, &rs_files_used);
+        let package_rs_files_used = rs_files_used.for_package(&package_id);
+        let mut unsafe_info =
+            unsafe_stats(&package_metrics, &package_rs_files_used);
+        unsafe_info.findings =
+            unsafe_findings(&package_metrics, &package_rs_files_used);
+        unsafe_info.macro_expanded = macro_expanded.get(&package_id).cloned();
+        unsafe_info.units =
+            unit_unsafe_stats(&package_id, &package_metrics, rs_files_used);
         let entry = ReportEntry {
             package,
             unsafety: unsafe_info,
         };
         report.packages.insert(entry.package.id.clone(), entry);
     }
+    let undocumented_unsafe = match scan_parameters.args.undocumented_unsafe {
+        Some(_) => undocumented_unsafe_findings(
+            geiger_context,
+            rs_files_used,
+            &cargo_metadata_parameters.metadata.workspace_members,
+        ),
+        None => vec![],
+    };
+    if let Some(count) = scan_parameters.args.hotspot_count() {
+        report.hotspots = function_hotspots(
+            cargo_metadata_parameters,
+            geiger_context,
+            graph,
+            rs_files_used,
+            count,
+        );
+    }
     report.used_but_not_scanned_files =
-        list_files_used_but_not_scanned(&geiger_context, &rs_files_used)
+        list_files_used_but_not_scanned(geiger_context, &rs_files_used.all())
             .into_iter()
             .collect();
-    let json_string = match output_format {
-        OutputFormat::Json => serde_json::to_string(&report).unwrap(),
-        _ => panic!("Only implemented for OutputFormat::Json"),
-    };
+    report.files_with_errors = geiger_context.files_with_errors.clone();
+    (
+        report,
+        ScanResult {
+            scan_output_lines: vec![],
+            warning_count: 0,
+            policy_violations,
+            undocumented_unsafe,
+            files_with_errors: geiger_context.files_with_errors.clone(),
+        },
+    )
+}
 
-    Ok(ScanResult {
-        scan_output_lines: vec![json_string],
-        warning_count: 0,
-    })
+/// Adds the scan of the build of another target to the combined scan of
+/// several targets. A file used by any of the builds is used, and a file
+/// scanned with the cfgs of several targets keeps the largest of its
+/// counters and all of its findings, so that unsafe code only compiled for
+/// some of the targets is counted once.
+fn merge_scan_details(combined: &mut ScanDetails, details: ScanDetails) {
+    for (unit, rs_files) in details.rs_files_used.unit_to_rs_files {
+        combined
+            .rs_files_used
+            .unit_to_rs_files
+            .entry(unit)
+            .or_default()
+            .extend(rs_files);
+    }
+    for (unit, source) in details.rs_files_used.unit_to_expanded_source {
+        combined
+            .rs_files_used
+            .unit_to_expanded_source
+            .entry(unit)
+            .or_insert(source);
+    }
+    for (package_id, package_metrics) in
+        details.geiger_context.package_id_to_metrics
+    {
+        let combined_package_metrics = combined
+            .geiger_context
+            .package_id_to_metrics
+            .entry(package_id)
+            .or_default();
+        for (path, wrapper) in package_metrics.rs_path_to_metrics {
+            match combined_package_metrics.rs_path_to_metrics.entry(path) {
+                Entry::Occupied(mut entry) => merge_rs_file_metrics(
+                    &mut entry.get_mut().metrics,
+                    wrapper.metrics,
+                ),
+                Entry::Vacant(entry) => {
+                    entry.insert(wrapper);
+                }
+            }
+        }
+    }
+    combined
+        .geiger_context
+        .ignored_paths
+        .extend(details.geiger_context.ignored_paths);
+    combined
+        .geiger_context
+        .files_with_errors
+        .extend(details.geiger_context.files_with_errors);
+    combined.geiger_context.files_with_errors.sort();
+    combined.geiger_context.files_with_errors.dedup();
+    for (package_id, counters) in details.macro_expanded {
+        let combined_counters =
+            combined.macro_expanded.entry(package_id).or_default();
+        *combined_counters = counter_block_max(combined_counters, &counters);
+    }
+}
+
+/// Merges the metrics of a file scanned with the cfgs of another target.
+fn merge_rs_file_metrics(combined: &mut RsFileMetrics, metrics: RsFileMetrics) {
+    combined.counters =
+        counter_block_max(&combined.counters, &metrics.counters);
+    // Code disabled on one of the targets but enabled on another one is
+    // counted by the counters above.
+    combined.cfg_disabled = combined.cfg_disabled.saturating_sub(
+        &combined.cfg_disabled.saturating_sub(&metrics.cfg_disabled),
+    );
+    combined.findings.extend(metrics.findings);
+    combined.findings.sort();
+    combined.findings.dedup();
+    combined.functions.extend(metrics.functions);
+    combined.functions.sort();
+    combined.functions.dedup_by(|function, kept| {
+        let same_function = function.file == kept.file
+            && function.start == kept.start
+            && function.item_path == kept.item_path;
+        if same_function {
+            kept.unsafe_fn |= function.unsafe_fn;
+            kept.unsafe_exprs = kept.unsafe_exprs.max(function.unsafe_exprs);
+        }
+        same_function
+    });
+    combined.approximate |= metrics.approximate;
+    if combined.parse_error.is_none() {
+        combined.parse_error = metrics.parse_error;
+    }
+}
+
+fn counter_block_max(a: &CounterBlock, b: &CounterBlock) -> CounterBlock {
+    a.clone() + b.saturating_sub(a)
+}
+
+/// Adds the used and unused counts of each target to the report of the
+/// combined scan of all targets.
+fn add_target_reports(
+    mut combined_report: SafetyReport,
+    target_reports: Vec<(&str, SafetyReport)>,
+) -> SafetyReport {
+    for (target, report) in target_reports {
+        combined_report.used_files_approximate |= report.used_files_approximate;
+        for (package_id, entry) in report.packages {
+            let target_unsafe_info = TargetUnsafeInfo {
+                used: entry.unsafety.used.clone(),
+                unused: entry.unsafety.unused.clone(),
+            };
+            combined_report
+                .packages
+                .entry(package_id)
+                .or_insert(entry)
+                .unsafety
+                .targets
+                .insert(target.to_string(), target_unsafe_info);
+        }
+        combined_report
+            .packages_without_metrics
+            .extend(report.packages_without_metrics);
+        combined_report
+            .used_but_not_scanned_files
+            .extend(report.used_but_not_scanned_files);
+    }
+    combined_report
 }
 
 #[cfg(test)]
 mod default_tests {
     use super::*;
+
+    use crate::scan::rs_file::{BuildUnit, RsFileMetricsWrapper};
+    use crate::scan::{GeigerContext, PackageMetrics};
+
+    use cargo::core::manifest::TargetKind;
+    use cargo_geiger_serde::{
+        Count, LineColumn, PackageInfo, Source, UnsafeFinding, UnsafeInfo,
+        UnsafeKind,
+    };
     use rstest::*;
+    use semver::Version;
+    use std::path::PathBuf;
+    use url::Url;
 
     #[rstest(
         input_features,
@@ -208,4 +609,209 @@ mod default_tests {
             args.no_default_features
         );
     }
+
+    #[rstest(
+        input_build_target_args,
+        expected_is_specific,
+        expected_is_all_targets,
+        case(BuildTargetArgs::default(), false, false),
+        case(
+            BuildTargetArgs {
+                all_targets: true,
+                ..Default::default()
+            },
+            true,
+            true
+        ),
+        case(
+            BuildTargetArgs {
+                lib: true,
+                ..Default::default()
+            },
+            true,
+            false
+        ),
+        case(
+            BuildTargetArgs {
+                bins: vec![String::from("geiger")],
+                ..Default::default()
+            },
+            true,
+            false
+        ),
+        case(
+            BuildTargetArgs {
+                tests: true,
+                ..Default::default()
+            },
+            true,
+            false
+        )
+    )]
+    fn build_compile_filter_test(
+        input_build_target_args: BuildTargetArgs,
+        expected_is_specific: bool,
+        expected_is_all_targets: bool,
+    ) {
+        let compile_filter = build_compile_filter(&input_build_target_args);
+
+        assert_eq!(compile_filter.is_specific(), expected_is_specific);
+        assert_eq!(compile_filter.is_all_targets(), expected_is_all_targets);
+    }
+
+    #[rstest]
+    fn add_target_reports_test() {
+        let package_id = cargo_geiger_serde::PackageId {
+            name: String::from("a"),
+            version: Version::new(1, 0, 0),
+            source: Source::Path(Url::parse("file:///a").unwrap()),
+        };
+        let target_report = |unsafe_exprs, used_files_approximate| {
+            let entry = ReportEntry {
+                package: PackageInfo::new(package_id.clone()),
+                unsafety: UnsafeInfo {
+                    used: CounterBlock {
+                        exprs: Count {
+                            safe: 0,
+                            unsafe_: unsafe_exprs,
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            };
+            SafetyReport {
+                roots: vec![package_id.clone()],
+                packages: vec![(package_id.clone(), entry)]
+                    .into_iter()
+                    .collect(),
+                used_files_approximate,
+                ..Default::default()
+            }
+        };
+
+        let combined_report = add_target_reports(
+            target_report(3, false),
+            vec![
+                ("aarch64-unknown-linux-gnu", target_report(2, false)),
+                ("wasm32-unknown-unknown", target_report(1, true)),
+            ],
+        );
+
+        assert_eq!(combined_report.roots, vec![package_id.clone()]);
+        assert!(combined_report.used_files_approximate);
+        let unsafety = &combined_report.packages[&package_id].unsafety;
+        assert_eq!(unsafety.used.exprs.unsafe_, 3);
+        assert_eq!(
+            unsafety
+                .targets
+                .iter()
+                .map(|(target, target_unsafe_info)| {
+                    (target.as_str(), target_unsafe_info.used.exprs.unsafe_)
+                })
+                .collect::<Vec<(&str, u64)>>(),
+            vec![
+                ("aarch64-unknown-linux-gnu", 2),
+                ("wasm32-unknown-unknown", 1)
+            ]
+        );
+    }
+
+    #[rstest]
+    fn merge_scan_details_test_targets_with_different_unsafe() {
+        let package_id = PackageId {
+            repr: String::from("a"),
+        };
+        let finding = |line| UnsafeFinding {
+            file: PathBuf::from("shared.rs"),
+            start: LineColumn { line, column: 0 },
+            end: LineColumn { line, column: 1 },
+            kind: UnsafeKind::Block,
+            item_path: String::from("f"),
+            documented: Some(false),
+        };
+        let file_metrics =
+            |unsafe_exprs, findings, approximate| RsFileMetricsWrapper {
+                metrics: RsFileMetrics {
+                    counters: CounterBlock {
+                        exprs: Count {
+                            safe: 0,
+                            unsafe_: unsafe_exprs,
+                        },
+                        ..Default::default()
+                    },
+                    findings,
+                    approximate,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+        let target_details =
+            |rs_files: Vec<(&str, RsFileMetricsWrapper)>| ScanDetails {
+                rs_files_used: RsFilesUsed {
+                    unit_to_rs_files: vec![(
+                        BuildUnit {
+                            package_id: package_id.clone(),
+                            target_kind: TargetKind::Lib(vec![]),
+                            target_name: String::from("a"),
+                        },
+                        rs_files
+                            .iter()
+                            .map(|(path, _)| PathBuf::from(path))
+                            .collect(),
+                    )]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                },
+                geiger_context: GeigerContext {
+                    package_id_to_metrics: vec![(
+                        package_id.clone(),
+                        PackageMetrics {
+                            rs_path_to_metrics: rs_files
+                                .into_iter()
+                                .map(|(path, metrics)| {
+                                    (PathBuf::from(path), metrics)
+                                })
+                                .collect(),
+                        },
+                    )]
+                    .into_iter()
+                    .collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+        let mut combined_details = target_details(vec![
+            ("linux.rs", file_metrics(1, vec![], false)),
+            ("shared.rs", file_metrics(1, vec![finding(1)], false)),
+        ]);
+
+        merge_scan_details(
+            &mut combined_details,
+            target_details(vec![
+                ("windows.rs", file_metrics(2, vec![], true)),
+                (
+                    "shared.rs",
+                    file_metrics(2, vec![finding(1), finding(5)], false),
+                ),
+            ]),
+        );
+
+        let package_metrics =
+            &combined_details.geiger_context.package_id_to_metrics[&package_id];
+        let unsafe_info = unsafe_stats(
+            package_metrics,
+            &combined_details.rs_files_used.for_package(&package_id),
+        );
+        assert_eq!(unsafe_info.used.exprs.unsafe_, 5);
+        assert_eq!(unsafe_info.unused, CounterBlock::default());
+        assert!(unsafe_info.approximate);
+        assert_eq!(
+            package_metrics.rs_path_to_metrics[&PathBuf::from("shared.rs")]
+                .metrics
+                .findings,
+            vec![finding(1), finding(5)]
+        );
+    }
 }
@@ -9,13 +9,17 @@ use crate::mapping::CargoMetadataParameters;
 use crate::tree::traversal::walk_dependency_tree;
 
 use super::super::{
-    construct_rs_files_used_lines, list_files_used_but_not_scanned,
-    ScanDetails, ScanParameters, ScanResult,
+    check_policy, construct_approximate_files_lines,
+    construct_cfg_disabled_lines, construct_files_with_errors_lines,
+    construct_hotspot_lines, construct_macro_expanded_lines,
+    construct_rs_files_used_lines, construct_safety_comment_lines,
+    construct_undocumented_unsafe_lines, construct_unit_unsafe_lines,
+    construct_unsafe_findings_lines, function_hotspots, list_approximate_files,
+    list_files_used_but_not_scanned, undocumented_unsafe_findings, ScanDetails,
+    ScanParameters, ScanResult,
 };
-use super::scan;
 
 use cargo::core::shell::Verbosity;
-use cargo::core::Workspace;
 use cargo::CliError;
 use cargo_metadata::PackageId;
 use colored::Colorize;
@@ -23,20 +27,74 @@ use colored::Colorize;
 pub fn scan_to_table(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
-    workspace: &Workspace,
+    scan_details: ScanDetails,
 ) -> Result<ScanResult, CliError> {
     let mut combined_scan_output_lines = Vec::<String>::new();
 
     let ScanDetails {
         rs_files_used,
         geiger_context,
-    } = scan(cargo_metadata_parameters, scan_parameters, workspace)?;
+        macro_expanded,
+    } = scan_details;
+
+    let hotspots = match scan_parameters.args.hotspot_count() {
+        Some(count) => function_hotspots(
+            cargo_metadata_parameters,
+            &geiger_context,
+            graph,
+            &rs_files_used,
+            count,
+        ),
+        None => vec![],
+    };
+    let policy_violations = match scan_parameters.policy {
+        Some(policy) => check_policy(
+            cargo_metadata_parameters,
+            &geiger_context,
+            graph,
+            policy,
+            root_package_ids,
+            Some(&rs_files_used),
+        ),
+        None => vec![],
+    };
+
+    let workspace_members =
+        &cargo_metadata_parameters.metadata.workspace_members;
+    let undocumented_unsafe = match scan_parameters.args.undocumented_unsafe {
+        Some(_) => undocumented_unsafe_findings(
+            &geiger_context,
+            &rs_files_used,
+            workspace_members,
+        ),
+        None => vec![],
+    };
+
+    // The hotspots subcommand only lists the hotspots, without the tree, but
+    // still checks the policy and reports the files that could not be
+    // analysed.
+    if scan_parameters.args.hotspots {
+        let mut hotspot_lines = construct_hotspot_lines(&hotspots);
+        if !geiger_context.files_with_errors.is_empty() {
+            hotspot_lines.push(String::new());
+            hotspot_lines.append(&mut construct_files_with_errors_lines(
+                &geiger_context.files_with_errors,
+            ));
+        }
+        return Ok(ScanResult {
+            scan_output_lines: hotspot_lines,
+            warning_count: 0,
+            policy_violations,
+            undocumented_unsafe,
+            files_with_errors: geiger_context.files_with_errors,
+        });
+    }
 
     if scan_parameters.print_config.verbosity == Verbosity::Verbose {
         let mut rs_files_used_lines =
-            construct_rs_files_used_lines(&rs_files_used);
+            construct_rs_files_used_lines(&rs_files_used.all());
         combined_scan_output_lines.append(&mut rs_files_used_lines);
     }
 
@@ -48,12 +106,17 @@ pub fn scan_to_table(
     );
     combined_scan_output_lines.append(&mut output_key_lines);
 
-    let text_tree_lines = walk_dependency_tree(
-        cargo_metadata_parameters,
-        graph,
-        scan_parameters.print_config,
-        root_package_id,
-    );
+    let text_trees = root_package_ids
+        .iter()
+        .map(|root_package_id| {
+            walk_dependency_tree(
+                cargo_metadata_parameters,
+                graph,
+                scan_parameters.print_config,
+                root_package_id.clone(),
+            )
+        })
+        .collect();
     let table_parameters = TableParameters {
         geiger_context: &geiger_context,
         print_config: scan_parameters.print_config,
@@ -63,15 +126,96 @@ pub fn scan_to_table(
     let ScanResult {
         mut scan_output_lines,
         mut warning_count,
+        ..
     } = create_table_from_text_tree_lines(
         cargo_metadata_parameters,
         &table_parameters,
-        text_tree_lines,
+        text_trees,
     );
     combined_scan_output_lines.append(&mut scan_output_lines);
 
+    if scan_parameters.args.no_build {
+        combined_scan_output_lines.push(String::new());
+        combined_scan_output_lines.push(String::from(
+            "Approximate: the used files were resolved from the module tree of each build target without building, \
+             the split into used and unused code is approximate",
+        ));
+    }
+
+    if scan_parameters.args.build_target_args.is_specific() {
+        let mut unit_unsafe_lines = construct_unit_unsafe_lines(
+            &geiger_context,
+            &rs_files_used,
+            root_package_ids,
+        );
+        if !unit_unsafe_lines.is_empty() {
+            combined_scan_output_lines.push(String::new());
+            combined_scan_output_lines.append(&mut unit_unsafe_lines);
+        }
+    }
+
+    if scan_parameters.print_config.verbosity == Verbosity::Verbose {
+        let mut unsafe_findings_lines =
+            construct_unsafe_findings_lines(&geiger_context, &rs_files_used);
+        combined_scan_output_lines.append(&mut unsafe_findings_lines);
+        let mut cfg_disabled_lines =
+            construct_cfg_disabled_lines(&geiger_context, &rs_files_used);
+        if !cfg_disabled_lines.is_empty() {
+            combined_scan_output_lines.push(String::new());
+            combined_scan_output_lines.append(&mut cfg_disabled_lines);
+        }
+    }
+
+    let mut macro_expanded_lines =
+        construct_macro_expanded_lines(&macro_expanded);
+    if !macro_expanded_lines.is_empty() {
+        combined_scan_output_lines.push(String::new());
+        combined_scan_output_lines.append(&mut macro_expanded_lines);
+    }
+
+    if scan_parameters.args.undocumented_unsafe.is_some() {
+        let mut safety_comment_lines = construct_safety_comment_lines(
+            &geiger_context,
+            &rs_files_used,
+            workspace_members,
+        );
+        if !safety_comment_lines.is_empty() {
+            combined_scan_output_lines.push(String::new());
+            combined_scan_output_lines.append(&mut safety_comment_lines);
+        }
+    }
+    if !undocumented_unsafe.is_empty() {
+        combined_scan_output_lines.push(String::new());
+        combined_scan_output_lines.append(
+            &mut construct_undocumented_unsafe_lines(&undocumented_unsafe),
+        );
+    }
+
+    if !hotspots.is_empty() {
+        combined_scan_output_lines.push(String::new());
+        combined_scan_output_lines
+            .append(&mut construct_hotspot_lines(&hotspots));
+    }
+
+    let approximate_files =
+        list_approximate_files(&geiger_context, &rs_files_used.all());
+    if !approximate_files.is_empty() {
+        combined_scan_output_lines.push(String::new());
+        combined_scan_output_lines
+            .append(&mut construct_approximate_files_lines(&approximate_files));
+    }
+
+    if !geiger_context.files_with_errors.is_empty() {
+        combined_scan_output_lines.push(String::new());
+        combined_scan_output_lines.append(
+            &mut construct_files_with_errors_lines(
+                &geiger_context.files_with_errors,
+            ),
+        );
+    }
+
     let used_but_not_scanned =
-        list_files_used_but_not_scanned(&geiger_context, &rs_files_used);
+        list_files_used_but_not_scanned(&geiger_context, &rs_files_used.all());
     warning_count += used_but_not_scanned.len() as u64;
     for path in &used_but_not_scanned {
         eprintln!(
@@ -83,6 +227,9 @@ pub fn scan_to_table(
     Ok(ScanResult {
         scan_output_lines: combined_scan_output_lines,
         warning_count,
+        policy_violations,
+        undocumented_unsafe,
+        files_with_errors: geiger_context.files_with_errors,
     })
 }
 
@@ -117,6 +264,7 @@ fn construct_key_lines(
     output_key_lines.push(String::from("Symbols: "));
 
     let forbids = "No `unsafe` usage found, declares #![forbid(unsafe_code)]";
+    let denies = "No `unsafe` usage found, declares #![deny(unsafe_code)]";
     let unknown = "No `unsafe` usage found, missing #![forbid(unsafe_code)]";
     let guilty = "`unsafe` usage found";
 
@@ -132,6 +280,7 @@ fn construct_key_lines(
 
     let symbol_kinds_to_string_values = vec![
         (SymbolKind::Lock, "", forbids),
+        (SymbolKind::LockWithKey, "", denies),
         (SymbolKind::QuestionMark, "", unknown),
         (SymbolKind::Rads, shift_sequence, guilty),
     ];
@@ -1,35 +1,50 @@
 use crate::format::print_config::PrintConfig;
 use crate::mapping::{CargoMetadataParameters, GetPackageRoot};
+use crate::scan::cache::{find_unsafe_in_file_cached, ScanCache};
+use crate::scan::lints::ManifestLints;
 use crate::scan::rs_file::{
     into_is_entry_point_and_path_buf, into_rs_code_file, into_target_kind,
     is_file_with_ext, RsFile, RsFileMetricsWrapper,
 };
 use crate::scan::PackageMetrics;
 
-use super::{GeigerContext, ScanMode};
+use super::{GeigerContext, ScanMode, ScanParameters};
 
 use cargo::util::CargoResult;
-use cargo::{CliError, Config};
+use cargo::CliError;
+use cargo_geiger_serde::FileWithError;
 use cargo_metadata::PackageId;
-use geiger::find::find_unsafe_in_file;
-use geiger::{IncludeTests, RsFileMetrics, ScanFileError};
+use cargo_platform::Cfg;
+use geiger::module_tree::resolve_module_tree;
+use geiger::{RsFileMetrics, ScanFileError};
 use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 use walkdir::WalkDir;
 
 pub fn find_unsafe(
     cargo_metadata_parameters: &CargoMetadataParameters,
-    config: &Config,
     mode: ScanMode,
-    print_config: &PrintConfig,
+    scan_parameters: &ScanParameters,
 ) -> Result<GeigerContext, CliError> {
+    let config = scan_parameters.config;
+    let print_config = scan_parameters.print_config;
+    let cache = match scan_parameters.args.no_cache {
+        true => None,
+        false => Some(ScanCache::new(config)),
+    };
     let mut progress = cargo::util::Progress::new("Scanning", config);
     let geiger_context = find_unsafe_in_packages(
-        print_config.allow_partial_results,
         cargo_metadata_parameters,
-        print_config.include_tests,
+        cache.as_ref(),
+        scan_parameters.cfgs,
+        scan_jobs(scan_parameters.args.jobs),
         mode,
+        print_config,
         |i, count| -> CargoResult<()> {
             progress.tick(i, count, "find_unsafe_tick")
         },
@@ -39,25 +54,60 @@ pub fn find_unsafe(
     Ok(geiger_context)
 }
 
+/// The number of threads to scan with, like cargo one per CPU by default.
+fn scan_jobs(jobs: Option<usize>) -> usize {
+    match jobs {
+        Some(jobs) => jobs.max(1),
+        None => thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1),
+    }
+}
+
 fn find_unsafe_in_packages<F>(
-    allow_partial_results: bool,
     cargo_metadata_parameters: &CargoMetadataParameters,
-    include_tests: IncludeTests,
+    cache: Option<&ScanCache>,
+    cfgs: Option<&[Cfg]>,
+    jobs: usize,
     mode: ScanMode,
-    mut progress_step: F,
+    print_config: &PrintConfig,
+    progress_step: F,
 ) -> GeigerContext
 where
     F: FnMut(usize, usize) -> CargoResult<()>,
 {
+    let package_id_to_cfgs = cfgs.map(|cfgs| {
+        package_id_to_cfgs(cargo_metadata_parameters.metadata, cfgs)
+    });
     let mut package_id_to_metrics = HashMap::new();
     let mut ignored = HashSet::new();
+    let mut files_with_errors = Vec::new();
     let packages = cargo_metadata_parameters.metadata.packages.to_vec();
-    let package_code_files: Vec<_> =
-        find_rs_files_in_packages(&packages).collect();
-    let package_code_file_count = package_code_files.len();
-    for (i, (package_id, rs_code_file)) in
-        package_code_files.into_iter().enumerate()
-    {
+    let package_id_to_module_paths =
+        package_id_to_module_paths(&packages, jobs, &mode);
+    let workspace_root: &Path =
+        cargo_metadata_parameters.metadata.workspace_root.as_ref();
+    let package_id_to_manifest_lints = packages
+        .iter()
+        .map(|package| {
+            (
+                package.id.clone(),
+                ManifestLints::read(
+                    package.manifest_path.as_ref(),
+                    workspace_root,
+                ),
+            )
+        })
+        .collect::<HashMap<PackageId, ManifestLints>>();
+    let mut scanned_files = Vec::new();
+    for (package_id, rs_code_file) in find_rs_files_in_packages(&packages) {
+        let rs_code_file = match rs_code_file {
+            Ok(rs_code_file) => rs_code_file,
+            Err(error) => {
+                handle_unsafe_in_file_error(error, &mut files_with_errors);
+                continue;
+            }
+        };
         if let RsFile::CustomBuildRoot(path_buf) = rs_code_file {
             ignored.insert(path_buf);
             continue;
@@ -67,17 +117,56 @@ where
         if let (false, ScanMode::EntryPointsOnly) = (is_entry_point, &mode) {
             continue;
         }
-        match find_unsafe_in_file(&path_buf, include_tests) {
+        scanned_files.push((package_id, is_entry_point, path_buf));
+    }
+    let scan_results = scan_in_parallel(
+        &scanned_files,
+        jobs,
+        |(package_id, _, path_buf)| {
+            let package_cfgs = package_id_to_cfgs
+                .as_ref()
+                .and_then(|package_id_to_cfgs| {
+                    package_id_to_cfgs.get(package_id)
+                })
+                .map(Vec::as_slice);
+            find_unsafe_in_file_cached(
+                cache,
+                path_buf,
+                print_config.include_tests,
+                package_cfgs,
+            )
+        },
+        progress_step,
+    );
+    // Merged in the order the files were found, independent of which
+    // thread finished scanning first.
+    for ((package_id, is_entry_point, path_buf), scan_result) in
+        scanned_files.into_iter().zip(scan_results)
+    {
+        match scan_result {
             Err(error) => {
-                handle_unsafe_in_file_error(
-                    allow_partial_results,
-                    error,
-                    &path_buf,
-                );
+                handle_unsafe_in_file_error(error, &mut files_with_errors);
             }
-            Ok(rs_file_metrics) => {
+            Ok(mut rs_file_metrics) => {
+                // Files scanned token by token are approximate, their parse
+                // error is still reported.
+                if let Some(parse_error) = &rs_file_metrics.parse_error {
+                    files_with_errors.push(parse_error.clone());
+                }
+                if is_entry_point {
+                    if let Some(manifest_lints) =
+                        package_id_to_manifest_lints.get(&package_id)
+                    {
+                        manifest_lints.apply(&mut rs_file_metrics);
+                    }
+                }
+                let module_path = package_id_to_module_paths
+                    .get(&package_id)
+                    .and_then(|module_paths| module_paths.get(&path_buf))
+                    .cloned();
                 update_package_id_to_metrics_with_rs_file_metrics(
                     is_entry_point,
+                    module_path,
                     package_id,
                     &mut package_id_to_metrics,
                     path_buf,
@@ -85,7 +174,6 @@ where
                 );
             }
         }
-        let _ = progress_step(i, package_code_file_count);
     }
 
     let cargo_core_package_metrics = package_id_to_metrics
@@ -94,30 +182,118 @@ where
             (cargo_metadata_package_id.clone(), package_metrics.clone())
         })
         .collect::<HashMap<PackageId, PackageMetrics>>();
+    files_with_errors.sort();
 
     GeigerContext {
         package_id_to_metrics: cargo_core_package_metrics,
         ignored_paths: ignored,
+        files_with_errors,
     }
 }
 
-fn find_rs_files_in_dir(dir: &Path) -> impl Iterator<Item = PathBuf> {
+/// Applies `scan` to all `items` on up to `jobs` threads, returning the
+/// results in the order of `items`. `progress_step` is called on the calling
+/// thread each time an item completes.
+fn scan_in_parallel<T, R, S, F>(
+    items: &[T],
+    jobs: usize,
+    scan: S,
+    mut progress_step: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    S: Fn(&T) -> R + Sync,
+    F: FnMut(usize, usize) -> CargoResult<()>,
+{
+    let item_count = items.len();
+    let next_index = AtomicUsize::new(0);
+    let mut results = Vec::with_capacity(item_count);
+    results.resize_with(item_count, || None);
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs.clamp(1, item_count.max(1)) {
+            let sender = sender.clone();
+            let next_index = &next_index;
+            let scan = &scan;
+            scope.spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                if index >= item_count {
+                    break;
+                }
+                if sender.send((index, scan(&items[index]))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        for (completed, (index, result)) in receiver.iter().enumerate() {
+            results[index] = Some(result);
+            let _ = progress_step(completed + 1, item_count);
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.expect("every item is scanned"))
+        .collect()
+}
+
+/// Extends the platform `cfgs` with the features enabled for each package
+/// in the resolved dependency graph.
+fn package_id_to_cfgs(
+    metadata: &cargo_metadata::Metadata,
+    cfgs: &[Cfg],
+) -> HashMap<PackageId, Vec<Cfg>> {
+    let nodes = match &metadata.resolve {
+        Some(resolve) => resolve.nodes.as_slice(),
+        None => &[],
+    };
+    metadata
+        .packages
+        .iter()
+        .map(|package| {
+            let features = nodes
+                .iter()
+                .find(|node| node.id == package.id)
+                .map(|node| node.features.as_slice())
+                .unwrap_or_default();
+            let package_cfgs = cfgs
+                .iter()
+                .cloned()
+                .chain(features.iter().map(|feature| {
+                    Cfg::KeyPair(String::from("feature"), feature.clone())
+                }))
+                .collect();
+            (package.id.clone(), package_cfgs)
+        })
+        .collect()
+}
+
+fn find_rs_files_in_dir(
+    dir: &Path,
+) -> impl Iterator<Item = Result<PathBuf, ScanFileError>> + '_ {
     let walker = WalkDir::new(dir).into_iter();
-    walker.filter_map(|entry| {
-        let entry = entry.expect("walkdir error."); // TODO: Return result.
+    walker.filter_map(move |entry| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                let path = error.path().unwrap_or(dir).to_path_buf();
+                return Some(Err(ScanFileError::Io(error.into(), path)));
+            }
+        };
         if !is_file_with_ext(&entry, "rs") {
             return None;
         }
-        Some(
-            entry
-                .path()
-                .canonicalize()
-                .expect("Error converting to canonical path"),
-        ) // TODO: Return result.
+        Some(entry.path().canonicalize().map_err(|error| {
+            ScanFileError::Io(error, entry.path().to_path_buf())
+        }))
     })
 }
 
-fn find_rs_files_in_package(package: &cargo_metadata::Package) -> Vec<RsFile> {
+fn find_rs_files_in_package(
+    package: &cargo_metadata::Package,
+) -> Vec<Result<RsFile, ScanFileError>> {
+    let mut rs_files = Vec::new();
     // Find all build target entry point source files.
     let mut canon_targets = HashMap::new();
     for target in &package.targets {
@@ -131,18 +307,25 @@ fn find_rs_files_in_package(package: &cargo_metadata::Package) -> Vec<RsFile> {
             // everything. We have to skip this build target.
             continue;
         }
-        let canon = path
-            .canonicalize() // will Err on non-existing paths.
-            .expect("canonicalize for build target path failed."); // FIXME
+        let canon = match path.canonicalize() {
+            Ok(canon) => canon,
+            Err(error) => {
+                let path: &Path = path.as_ref();
+                let path_buf = path.to_path_buf();
+                rs_files.push(Err(ScanFileError::Io(error, path_buf)));
+                continue;
+            }
+        };
         let targets = canon_targets.entry(canon).or_insert_with(Vec::new);
         targets.push(target);
     }
-    let mut rs_files = Vec::new();
 
     if let Some(root_path) = package.clone().get_root() {
-        for path_bufs in find_rs_files_in_dir(root_path.as_path()) {
-            if !canon_targets.contains_key(&path_bufs) {
-                rs_files.push(RsFile::Other(path_bufs));
+        for path_buf in find_rs_files_in_dir(root_path.as_path()) {
+            match path_buf {
+                Ok(path_buf) if canon_targets.contains_key(&path_buf) => {}
+                Ok(path_buf) => rs_files.push(Ok(RsFile::Other(path_buf))),
+                Err(error) => rs_files.push(Err(error)),
             }
         }
     }
@@ -150,7 +333,8 @@ fn find_rs_files_in_package(package: &cargo_metadata::Package) -> Vec<RsFile> {
     for (path_buf, targets) in canon_targets.into_iter() {
         for target in targets {
             let target_kind = into_target_kind(target.clone().kind);
-            rs_files.push(into_rs_code_file(&target_kind, path_buf.clone()));
+            rs_files
+                .push(Ok(into_rs_code_file(&target_kind, path_buf.clone())));
         }
     }
 
@@ -159,7 +343,7 @@ fn find_rs_files_in_package(package: &cargo_metadata::Package) -> Vec<RsFile> {
 
 fn find_rs_files_in_packages(
     packages: &[cargo_metadata::Package],
-) -> impl Iterator<Item = (PackageId, RsFile)> + '_ {
+) -> impl Iterator<Item = (PackageId, Result<RsFile, ScanFileError>)> + '_ {
     packages.iter().flat_map(|package| {
         find_rs_files_in_package(package)
             .into_iter()
@@ -167,25 +351,85 @@ fn find_rs_files_in_packages(
     })
 }
 
+/// Reports a file that could not be analysed, and records it to be listed in
+/// the output and fail the run in strict mode.
 fn handle_unsafe_in_file_error(
-    allow_partial_results: bool,
     error: ScanFileError,
-    path_buf: &Path,
+    files_with_errors: &mut Vec<FileWithError>,
 ) {
-    if allow_partial_results {
-        eprintln!("Failed to parse file: {}, {:?} ", path_buf.display(), error);
-    } else {
-        panic!("Failed to parse file: {}, {:?} ", path_buf.display(), error);
+    let file_with_error = FileWithError::from(&error);
+    eprintln!(
+        "Failed to parse file: {}, {:?} ",
+        file_with_error.file.display(),
+        error
+    );
+    files_with_errors.push(file_with_error);
+}
+
+/// The module path of each file of each package, resolved on up to `jobs`
+/// threads. Module paths only prefix the item paths of findings and
+/// functions, so they are not resolved when only scanning entry points.
+fn package_id_to_module_paths(
+    packages: &[cargo_metadata::Package],
+    jobs: usize,
+    mode: &ScanMode,
+) -> HashMap<PackageId, HashMap<PathBuf, String>> {
+    if let ScanMode::EntryPointsOnly = mode {
+        return HashMap::new();
+    }
+    let module_paths =
+        scan_in_parallel(packages, jobs, package_module_paths, |_, _| Ok(()));
+    packages
+        .iter()
+        .map(|package| package.id.clone())
+        .zip(module_paths)
+        .collect()
+}
+
+/// Merges the module trees of all build targets of the package. Entry points
+/// that fail to resolve are skipped, those errors are reported when scanning.
+fn package_module_paths(
+    package: &cargo_metadata::Package,
+) -> HashMap<PathBuf, String> {
+    let mut file_to_module_path = HashMap::new();
+    for target in &package.targets {
+        if let Ok(module_tree) = resolve_module_tree(target.src_path.as_ref()) {
+            for (path_buf, module_path) in module_tree.file_to_module_path {
+                file_to_module_path.entry(path_buf).or_insert(module_path);
+            }
+        }
+    }
+    file_to_module_path
+}
+
+/// Prefixes the item path of every finding with the module path of the file.
+fn prefix_item_paths(rs_file_metrics: &mut RsFileMetrics, module_path: &str) {
+    if module_path.is_empty() {
+        return;
+    }
+    let prefix = |item_path: &str| match item_path.is_empty() {
+        true => module_path.to_string(),
+        false => format!("{}::{}", module_path, item_path),
+    };
+    for finding in &mut rs_file_metrics.findings {
+        finding.item_path = prefix(&finding.item_path);
+    }
+    for function in &mut rs_file_metrics.functions {
+        function.item_path = prefix(&function.item_path);
     }
 }
 
 fn update_package_id_to_metrics_with_rs_file_metrics(
     is_entry_point: bool,
+    module_path: Option<String>,
     package_id: PackageId,
     package_id_to_metrics: &mut HashMap<PackageId, PackageMetrics>,
     path_buf: PathBuf,
-    rs_file_metrics: RsFileMetrics,
+    mut rs_file_metrics: RsFileMetrics,
 ) {
+    if let Some(module_path) = &module_path {
+        prefix_item_paths(&mut rs_file_metrics, module_path);
+    }
     let package_metrics = package_id_to_metrics
         .entry(package_id)
         .or_insert_with(PackageMetrics::default);
@@ -195,13 +439,19 @@ fn update_package_id_to_metrics_with_rs_file_metrics(
         .or_insert_with(RsFileMetricsWrapper::default);
     wrapper.metrics = rs_file_metrics;
     wrapper.is_crate_entry_point = is_entry_point;
+    wrapper.module_path = module_path;
 }
 
 #[cfg(test)]
 mod find_tests {
     use super::*;
 
+    use cargo_geiger_serde::{
+        FileErrorKind, FunctionMetrics, LineColumn, UnsafeFinding, UnsafeKind,
+    };
     use cargo_metadata::{CargoOpt, MetadataCommand};
+    use geiger::find::find_unsafe_in_file;
+    use geiger::IncludeTests;
     use rstest::*;
     use std::fs::File;
     use std::io;
@@ -232,6 +482,7 @@ mod find_tests {
 
         let mut actual_rs_file_names = actual_rs_files
             .into_iter()
+            .map(Result::unwrap)
             .map(|f| {
                 String::from(f.as_path().file_name().unwrap().to_str().unwrap())
             })
@@ -250,7 +501,7 @@ mod find_tests {
 
         let path_bufs_in_package = rs_files_in_package
             .iter()
-            .map(|f| match f {
+            .map(|f| match f.as_ref().unwrap() {
                 RsFile::BinRoot(path_buf) => path_buf,
                 RsFile::CustomBuildRoot(path_buf) => path_buf,
                 RsFile::LibRoot(path_buf) => path_buf,
@@ -264,32 +515,41 @@ mod find_tests {
     }
 
     #[rstest]
-    fn handle_unsafe_in_file_error_doesnt_panic_when_allow_partial_results_is_true(
-    ) {
+    fn handle_unsafe_in_file_error_records_file_with_error() {
         let path_buf = PathBuf::from("test_path");
+        let mut files_with_errors = vec![];
+
         handle_unsafe_in_file_error(
-            true,
             ScanFileError::Io(
                 io::Error::new(ErrorKind::Other, "test"),
                 path_buf.clone(),
             ),
-            &path_buf,
+            &mut files_with_errors,
+        );
+
+        assert_eq!(
+            files_with_errors,
+            vec![FileWithError {
+                file: path_buf,
+                kind: FileErrorKind::Io,
+                message: String::from("test"),
+                start: None,
+            }]
         );
     }
 
     #[rstest]
-    #[should_panic]
-    fn handle_unsafe_in_file_error_panics_when_allow_partial_results_is_false()
-    {
-        let path_buf = PathBuf::from("test_path");
-        handle_unsafe_in_file_error(
-            false,
-            ScanFileError::Io(
-                io::Error::new(ErrorKind::Other, "test"),
-                path_buf.clone(),
-            ),
-            &path_buf,
-        );
+    fn find_rs_files_in_dir_test_missing_dir() {
+        let temp_dir = tempdir().unwrap();
+        let missing_dir = temp_dir.path().join("missing");
+
+        let rs_files = find_rs_files_in_dir(&missing_dir).collect::<Vec<_>>();
+
+        assert_eq!(rs_files.len(), 1);
+        assert!(matches!(
+            &rs_files[0],
+            Err(ScanFileError::Io(_, path_buf)) if path_buf == &missing_dir
+        ));
     }
 
     #[rstest(
@@ -308,7 +568,7 @@ mod find_tests {
             HashMap::<PackageId, PackageMetrics>::new();
 
         let mut rs_files_in_package = find_rs_files_in_package(&package);
-        let rs_file = rs_files_in_package.pop().unwrap();
+        let rs_file = rs_files_in_package.pop().unwrap().unwrap();
         let (_, path_buf) = into_is_entry_point_and_path_buf(rs_file);
 
         let rs_file_metrics =
@@ -316,6 +576,7 @@ mod find_tests {
 
         update_package_id_to_metrics_with_rs_file_metrics(
             input_is_entry_point,
+            None,
             package.id.clone(),
             &mut package_id_to_metrics,
             package.manifest_path.clone().into(),
@@ -335,6 +596,113 @@ mod find_tests {
         assert_eq!(wrapper.is_crate_entry_point, expected_is_crate_entry_point);
     }
 
+    #[rstest]
+    fn package_module_paths_test() {
+        let package = get_current_workspace_package();
+        let src_dir = package
+            .manifest_path
+            .parent()
+            .unwrap()
+            .join("src")
+            .canonicalize()
+            .unwrap();
+
+        let module_paths = package_module_paths(&package);
+
+        assert_eq!(
+            module_paths.get(&src_dir.join("lib.rs")),
+            Some(&String::new())
+        );
+        assert_eq!(
+            module_paths.get(&src_dir.join("scan").join("find.rs")),
+            Some(&String::from("scan::find"))
+        );
+    }
+
+    #[rstest]
+    fn package_id_to_module_paths_test() {
+        let package = get_current_workspace_package();
+        let packages = vec![package.clone()];
+
+        let module_paths =
+            package_id_to_module_paths(&packages, 2, &ScanMode::Full);
+        assert_eq!(
+            module_paths.get(&package.id),
+            Some(&package_module_paths(&package))
+        );
+
+        let module_paths = package_id_to_module_paths(
+            &packages,
+            2,
+            &ScanMode::EntryPointsOnly,
+        );
+        assert!(module_paths.is_empty());
+    }
+
+    #[rstest(
+        input_module_path,
+        input_item_path,
+        expected_item_path,
+        case("", "S::f", "S::f"),
+        case("scan::find", "S::f", "scan::find::S::f"),
+        case("scan::find", "", "scan::find")
+    )]
+    fn prefix_item_paths_test(
+        input_module_path: &str,
+        input_item_path: &str,
+        expected_item_path: &str,
+    ) {
+        let mut rs_file_metrics = RsFileMetrics {
+            findings: vec![UnsafeFinding {
+                file: PathBuf::from("src/scan/find.rs"),
+                start: LineColumn { line: 1, column: 0 },
+                end: LineColumn { line: 1, column: 8 },
+                kind: UnsafeKind::Block,
+                item_path: String::from(input_item_path),
+                documented: None,
+            }],
+            functions: vec![FunctionMetrics {
+                item_path: String::from(input_item_path),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        prefix_item_paths(&mut rs_file_metrics, input_module_path);
+
+        assert_eq!(rs_file_metrics.findings[0].item_path, expected_item_path);
+        assert_eq!(rs_file_metrics.functions[0].item_path, expected_item_path);
+    }
+
+    #[rstest(input_jobs, case(1), case(4), case(64))]
+    fn scan_in_parallel_test(input_jobs: usize) {
+        let items = (0..20).collect::<Vec<u64>>();
+        let mut progress_steps = vec![];
+
+        let results = scan_in_parallel(
+            &items,
+            input_jobs,
+            |item| item * 2,
+            |completed, count| {
+                progress_steps.push((completed, count));
+                Ok(())
+            },
+        );
+
+        assert_eq!(results, (0..40).step_by(2).collect::<Vec<u64>>());
+        assert_eq!(
+            progress_steps,
+            (1..=20).map(|i| (i, 20)).collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    fn scan_jobs_test() {
+        assert_eq!(scan_jobs(Some(3)), 3);
+        assert_eq!(scan_jobs(Some(0)), 1);
+        assert!(scan_jobs(None) >= 1);
+    }
+
     #[fixture]
     fn get_current_workspace_package() -> cargo_metadata::Package {
         let metadata = MetadataCommand::new()
@@ -1,63 +1,75 @@
 mod table;
 
-use crate::format::print_config::{OutputFormat, PrintConfig};
+use crate::format::print_config::OutputFormat;
 use crate::graph::Graph;
 use crate::mapping::CargoMetadataParameters;
 
 use super::find::find_unsafe;
-use super::{package_metrics, ScanMode, ScanParameters, ScanResult};
+use super::lints::package_lint_levels;
+use super::{
+    check_policy, package_metrics, report_roots, ScanMode, ScanParameters,
+    ScanResult,
+};
 
 use table::scan_forbid_to_table;
 
-use cargo::{CliError, Config};
+use cargo::CliError;
 use cargo_geiger_serde::{QuickReportEntry, QuickSafetyReport};
 use cargo_metadata::PackageId;
 
 pub fn scan_forbid_unsafe(
     cargo_metadata_parameters: &CargoMetadataParameters,
     graph: &Graph,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
     scan_parameters: &ScanParameters,
 ) -> Result<ScanResult, CliError> {
     match scan_parameters.args.output_format {
         OutputFormat::Json => scan_forbid_to_report(
             cargo_metadata_parameters,
-            scan_parameters.config,
             graph,
-            scan_parameters.args.output_format,
-            scan_parameters.print_config,
-            root_package_id,
+            root_package_ids,
+            scan_parameters,
         ),
         _ => scan_forbid_to_table(
             cargo_metadata_parameters,
-            scan_parameters.config,
             graph,
-            scan_parameters.print_config,
-            root_package_id,
+            root_package_ids,
+            scan_parameters,
         ),
     }
 }
 
 fn scan_forbid_to_report(
     cargo_metadata_parameters: &CargoMetadataParameters,
-    config: &Config,
     graph: &Graph,
-    output_format: OutputFormat,
-    print_config: &PrintConfig,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
 ) -> Result<ScanResult, CliError> {
     let geiger_context = find_unsafe(
         cargo_metadata_parameters,
-        config,
         ScanMode::EntryPointsOnly,
-        print_config,
+        scan_parameters,
     )?;
-    let mut report = QuickSafetyReport::default();
-    for (package, package_metrics) in package_metrics(
+    let policy_violations = match scan_parameters.policy {
+        Some(policy) => check_policy(
+            cargo_metadata_parameters,
+            &geiger_context,
+            graph,
+            policy,
+            root_package_ids,
+            None,
+        ),
+        None => vec![],
+    };
+    let mut report = QuickSafetyReport {
+        roots: report_roots(cargo_metadata_parameters, root_package_ids),
+        ..Default::default()
+    };
+    for (_, package, package_metrics) in package_metrics(
         cargo_metadata_parameters,
         &geiger_context,
         graph,
-        root_package_id,
+        root_package_ids,
     ) {
         let pack_metrics = match package_metrics {
             Some(m) => m,
@@ -71,13 +83,18 @@ fn scan_forbid_to_report(
                 rs_file_metrics_wrapper.metrics.forbids_unsafe
             },
         );
+        let (unsafe_code_lint, unsafe_op_in_unsafe_fn_lint) =
+            package_lint_levels(&pack_metrics);
         let entry = QuickReportEntry {
             package,
             forbids_unsafe,
+            unsafe_code_lint,
+            unsafe_op_in_unsafe_fn_lint,
         };
         report.packages.insert(entry.package.id.clone(), entry);
     }
-    let json_string = match output_format {
+    report.files_with_errors = geiger_context.files_with_errors;
+    let json_string = match scan_parameters.args.output_format {
         OutputFormat::Json => serde_json::to_string(&report).unwrap(),
         _ => panic!("Only implemented for OutputFormat::Json"),
     };
@@ -85,5 +102,8 @@ fn scan_forbid_to_report(
     Ok(ScanResult {
         scan_output_lines: vec![json_string],
         warning_count: 0,
+        policy_violations,
+        undocumented_unsafe: vec![],
+        files_with_errors: report.files_with_errors,
     })
 }
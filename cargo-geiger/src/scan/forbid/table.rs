@@ -9,70 +9,100 @@ use crate::tree::traversal::walk_dependency_tree;
 use crate::tree::TextTreeLine;
 
 use super::super::find::find_unsafe;
-use super::super::{ScanMode, ScanResult};
-
-use cargo::{CliError, Config};
+use super::super::lints::package_lint_levels;
+use super::super::{
+    check_policy, construct_files_with_errors_lines, ScanMode, ScanParameters,
+    ScanResult,
+};
+
+use cargo::CliError;
+use cargo_geiger_serde::LintLevel;
 use cargo_metadata::PackageId;
 use colored::Colorize;
 
 pub fn scan_forbid_to_table(
     cargo_metadata_parameters: &CargoMetadataParameters,
-    config: &Config,
     graph: &Graph,
-    print_config: &PrintConfig,
-    root_package_id: PackageId,
+    root_package_ids: &[PackageId],
+    scan_parameters: &ScanParameters,
 ) -> Result<ScanResult, CliError> {
+    let print_config = scan_parameters.print_config;
     let mut scan_output_lines = Vec::<String>::new();
     let emoji_symbols = EmojiSymbols::new(print_config.output_format);
 
     let mut output_key_lines = construct_key_lines(&emoji_symbols);
     scan_output_lines.append(&mut output_key_lines);
 
-    let tree_lines = walk_dependency_tree(
+    let geiger_ctx = find_unsafe(
         cargo_metadata_parameters,
-        graph,
-        print_config,
-        root_package_id,
-    );
+        ScanMode::EntryPointsOnly,
+        scan_parameters,
+    )?;
 
-    for tree_line in tree_lines {
-        match tree_line {
-            TextTreeLine::ExtraDepsGroup { kind, tree_vines } => {
-                let name = get_kind_group_name(kind);
-                if name.is_none() {
-                    continue;
+    for (tree_index, root_package_id) in root_package_ids.iter().enumerate() {
+        if tree_index > 0 {
+            scan_output_lines.push(String::new());
+        }
+        let tree_lines = walk_dependency_tree(
+            cargo_metadata_parameters,
+            graph,
+            print_config,
+            root_package_id.clone(),
+        );
+        for tree_line in tree_lines {
+            match tree_line {
+                TextTreeLine::ExtraDepsGroup { kind, tree_vines } => {
+                    let name = get_kind_group_name(kind);
+                    if name.is_none() {
+                        continue;
+                    }
+                    let name = name.unwrap();
+                    // TODO: Fix the alignment on macOS (others too?)
+                    scan_output_lines.push(format!("  {}{}", tree_vines, name));
                 }
-                let name = name.unwrap();
-                // TODO: Fix the alignment on macOS (others too?)
-                scan_output_lines.push(format!("  {}{}", tree_vines, name));
-            }
-            TextTreeLine::Package {
-                id: package_id,
-                tree_vines,
-            } => {
-                let geiger_ctx = find_unsafe(
-                    cargo_metadata_parameters,
-                    config,
-                    ScanMode::EntryPointsOnly,
-                    print_config,
-                )?;
-
-                handle_package_text_tree_line(
-                    cargo_metadata_parameters,
-                    &emoji_symbols,
-                    &geiger_ctx,
-                    package_id,
-                    print_config,
-                    &mut scan_output_lines,
+                TextTreeLine::Package {
+                    id: package_id,
                     tree_vines,
-                );
+                } => {
+                    handle_package_text_tree_line(
+                        cargo_metadata_parameters,
+                        &emoji_symbols,
+                        &geiger_ctx,
+                        package_id,
+                        print_config,
+                        &mut scan_output_lines,
+                        tree_vines,
+                    );
+                }
             }
         }
     }
 
+    let policy_violations = match scan_parameters.policy {
+        Some(policy) => check_policy(
+            cargo_metadata_parameters,
+            &geiger_ctx,
+            graph,
+            policy,
+            root_package_ids,
+            None,
+        ),
+        None => vec![],
+    };
+
+    if !geiger_ctx.files_with_errors.is_empty() {
+        scan_output_lines.push(String::new());
+        scan_output_lines.append(&mut construct_files_with_errors_lines(
+            &geiger_ctx.files_with_errors,
+        ));
+    }
+
     Ok(ScanResult {
         scan_output_lines,
         warning_count: 0,
+        policy_violations,
+        undocumented_unsafe: vec![],
+        files_with_errors: geiger_ctx.files_with_errors,
     })
 }
 
@@ -80,10 +110,12 @@ fn construct_key_lines(emoji_symbols: &EmojiSymbols) -> Vec<String> {
     let mut output_key_lines = vec![String::new(), String::from("Symbols: ")];
 
     let forbids = "All entry point .rs files declare #![forbid(unsafe_code)].";
+    let denies = "All entry point .rs files declare #![deny(unsafe_code)].";
     let unknown = "This crate may use unsafe code.";
 
     let symbol_kinds_to_string_values = vec![
         (SymbolKind::Lock, forbids),
+        (SymbolKind::LockWithKey, denies),
         (SymbolKind::QuestionMark, unknown),
     ];
 
@@ -117,6 +149,7 @@ fn handle_package_text_tree_line(
     tree_vines: String,
 ) {
     let sym_lock = emoji_symbols.emoji(SymbolKind::Lock);
+    let sym_lock_with_key = emoji_symbols.emoji(SymbolKind::LockWithKey);
     let sym_qmark = emoji_symbols.emoji(SymbolKind::QuestionMark);
 
     let name = format_package_name(
@@ -125,18 +158,14 @@ fn handle_package_text_tree_line(
         &print_config.format,
     );
     let package_metrics = geiger_ctx.package_id_to_metrics.get(&package_id);
-    let package_forbids_unsafe = match package_metrics {
-        None => false, // no metrics available, .rs parsing failed?
-        Some(package_metric) => package_metric.rs_path_to_metrics.iter().all(
-            |(_k, rs_file_metrics_wrapper)| {
-                rs_file_metrics_wrapper.metrics.forbids_unsafe
-            },
-        ),
+    let unsafe_code_lint = match package_metrics {
+        None => LintLevel::Allow, // no metrics available, .rs parsing failed?
+        Some(package_metric) => package_lint_levels(package_metric).0,
     };
-    let (symbol, name) = if package_forbids_unsafe {
-        (&sym_lock, name.green())
-    } else {
-        (&sym_qmark, name.red())
+    let (symbol, name) = match unsafe_code_lint {
+        LintLevel::Forbid => (&sym_lock, name.green()),
+        LintLevel::Deny => (&sym_lock_with_key, name.green()),
+        LintLevel::Allow | LintLevel::Warn => (&sym_qmark, name.red()),
     };
     scan_output_lines.push(format!("{} {}{}", symbol, tree_vines, name));
 }
@@ -152,6 +181,6 @@ mod forbid_tests {
         let emoji_symbols = EmojiSymbols::new(OutputFormat::Utf8);
         let output_key_lines = construct_key_lines(&emoji_symbols);
 
-        assert_eq!(output_key_lines.len(), 5);
+        assert_eq!(output_key_lines.len(), 6);
     }
 }
@@ -0,0 +1,268 @@
+use super::PackageMetrics;
+
+use cargo_geiger_serde::LintLevel;
+use geiger::RsFileMetrics;
+use std::fs;
+use std::path::Path;
+use toml::Value;
+
+/// Lint levels declared in the `[lints.rust]` table of a package manifest,
+/// or inherited from `[workspace.lints.rust]` with `[lints] workspace = true`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ManifestLints {
+    pub unsafe_code: Option<LintLevel>,
+    pub unsafe_op_in_unsafe_fn: Option<LintLevel>,
+}
+
+impl ManifestLints {
+    /// Reads the lints of the package with the given manifest, missing or
+    /// malformed manifests declare no lints.
+    pub fn read(manifest_path: &Path, workspace_root: &Path) -> Self {
+        let manifest = match read_toml(manifest_path) {
+            Some(manifest) => manifest,
+            None => return ManifestLints::default(),
+        };
+        let lints = manifest.get("lints");
+        let inherits_workspace_lints = lints
+            .and_then(|lints| lints.get("workspace"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let rust_lints = match inherits_workspace_lints {
+            true => read_toml(&workspace_root.join("Cargo.toml")).and_then(
+                |workspace_manifest| {
+                    workspace_manifest
+                        .get("workspace")
+                        .and_then(|workspace| workspace.get("lints"))
+                        .and_then(|lints| lints.get("rust"))
+                        .cloned()
+                },
+            ),
+            false => lints.and_then(|lints| lints.get("rust")).cloned(),
+        };
+        match rust_lints {
+            Some(rust_lints) => ManifestLints::from_rust_lints(&rust_lints),
+            None => ManifestLints::default(),
+        }
+    }
+
+    fn from_rust_lints(rust_lints: &Value) -> Self {
+        ManifestLints {
+            unsafe_code: lint_level(rust_lints.get("unsafe_code")),
+            unsafe_op_in_unsafe_fn: lint_level(
+                rust_lints.get("unsafe_op_in_unsafe_fn"),
+            ),
+        }
+    }
+
+    /// Combines the lints of the manifest with the ones declared by the
+    /// attributes of a crate entry point. Attributes override the manifest,
+    /// unless the manifest forbids the lint.
+    pub fn apply(&self, rs_file_metrics: &mut RsFileMetrics) {
+        rs_file_metrics.unsafe_code_lint =
+            combine(self.unsafe_code, rs_file_metrics.unsafe_code_lint);
+        rs_file_metrics.unsafe_op_in_unsafe_fn_lint = combine(
+            self.unsafe_op_in_unsafe_fn,
+            rs_file_metrics.unsafe_op_in_unsafe_fn_lint,
+        );
+        rs_file_metrics.forbids_unsafe =
+            rs_file_metrics.unsafe_code_lint == Some(LintLevel::Forbid);
+    }
+}
+
+/// The `unsafe_code` and `unsafe_op_in_unsafe_fn` lint levels of a package,
+/// the least strict level declared by any of its crate entry points.
+pub fn package_lint_levels(
+    package_metrics: &PackageMetrics,
+) -> (LintLevel, LintLevel) {
+    package_metrics
+        .rs_path_to_metrics
+        .values()
+        .filter(|wrapper| wrapper.is_crate_entry_point)
+        .fold(
+            (LintLevel::Forbid, LintLevel::Forbid),
+            |(unsafe_code, unsafe_op_in_unsafe_fn), wrapper| {
+                (
+                    unsafe_code.min(
+                        wrapper.metrics.unsafe_code_lint.unwrap_or_default(),
+                    ),
+                    unsafe_op_in_unsafe_fn.min(
+                        wrapper
+                            .metrics
+                            .unsafe_op_in_unsafe_fn_lint
+                            .unwrap_or_default(),
+                    ),
+                )
+            },
+        )
+}
+
+fn combine(
+    manifest: Option<LintLevel>,
+    attributes: Option<LintLevel>,
+) -> Option<LintLevel> {
+    match (manifest, attributes) {
+        (Some(LintLevel::Forbid), _) => Some(LintLevel::Forbid),
+        (manifest, None) => manifest,
+        (_, attributes) => attributes,
+    }
+}
+
+/// The level of a lint, written either as `lint = "forbid"` or as
+/// `lint = { level = "forbid", priority = -1 }`.
+fn lint_level(value: Option<&Value>) -> Option<LintLevel> {
+    let level = match value? {
+        Value::String(level) => level.as_str(),
+        Value::Table(table) => table.get("level")?.as_str()?,
+        _ => return None,
+    };
+    match level {
+        "allow" => Some(LintLevel::Allow),
+        "warn" => Some(LintLevel::Warn),
+        "deny" => Some(LintLevel::Deny),
+        "forbid" => Some(LintLevel::Forbid),
+        _ => None,
+    }
+}
+
+fn read_toml(path: &Path) -> Option<Value> {
+    let content = fs::read_to_string(path).ok()?;
+    toml::from_str(&content).ok()
+}
+
+#[cfg(test)]
+mod lints_tests {
+    use super::*;
+
+    use crate::scan::RsFileMetricsWrapper;
+
+    use rstest::*;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[rstest(
+        input_manifest,
+        input_workspace_manifest,
+        expected_manifest_lints,
+        case(
+            "[package]\nname = \"p\"",
+            "",
+            ManifestLints::default()
+        ),
+        case(
+            "[lints.rust]\nunsafe_code = \"forbid\"",
+            "",
+            ManifestLints {
+                unsafe_code: Some(LintLevel::Forbid),
+                unsafe_op_in_unsafe_fn: None,
+            }
+        ),
+        case(
+            "[lints.rust]\nunsafe_op_in_unsafe_fn = { level = \"deny\", priority = -1 }",
+            "",
+            ManifestLints {
+                unsafe_code: None,
+                unsafe_op_in_unsafe_fn: Some(LintLevel::Deny),
+            }
+        ),
+        case(
+            "[lints]\nworkspace = true",
+            "[workspace.lints.rust]\nunsafe_code = \"deny\"",
+            ManifestLints {
+                unsafe_code: Some(LintLevel::Deny),
+                unsafe_op_in_unsafe_fn: None,
+            }
+        )
+    )]
+    fn manifest_lints_read_test(
+        input_manifest: &str,
+        input_workspace_manifest: &str,
+        expected_manifest_lints: ManifestLints,
+    ) {
+        let temp_dir = tempdir().unwrap();
+        let package_dir = temp_dir.path().join("package");
+        fs::create_dir(&package_dir).unwrap();
+        fs::write(package_dir.join("Cargo.toml"), input_manifest).unwrap();
+        fs::write(temp_dir.path().join("Cargo.toml"), input_workspace_manifest)
+            .unwrap();
+
+        let manifest_lints = ManifestLints::read(
+            &package_dir.join("Cargo.toml"),
+            temp_dir.path(),
+        );
+
+        assert_eq!(manifest_lints, expected_manifest_lints);
+    }
+
+    #[rstest(
+        input_manifest_lint,
+        input_attribute_lint,
+        expected_lint,
+        case(None, None, None),
+        case(Some(LintLevel::Deny), None, Some(LintLevel::Deny)),
+        case(
+            Some(LintLevel::Deny),
+            Some(LintLevel::Allow),
+            Some(LintLevel::Allow)
+        ),
+        case(
+            Some(LintLevel::Forbid),
+            Some(LintLevel::Allow),
+            Some(LintLevel::Forbid)
+        )
+    )]
+    fn manifest_lints_apply_test(
+        input_manifest_lint: Option<LintLevel>,
+        input_attribute_lint: Option<LintLevel>,
+        expected_lint: Option<LintLevel>,
+    ) {
+        let manifest_lints = ManifestLints {
+            unsafe_code: input_manifest_lint,
+            unsafe_op_in_unsafe_fn: None,
+        };
+        let mut rs_file_metrics = RsFileMetrics {
+            unsafe_code_lint: input_attribute_lint,
+            ..Default::default()
+        };
+
+        manifest_lints.apply(&mut rs_file_metrics);
+
+        assert_eq!(rs_file_metrics.unsafe_code_lint, expected_lint);
+        assert_eq!(
+            rs_file_metrics.forbids_unsafe,
+            expected_lint == Some(LintLevel::Forbid)
+        );
+    }
+
+    #[rstest]
+    fn package_lint_levels_test() {
+        let wrapper =
+            |is_crate_entry_point, unsafe_code_lint| RsFileMetricsWrapper {
+                metrics: RsFileMetrics {
+                    unsafe_code_lint,
+                    ..Default::default()
+                },
+                is_crate_entry_point,
+                module_path: None,
+            };
+        let package_metrics = PackageMetrics {
+            rs_path_to_metrics: vec![
+                (
+                    PathBuf::from("src/lib.rs"),
+                    wrapper(true, Some(LintLevel::Forbid)),
+                ),
+                (
+                    PathBuf::from("src/main.rs"),
+                    wrapper(true, Some(LintLevel::Deny)),
+                ),
+                (PathBuf::from("src/other.rs"), wrapper(false, None)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        assert_eq!(
+            package_lint_levels(&package_metrics),
+            (LintLevel::Deny, LintLevel::Allow)
+        );
+    }
+}
@@ -0,0 +1,568 @@
+//! A policy declares how much unsafe code each crate in the dependency tree
+//! is allowed to use, so that `cargo geiger` can be used to gate CI builds.
+//!
+//! Policies are read from a `geiger.toml` file or from the
+//! `[package.metadata.geiger]` table of the root package:
+//!
+//! ```toml
+//! [allow]
+//! libc = "any"
+//! "*-sys" = "allow"
+//! my-crate = { exprs = 12, functions = 1 }
+//! ```
+//!
+//! Crates that are not matched by any entry are not allowed to use any unsafe
+//! code. Use `"*" = "any"` to only restrict the listed crates.
+
+use cargo::util::errors::CliError;
+use cargo_geiger_serde::{Count, PackageId, UnsafeInfo};
+use cargo_metadata::Metadata;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The policy file looked up next to the manifest of the root package when
+/// no policy path is provided on the command line.
+pub const POLICY_FILE_NAME: &str = "geiger.toml";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Policy {
+    /// Maps crate names, or patterns containing `*` wildcards, to the unsafe
+    /// code they are allowed to use.
+    #[serde(default)]
+    pub allow: BTreeMap<String, Allowance>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Allowance {
+    Level(AllowanceLevel),
+    Budget(UnsafeBudget),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AllowanceLevel {
+    /// Any amount of unsafe code is allowed.
+    #[serde(alias = "allow")]
+    Any,
+    /// No unsafe code is allowed, the default for crates missing from the
+    /// policy.
+    Deny,
+}
+
+/// Upper bounds for the unsafe counters used by the build, counters without
+/// a bound are not restricted. The informational counters of a
+/// `CounterBlock`, e.g. `statics` or `transmutes`, can not be bounded.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UnsafeBudget {
+    pub functions: Option<u64>,
+    pub exprs: Option<u64>,
+    pub item_impls: Option<u64>,
+    pub item_traits: Option<u64>,
+    pub methods: Option<u64>,
+    pub macros: Option<u64>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PolicyViolation {
+    pub package_id: PackageId,
+    pub kind: PolicyViolationKind,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PolicyViolationKind {
+    /// Found in `--forbid-only` mode, where unsafe usage isn't counted.
+    DoesNotForbidUnsafe,
+    ExceedsBudget {
+        counter: &'static str,
+        used: u64,
+        allowed: u64,
+    },
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: ", self.package_id.name, self.package_id.version)?;
+        match &self.kind {
+            PolicyViolationKind::DoesNotForbidUnsafe => write!(
+                f,
+                "does not forbid unsafe code, but the policy allows none"
+            ),
+            PolicyViolationKind::ExceedsBudget {
+                counter,
+                used,
+                allowed,
+            } => write!(
+                f,
+                "{} unsafe {} used by the build, but the policy allows {}",
+                used, counter, allowed
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PolicyError {
+    pub path: PathBuf,
+    /// The `[allow]` entry that could not be read, if the error is limited to
+    /// a single entry.
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+impl Error for PolicyError {}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid policy in {}", self.path.display())?;
+        if let Some(entry) = &self.entry {
+            write!(f, ", entry `{}`", entry)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct PolicyViolationsError {
+    pub violation_count: u64,
+}
+
+impl Error for PolicyViolationsError {}
+
+impl fmt::Display for PolicyViolationsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Found {} policy violations", self.violation_count)
+    }
+}
+
+impl Policy {
+    /// Loads the policy from `policy_path` if provided. Otherwise falls back
+    /// to a `geiger.toml` next to the root manifest, and then to the
+    /// `[package.metadata.geiger]` table of the root package.
+    pub fn load(
+        policy_path: Option<&Path>,
+        metadata: &Metadata,
+    ) -> Result<Option<Policy>, CliError> {
+        if let Some(path) = policy_path {
+            return Policy::from_file(path).map(Some);
+        }
+
+        let root_package = match metadata.root_package() {
+            Some(package) => package,
+            None => return Ok(None),
+        };
+
+        let default_path = root_package
+            .manifest_path
+            .parent()
+            .map(|dir| PathBuf::from(dir.join(POLICY_FILE_NAME)));
+        if let Some(path) = default_path.filter(|p| p.exists()) {
+            return Policy::from_file(&path).map(Some);
+        }
+
+        match root_package.metadata.get("geiger") {
+            Some(value) => Policy::from_value(
+                value.clone(),
+                root_package.manifest_path.as_ref(),
+            )
+            .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Policy, CliError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| policy_error(path, None, e.to_string()))?;
+        let value = toml::from_str::<serde_json::Value>(&content)
+            .map_err(|e| policy_error(path, None, e.to_string()))?;
+        Policy::from_value(value, path)
+    }
+
+    /// Reads the `[allow]` entries one by one, so that an invalid entry is
+    /// named in the error. Deserializing an `Allowance` directly would only
+    /// report that none of its variants matched.
+    fn from_value(
+        value: serde_json::Value,
+        path: &Path,
+    ) -> Result<Policy, CliError> {
+        #[derive(Deserialize)]
+        struct RawPolicy {
+            #[serde(default)]
+            allow: BTreeMap<String, serde_json::Value>,
+        }
+
+        let raw_policy = serde_json::from_value::<RawPolicy>(value)
+            .map_err(|e| policy_error(path, None, e.to_string()))?;
+        let allow = raw_policy
+            .allow
+            .into_iter()
+            .map(|(entry, value)| {
+                let allowance = match value {
+                    serde_json::Value::Object(_) => {
+                        serde_json::from_value(value).map(Allowance::Budget)
+                    }
+                    _ => serde_json::from_value(value).map(Allowance::Level),
+                };
+                match allowance {
+                    Ok(allowance) => Ok((entry, allowance)),
+                    Err(e) => {
+                        Err(policy_error(path, Some(entry), e.to_string()))
+                    }
+                }
+            })
+            .collect::<Result<BTreeMap<String, Allowance>, CliError>>()?;
+        Ok(Policy { allow })
+    }
+
+    /// Returns the allowance of the crate called `name`. An exact match takes
+    /// precedence over patterns, and the most specific pattern wins.
+    pub fn allowance(&self, name: &str) -> Allowance {
+        if let Some(allowance) = self.allow.get(name) {
+            return *allowance;
+        }
+
+        self.allow
+            .iter()
+            .filter(|(pattern, _)| {
+                pattern.contains('*') && matches_pattern(pattern, name)
+            })
+            .max_by_key(|(pattern, _)| pattern.replace('*', "").len())
+            .map(|(_, allowance)| *allowance)
+            .unwrap_or(Allowance::Level(AllowanceLevel::Deny))
+    }
+
+    /// Compares the unsafe usage of a package found by a full scan against
+    /// its allowance.
+    pub fn check_unsafe_info(
+        &self,
+        package_id: &PackageId,
+        unsafe_info: &UnsafeInfo,
+    ) -> Vec<PolicyViolation> {
+        let budget = match self.allowance(&package_id.name) {
+            Allowance::Level(AllowanceLevel::Any) => return vec![],
+            Allowance::Level(AllowanceLevel::Deny) => UnsafeBudget {
+                functions: Some(0),
+                exprs: Some(0),
+                item_impls: Some(0),
+                item_traits: Some(0),
+                methods: Some(0),
+                macros: Some(0),
+            },
+            Allowance::Budget(budget) => budget,
+        };
+
+        let used = &unsafe_info.used;
+        let counters: [(&'static str, &Count, Option<u64>); 6] = [
+            ("functions", &used.functions, budget.functions),
+            ("exprs", &used.exprs, budget.exprs),
+            ("item_impls", &used.item_impls, budget.item_impls),
+            ("item_traits", &used.item_traits, budget.item_traits),
+            ("methods", &used.methods, budget.methods),
+            ("macros", &used.macros, budget.macros),
+        ];
+
+        counters
+            .iter()
+            .filter_map(|(counter, count, allowed)| match allowed {
+                Some(allowed) if count.unsafe_ > *allowed => {
+                    Some(PolicyViolation {
+                        package_id: package_id.clone(),
+                        kind: PolicyViolationKind::ExceedsBudget {
+                            counter,
+                            used: count.unsafe_,
+                            allowed: *allowed,
+                        },
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Used by `--forbid-only`, where only crates denied any unsafe code can
+    /// be checked.
+    pub fn check_forbids_unsafe(
+        &self,
+        package_id: &PackageId,
+        forbids_unsafe: bool,
+    ) -> Option<PolicyViolation> {
+        match self.allowance(&package_id.name) {
+            Allowance::Level(AllowanceLevel::Deny) if !forbids_unsafe => {
+                Some(PolicyViolation {
+                    package_id: package_id.clone(),
+                    kind: PolicyViolationKind::DoesNotForbidUnsafe,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one item.
+    let first = parts.next().unwrap();
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let mut parts = parts.collect::<Vec<&str>>();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // No wildcard in the pattern.
+        None => return rest.is_empty(),
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+fn policy_error(
+    path: &Path,
+    entry: Option<String>,
+    message: String,
+) -> CliError {
+    CliError::new(
+        (PolicyError {
+            path: path.to_path_buf(),
+            entry,
+            message,
+        })
+        .into(),
+        1,
+    )
+}
+
+#[cfg(test)]
+mod policy_tests {
+    use super::*;
+
+    use cargo_geiger_serde::{CounterBlock, Source};
+    use rstest::*;
+    use semver::Version;
+    use url::Url;
+
+    const POLICY: &str = r#"
+        [allow]
+        libc = "any"
+        "*-sys" = "allow"
+        "openssl-*" = "deny"
+        my-crate = { exprs = 12, functions = 1 }
+    "#;
+
+    #[rstest(
+        input_pattern,
+        input_name,
+        expected_match,
+        case("*", "libc", true),
+        case("*-sys", "openssl-sys", true),
+        case("*-sys", "openssl-sys-extras", false),
+        case("openssl-*", "openssl-sys", true),
+        case("open*-s*s", "openssl-sys", true),
+        case("open*-s*s", "openssl-src", false),
+        case("libc", "libc", true),
+        case("libc", "libcc", false)
+    )]
+    fn matches_pattern_test(
+        input_pattern: &str,
+        input_name: &str,
+        expected_match: bool,
+    ) {
+        assert_eq!(matches_pattern(input_pattern, input_name), expected_match);
+    }
+
+    #[rstest(
+        input_name,
+        expected_allowance,
+        case("libc", Allowance::Level(AllowanceLevel::Any)),
+        case("libz-sys", Allowance::Level(AllowanceLevel::Any)),
+        case("openssl-sys", Allowance::Level(AllowanceLevel::Deny)),
+        case(
+            "my-crate",
+            Allowance::Budget(UnsafeBudget {
+                exprs: Some(12),
+                functions: Some(1),
+                ..Default::default()
+            })
+        ),
+        case("unlisted", Allowance::Level(AllowanceLevel::Deny))
+    )]
+    fn allowance_test(input_name: &str, expected_allowance: Allowance) {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+        assert_eq!(policy.allowance(input_name), expected_allowance);
+    }
+
+    #[rstest]
+    fn policy_with_unknown_budget_counter_is_rejected() {
+        let result = toml::from_str::<Policy>(
+            r#"
+            [allow]
+            my-crate = { unsafe_exprs = 12 }
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[rstest(
+        input_policy,
+        expected_error,
+        case(
+            r#"
+            [allow]
+            libc = "any"
+            my-crate = { unsafe_exprs = 12 }
+            "#,
+            "Invalid policy in geiger.toml, entry `my-crate`: unknown field \
+             `unsafe_exprs`, expected one of `functions`, `exprs`, \
+             `item_impls`, `item_traits`, `methods`, `macros`"
+        ),
+        case(
+            r#"
+            [allow]
+            libc = "maybe"
+            "#,
+            "Invalid policy in geiger.toml, entry `libc`: unknown variant \
+             `maybe`, expected `any` or `deny`"
+        ),
+        case(
+            r#"
+            allow = 1
+            "#,
+            "Invalid policy in geiger.toml: invalid type: integer `1`, \
+             expected a map"
+        )
+    )]
+    fn policy_error_display_test(input_policy: &str, expected_error: &str) {
+        let error = Policy::from_value(
+            toml::from_str(input_policy).unwrap(),
+            Path::new("geiger.toml"),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.error.unwrap().to_string(), expected_error);
+    }
+
+    #[rstest]
+    fn policy_from_value_test() {
+        let policy =
+            Policy::from_value(toml::from_str(POLICY).unwrap(), Path::new(""))
+                .unwrap();
+
+        assert_eq!(policy, toml::from_str::<Policy>(POLICY).unwrap());
+    }
+
+    #[rstest(
+        input_name,
+        input_used_exprs,
+        expected_violations,
+        case("libc", 100, vec![]),
+        case("my-crate", 12, vec![]),
+        case(
+            "my-crate",
+            13,
+            vec![PolicyViolationKind::ExceedsBudget {
+                counter: "exprs",
+                used: 13,
+                allowed: 12
+            }]
+        ),
+        case("unlisted", 0, vec![]),
+        case(
+            "unlisted",
+            1,
+            vec![PolicyViolationKind::ExceedsBudget {
+                counter: "exprs",
+                used: 1,
+                allowed: 0
+            }]
+        )
+    )]
+    fn check_unsafe_info_test(
+        input_name: &str,
+        input_used_exprs: u64,
+        expected_violations: Vec<PolicyViolationKind>,
+    ) {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+        let package_id = create_package_id(input_name);
+        let unsafe_info = UnsafeInfo {
+            used: CounterBlock {
+                exprs: Count {
+                    safe: 0,
+                    unsafe_: input_used_exprs,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let violations = policy
+            .check_unsafe_info(&package_id, &unsafe_info)
+            .into_iter()
+            .map(|v| v.kind)
+            .collect::<Vec<PolicyViolationKind>>();
+
+        assert_eq!(violations, expected_violations);
+    }
+
+    #[rstest(
+        input_name,
+        input_forbids_unsafe,
+        expected_violation,
+        case("unlisted", true, false),
+        case("unlisted", false, true),
+        case("my-crate", false, false),
+        case("libc", false, false)
+    )]
+    fn check_forbids_unsafe_test(
+        input_name: &str,
+        input_forbids_unsafe: bool,
+        expected_violation: bool,
+    ) {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+        let package_id = create_package_id(input_name);
+
+        assert_eq!(
+            policy
+                .check_forbids_unsafe(&package_id, input_forbids_unsafe)
+                .is_some(),
+            expected_violation
+        );
+    }
+
+    #[rstest]
+    fn policy_violation_display_test() {
+        let violation = PolicyViolation {
+            package_id: create_package_id("my-crate"),
+            kind: PolicyViolationKind::ExceedsBudget {
+                counter: "exprs",
+                used: 13,
+                allowed: 12,
+            },
+        };
+
+        assert_eq!(
+            violation.to_string(),
+            "my-crate 1.0.0: 13 unsafe exprs used by the build, but the \
+             policy allows 12"
+        );
+    }
+
+    fn create_package_id(name: &str) -> PackageId {
+        PackageId {
+            name: String::from(name),
+            version: Version::new(1, 0, 0),
+            source: Source::Path(Url::parse("file:///crate").unwrap()),
+        }
+    }
+}
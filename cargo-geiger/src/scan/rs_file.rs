@@ -1,23 +1,28 @@
 mod custom_executor;
 
+use crate::args::BuildTargetArgs;
+
 use custom_executor::{CustomExecutor, CustomExecutorInnerContext};
 
-use cargo::core::compiler::Executor;
+use cargo::core::compiler::{Context, Executor, Lto, UnitInterner};
 use cargo::core::manifest::TargetKind;
-use cargo::core::Workspace;
+use cargo::core::profiles;
+use cargo::core::{PackageId, Target, Workspace};
 use cargo::ops;
-use cargo::ops::{CleanOptions, CompileOptions};
-use cargo::util::{interning::InternedString, CargoResult};
+use cargo::ops::CompileOptions;
+use cargo::util::CargoResult;
 use cargo::Config;
+use cargo_metadata::{Metadata, PackageId as CargoMetadataPackageId};
 use cargo_util::paths;
+use geiger::module_tree::resolve_module_tree;
 use geiger::RsFileMetrics;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, PoisonError};
-use walkdir::{DirEntry, WalkDir};
+use walkdir::DirEntry;
 
 /// Provides information needed to scan for crate root
 /// `#![forbid(unsafe_code)]`.
@@ -47,6 +52,11 @@ pub struct RsFileMetricsWrapper {
     /// and cannot know if a file is a crate entry point or not, so we add this
     /// information here.
     pub is_crate_entry_point: bool,
+
+    /// The module path of the file, e.g. `foo::bar`, when it is reachable
+    /// from a build target of the package through `mod` declarations,
+    /// `#[path]` attributes or `include!`. `None` for unreachable files.
+    pub module_path: Option<String>,
 }
 
 #[derive(Debug)]
@@ -67,8 +77,6 @@ pub enum RsResolveError {
 
     /// Like io::Error but with the related path.
     Io(io::Error, PathBuf),
-
-    Walkdir(walkdir::Error),
 }
 
 impl Error for RsResolveError {}
@@ -145,34 +153,74 @@ pub fn is_file_with_ext(entry: &DirEntry, file_ext: &str) -> bool {
     ext.to_string_lossy() == file_ext
 }
 
-/// Trigger a `cargo clean` + `cargo check` and listen to the cargo/rustc
-/// communication to figure out which source files were used by the build.
+/// Name of the directory inside the target directory of the workspace that
+/// cargo-geiger builds into, keeping the build cache of the user intact.
+pub const GEIGER_TARGET_DIR_NAME: &str = "geiger";
+
+/// A unit of work of the build, a single target of a package. Used files
+/// are recorded per unit, so that a file is only counted as used for the
+/// package that actually compiled it, even when the source directories of
+/// two packages overlap.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BuildUnit {
+    pub package_id: CargoMetadataPackageId,
+    pub target_kind: TargetKind,
+    pub target_name: String,
+}
+
+/// The `.rs` files used by the build, keyed by the unit that used them. The
+/// wrapped `PathBufs` are canonicalized.
+#[derive(Debug, Default)]
+pub struct RsFilesUsed {
+    pub unit_to_rs_files: HashMap<BuildUnit, HashSet<PathBuf>>,
+
+    /// The macro expanded source of each unit that was built, only present
+    /// when scanning with `--expand-macros`.
+    pub unit_to_expanded_source: HashMap<BuildUnit, String>,
+}
+
+impl RsFilesUsed {
+    /// The files used by any unit of the build.
+    pub fn all(&self) -> HashSet<PathBuf> {
+        self.unit_to_rs_files.values().flatten().cloned().collect()
+    }
+
+    /// The files used by the targets of a single package, e.g. the library,
+    /// build script or proc-macro.
+    pub fn for_package(
+        &self,
+        package_id: &CargoMetadataPackageId,
+    ) -> HashSet<PathBuf> {
+        self.unit_to_rs_files
+            .iter()
+            .filter(|(build_unit, _)| build_unit.package_id == *package_id)
+            .flat_map(|(_, rs_files)| rs_files.iter().cloned())
+            .collect()
+    }
+}
+
+/// A unit of the build together with the location of its rustc dep-info
+/// file.
+struct UnitDepInfo {
+    package_id: PackageId,
+    manifest_path: PathBuf,
+    target: Target,
+    dep_info_path: PathBuf,
+}
+
+/// Trigger a `cargo check` in an isolated target directory and collect the
+/// source files used by each unit of the build from the rustc dep-info
+/// files. Units that are still fresh from a previous scan are not rebuilt,
+/// their dep-info files are reused. With `expand_macros` set, every unit is
+/// rebuilt to also capture its macro expanded source.
 pub fn resolve_rs_file_deps(
     compile_options: &CompileOptions,
+    expand_macros: bool,
+    metadata: &Metadata,
     workspace: &Workspace,
-) -> Result<HashSet<PathBuf>, RsResolveError> {
+) -> Result<RsFilesUsed, RsResolveError> {
     let config = workspace.config();
-    let (pkg_set, _) = ops::resolve_ws(workspace)
-        .map_err(|e| RsResolveError::Cargo(e.to_string()))?;
-    let packages = pkg_set
-        .package_ids()
-        .map(|package_id| package_id.name().as_str().to_owned())
-        .collect();
-    // Need to run a cargo clean to identify all new .d deps files.
-    // TODO: Figure out how this can be avoided to improve performance, clean
-    // Rust builds are __slow__.
-    let clean_options = CleanOptions {
-        config,
-        spec: packages,
-        targets: vec![],
-        profile_specified: false,
-        // A temporary hack to get cargo 0.43 to build, TODO: look closer at the updated cargo API
-        // later.
-        requested_profile: InternedString::new("dev"),
-        doc: false,
-    };
-
-    ops::clean(workspace, &clean_options)
+    let geiger_workspace = create_geiger_workspace(workspace)
         .map_err(|e| RsResolveError::Cargo(e.to_string()))?;
 
     let inner_arc = Arc::new(Mutex::new(CustomExecutorInnerContext::default()));
@@ -180,60 +228,258 @@ pub fn resolve_rs_file_deps(
         compile_with_exec(
             compile_options,
             config,
+            expand_macros,
             inner_arc.clone(),
-            workspace,
+            &geiger_workspace,
         )?;
     }
 
+    let unit_dep_infos = unit_dep_infos(compile_options, &geiger_workspace)
+        .map_err(|e| RsResolveError::Cargo(e.to_string()))?;
+
+    // Packages are matched by manifest path, since the cargo and
+    // cargo_metadata representations of a package id can differ.
+    let manifest_path_to_package_id = metadata
+        .packages
+        .iter()
+        .map(|p| (PathBuf::from(p.manifest_path.clone()), p.id.clone()))
+        .collect::<HashMap<PathBuf, CargoMetadataPackageId>>();
+    let package_id_to_metadata_package_id = unit_dep_infos
+        .iter()
+        .filter_map(|unit_dep_info| {
+            manifest_path_to_package_id
+                .get(&unit_dep_info.manifest_path)
+                .map(|id| (unit_dep_info.package_id, id.clone()))
+        })
+        .collect::<HashMap<PackageId, CargoMetadataPackageId>>();
+    let to_build_unit = |package_id: &PackageId, target: &Target| {
+        package_id_to_metadata_package_id.get(package_id).map(
+            |metadata_package_id| BuildUnit {
+                package_id: metadata_package_id.clone(),
+                target_kind: target.kind().clone(),
+                target_name: target.name().to_string(),
+            },
+        )
+    };
+
     let workspace_root = workspace.root().to_path_buf();
     let inner_mutex =
         Arc::try_unwrap(inner_arc).map_err(|_| RsResolveError::ArcUnwrap())?;
-    let (rs_files, out_dir_args) = {
-        let ctx = inner_mutex.into_inner()?;
-        (ctx.rs_file_args, ctx.out_dir_args)
-    };
-    let mut path_buf_hash_set = HashSet::<PathBuf>::new();
-    for out_dir in out_dir_args {
-        // TODO: Figure out if the `.d` dep files are used by one or more rustc
-        // calls. It could be useful to know which `.d` dep files belong to
-        // which rustc call. That would allow associating each `.rs` file found
-        // in each dep file with a PackageId.
-        add_dir_entries_to_path_buf_hash_set(
-            out_dir,
-            &mut path_buf_hash_set,
-            workspace_root.clone(),
+    let CustomExecutorInnerContext {
+        unit_to_rs_file_args,
+        unit_to_expanded_source,
+    } = inner_mutex.into_inner()?;
+    let mut rs_files_used = RsFilesUsed::default();
+    for unit_dep_info in unit_dep_infos {
+        let build_unit = match to_build_unit(
+            &unit_dep_info.package_id,
+            &unit_dep_info.target,
+        ) {
+            Some(build_unit) => build_unit,
+            None => continue,
+        };
+        add_dep_info_to_path_buf_hash_set(
+            &unit_dep_info.dep_info_path,
+            rs_files_used
+                .unit_to_rs_files
+                .entry(build_unit)
+                .or_default(),
+            &workspace_root,
         )?;
     }
-    for path_buf in rs_files {
-        // rs_files must already be canonicalized
-        path_buf_hash_set.insert(path_buf);
+    for ((package_id, target), rs_files) in unit_to_rs_file_args {
+        if let Some(build_unit) = to_build_unit(&package_id, &target) {
+            // rs_files must already be canonicalized
+            rs_files_used
+                .unit_to_rs_files
+                .entry(build_unit)
+                .or_default()
+                .extend(rs_files);
+        }
+    }
+    for ((package_id, target), expanded_source) in unit_to_expanded_source {
+        if let Some(build_unit) = to_build_unit(&package_id, &target) {
+            rs_files_used
+                .unit_to_expanded_source
+                .insert(build_unit, expanded_source);
+        }
     }
 
-    Ok(path_buf_hash_set)
+    Ok(rs_files_used)
 }
 
-fn add_dir_entries_to_path_buf_hash_set(
-    out_dir: PathBuf,
-    path_buf_hash_set: &mut HashSet<PathBuf>,
-    workspace_root: PathBuf,
-) -> Result<(), RsResolveError> {
-    for entry in WalkDir::new(&out_dir) {
-        let entry = entry.map_err(RsResolveError::Walkdir)?;
-        if !is_file_with_ext(&entry, "d") {
-            continue;
+/// Approximates the `.rs` files used by the build without building anything,
+/// by resolving the module tree of each build target from its entry point.
+/// The library, proc-macro and build script of every given package are used,
+/// as are the selected build targets of the root packages. Files only reached
+/// through macros or the output of build scripts are missed, while modules
+/// disabled by a `cfg` are still counted as used.
+pub fn resolve_rs_files_from_module_trees<'a>(
+    build_target_args: &BuildTargetArgs,
+    metadata: &Metadata,
+    package_ids: impl IntoIterator<Item = &'a CargoMetadataPackageId>,
+    root_package_ids: &[CargoMetadataPackageId],
+) -> RsFilesUsed {
+    let package_ids = package_ids.into_iter().collect::<HashSet<_>>();
+    let mut rs_files_used = RsFilesUsed::default();
+    for package in metadata
+        .packages
+        .iter()
+        .filter(|package| package_ids.contains(&package.id))
+    {
+        let is_root = root_package_ids.contains(&package.id);
+        for target in &package.targets {
+            let target_kind = into_target_kind(target.kind.clone());
+            if !is_target_selected(
+                build_target_args,
+                is_root,
+                &target_kind,
+                &target.name,
+            ) {
+                continue;
+            }
+            // Entry points that fail to resolve are reported when scanning.
+            let module_tree =
+                match resolve_module_tree(target.src_path.as_ref()) {
+                    Ok(module_tree) => module_tree,
+                    Err(_) => continue,
+                };
+            rs_files_used
+                .unit_to_rs_files
+                .entry(BuildUnit {
+                    package_id: package.id.clone(),
+                    target_kind,
+                    target_name: target.name.clone(),
+                })
+                .or_default()
+                .extend(module_tree.file_to_module_path.into_keys());
         }
-        let dependencies = parse_rustc_dep_info(entry.path()).map_err(|e| {
-            RsResolveError::DepParse(e.to_string(), entry.path().to_path_buf())
-        })?;
-        let canonical_paths = dependencies
-            .into_iter()
-            .flat_map(|(_, dependency_files)| dependency_files)
-            .map(PathBuf::from)
-            .map(|pb| workspace_root.join(pb))
-            .map(|pb| pb.canonicalize().map_err(|e| RsResolveError::Io(e, pb)));
-        for path_buf in canonical_paths {
-            path_buf_hash_set.insert(path_buf?);
+    }
+    rs_files_used
+}
+
+/// Whether `cargo check` would build the target with the given build target
+/// arguments. Only the library and build script of dependencies are built.
+fn is_target_selected(
+    build_target_args: &BuildTargetArgs,
+    is_root: bool,
+    target_kind: &TargetKind,
+    target_name: &str,
+) -> bool {
+    let BuildTargetArgs {
+        all_targets,
+        benches,
+        bins,
+        examples,
+        tests,
+        ..
+    } = build_target_args;
+    match target_kind {
+        TargetKind::Lib(_) | TargetKind::CustomBuild => true,
+        _ if !is_root => false,
+        _ if *all_targets => true,
+        TargetKind::Bin => {
+            !build_target_args.is_specific()
+                || bins.iter().any(|bin| bin == target_name)
+        }
+        TargetKind::ExampleBin | TargetKind::ExampleLib(_) => {
+            examples.iter().any(|example| example == target_name)
         }
+        TargetKind::Test => *tests,
+        TargetKind::Bench => *benches,
+    }
+}
+
+/// Loads the same workspace again, but with its target directory pointing to
+/// `GEIGER_TARGET_DIR_NAME` inside the original target directory.
+fn create_geiger_workspace<'cfg>(
+    workspace: &Workspace<'cfg>,
+) -> CargoResult<Workspace<'cfg>> {
+    let manifest_path = match workspace.current_opt() {
+        Some(package) => package.manifest_path(),
+        None => workspace.root_manifest(),
+    };
+    let mut geiger_workspace =
+        Workspace::new(manifest_path, workspace.config())?;
+    geiger_workspace
+        .set_target_dir(workspace.target_dir().join(GEIGER_TARGET_DIR_NAME));
+    Ok(geiger_workspace)
+}
+
+/// Lists every unit of the build with the location of its rustc dep-info
+/// file. The paths are computed the same way cargo does when invoking rustc,
+/// since fresh units never reach the `CustomExecutor`. Stale dep-info files
+/// of units no longer part of the build are ignored this way.
+fn unit_dep_infos(
+    compile_options: &CompileOptions,
+    workspace: &Workspace,
+) -> CargoResult<Vec<UnitDepInfo>> {
+    let interner = UnitInterner::new();
+    let build_context = ops::create_bcx(workspace, compile_options, &interner)?;
+    let mut context = Context::new(&build_context)?;
+    // The file names of a unit depend on its LTO, which `Context::compile`
+    // computes before preparing the units. The function doing so is private,
+    // but `cargo check` builds with the dev profile, where it only depends
+    // on whether LTO is explicitly turned off.
+    context.lto = build_context
+        .unit_graph
+        .keys()
+        .map(|unit| {
+            let lto = match unit.profile.lto {
+                profiles::Lto::Off if !unit.target.for_host() => Lto::Off,
+                _ => Lto::OnlyObject,
+            };
+            (unit.clone(), lto)
+        })
+        .collect();
+    context.prepare_units()?;
+
+    let files = context.files();
+    Ok(build_context
+        .unit_graph
+        .keys()
+        .filter(|unit| !unit.mode.is_run_custom_build())
+        .map(|unit| {
+            let dep_info_name = if files.use_extra_filename(unit) {
+                format!(
+                    "{}-{}.d",
+                    unit.target.crate_name(),
+                    files.metadata(unit)
+                )
+            } else {
+                format!("{}.d", unit.target.crate_name())
+            };
+            UnitDepInfo {
+                package_id: unit.pkg.package_id(),
+                manifest_path: unit.pkg.manifest_path().to_path_buf(),
+                target: unit.target.clone(),
+                dep_info_path: files.out_dir(unit).join(dep_info_name),
+            }
+        })
+        .collect())
+}
+
+fn add_dep_info_to_path_buf_hash_set(
+    dep_info_path: &Path,
+    path_buf_hash_set: &mut HashSet<PathBuf>,
+    workspace_root: &Path,
+) -> Result<(), RsResolveError> {
+    // Units that failed to build, or were filtered out by cargo, leave no
+    // dep-info file behind.
+    if !dep_info_path.exists() {
+        return Ok(());
+    }
+    let dependencies = parse_rustc_dep_info(dep_info_path).map_err(|e| {
+        RsResolveError::DepParse(e.to_string(), dep_info_path.to_path_buf())
+    })?;
+    let canonical_paths = dependencies
+        .into_iter()
+        .flat_map(|(_, dependency_files)| dependency_files)
+        .map(PathBuf::from)
+        .map(|pb| workspace_root.join(pb))
+        .map(|pb| pb.canonicalize().map_err(|e| RsResolveError::Io(e, pb)));
+    for path_buf in canonical_paths {
+        path_buf_hash_set.insert(path_buf?);
     }
 
     Ok(())
@@ -242,11 +488,13 @@ fn add_dir_entries_to_path_buf_hash_set(
 fn compile_with_exec(
     compile_options: &CompileOptions,
     config: &Config,
+    expand_macros: bool,
     inner_arc: Arc<Mutex<CustomExecutorInnerContext>>,
     workspace: &Workspace,
 ) -> Result<(), RsResolveError> {
     let custom_executor = CustomExecutor {
         cwd: config.cwd().to_path_buf(),
+        expand_macros,
         inner_ctx: inner_arc,
     };
 
@@ -296,7 +544,199 @@ fn parse_rustc_dep_info(
 #[cfg(test)]
 mod rs_file_tests {
     use super::*;
+    use cargo_metadata::MetadataCommand;
     use rstest::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use walkdir::WalkDir;
+
+    #[rstest]
+    fn add_dep_info_to_path_buf_hash_set_test() {
+        let temp_dir = tempdir().unwrap();
+        let lib_rs_path = temp_dir.path().join("lib.rs");
+        File::create(&lib_rs_path).unwrap();
+        let dep_info_path = temp_dir.path().join("crate-0123456789abcdef.d");
+        let mut dep_info_file = File::create(&dep_info_path).unwrap();
+        writeln!(dep_info_file, "crate.rmeta: lib.rs").unwrap();
+
+        let mut path_buf_hash_set = HashSet::new();
+        add_dep_info_to_path_buf_hash_set(
+            &dep_info_path,
+            &mut path_buf_hash_set,
+            temp_dir.path(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            path_buf_hash_set,
+            vec![lib_rs_path.canonicalize().unwrap()]
+                .into_iter()
+                .collect::<HashSet<PathBuf>>()
+        );
+    }
+
+    #[rstest]
+    fn rs_files_used_test() {
+        let package_id = |repr: &str| CargoMetadataPackageId {
+            repr: String::from(repr),
+        };
+        let build_unit = |repr: &str, target_kind: TargetKind| BuildUnit {
+            package_id: package_id(repr),
+            target_kind,
+            target_name: String::from(repr),
+        };
+        let paths = |paths: &[&str]| {
+            paths
+                .iter()
+                .map(PathBuf::from)
+                .collect::<HashSet<PathBuf>>()
+        };
+
+        let rs_files_used = RsFilesUsed {
+            unit_to_rs_files: vec![
+                (
+                    build_unit("outer", TargetKind::Lib(vec![])),
+                    paths(&["outer/src/lib.rs"]),
+                ),
+                (
+                    build_unit("outer", TargetKind::CustomBuild),
+                    paths(&["outer/build.rs"]),
+                ),
+                (
+                    build_unit("inner", TargetKind::Lib(vec![])),
+                    paths(&["outer/inner/src/lib.rs"]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            rs_files_used.for_package(&package_id("outer")),
+            paths(&["outer/src/lib.rs", "outer/build.rs"])
+        );
+        assert_eq!(
+            rs_files_used.for_package(&package_id("inner")),
+            paths(&["outer/inner/src/lib.rs"])
+        );
+        assert_eq!(
+            rs_files_used.all(),
+            paths(&[
+                "outer/src/lib.rs",
+                "outer/build.rs",
+                "outer/inner/src/lib.rs"
+            ])
+        );
+    }
+
+    #[rstest]
+    fn resolve_rs_files_from_module_trees_test() {
+        let metadata = MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()
+            .unwrap();
+        let package = metadata.root_package().unwrap();
+        let src_dir = package
+            .manifest_path
+            .parent()
+            .unwrap()
+            .join("src")
+            .canonicalize()
+            .unwrap();
+
+        let rs_files_used = resolve_rs_files_from_module_trees(
+            &BuildTargetArgs::default(),
+            &metadata,
+            vec![&package.id],
+            std::slice::from_ref(&package.id),
+        );
+
+        let package_rs_files = rs_files_used.for_package(&package.id);
+        assert!(package_rs_files.contains(&src_dir.join("lib.rs")));
+        assert!(package_rs_files.contains(&src_dir.join("main.rs")));
+        assert!(
+            package_rs_files.contains(&src_dir.join("scan").join("rs_file.rs"))
+        );
+        assert!(rs_files_used
+            .unit_to_rs_files
+            .keys()
+            .all(|build_unit| build_unit.target_kind != TargetKind::Test));
+    }
+
+    #[rstest(
+        input_build_target_args,
+        input_is_root,
+        input_target_kind,
+        expected_is_target_selected,
+        case(BuildTargetArgs::default(), false, TargetKind::Lib(vec![]), true),
+        case(BuildTargetArgs::default(), false, TargetKind::CustomBuild, true),
+        case(BuildTargetArgs::default(), false, TargetKind::Bin, false),
+        case(BuildTargetArgs::default(), true, TargetKind::Bin, true),
+        case(BuildTargetArgs::default(), true, TargetKind::Test, false),
+        case(
+            BuildTargetArgs { all_targets: true, ..Default::default() },
+            true,
+            TargetKind::Test,
+            true
+        ),
+        case(
+            BuildTargetArgs { lib: true, ..Default::default() },
+            true,
+            TargetKind::Bin,
+            false
+        ),
+        case(
+            BuildTargetArgs { bins: vec![String::from("a")], ..Default::default() },
+            true,
+            TargetKind::Bin,
+            true
+        ),
+        case(
+            BuildTargetArgs { examples: vec![String::from("b")], ..Default::default() },
+            true,
+            TargetKind::ExampleBin,
+            false
+        ),
+        case(
+            BuildTargetArgs { tests: true, ..Default::default() },
+            true,
+            TargetKind::Test,
+            true
+        )
+    )]
+    fn is_target_selected_test(
+        input_build_target_args: BuildTargetArgs,
+        input_is_root: bool,
+        input_target_kind: TargetKind,
+        expected_is_target_selected: bool,
+    ) {
+        assert_eq!(
+            is_target_selected(
+                &input_build_target_args,
+                input_is_root,
+                &input_target_kind,
+                "a",
+            ),
+            expected_is_target_selected
+        );
+    }
+
+    #[rstest]
+    fn add_dep_info_to_path_buf_hash_set_test_missing_dep_info() {
+        let temp_dir = tempdir().unwrap();
+        let mut path_buf_hash_set = HashSet::new();
+
+        let result = add_dep_info_to_path_buf_hash_set(
+            &temp_dir.path().join("missing.d"),
+            &mut path_buf_hash_set,
+            temp_dir.path(),
+        );
+
+        assert!(result.is_ok());
+        assert!(path_buf_hash_set.is_empty());
+    }
 
     #[rstest(
         input_rs_file,
@@ -1,10 +1,10 @@
+use anyhow::Context;
 use cargo::core::compiler::{CompileMode, Executor, Unit};
 use cargo::core::{PackageId, Target};
 use cargo::util::CargoResult;
 use cargo_util::ProcessBuilder;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::ffi::OsString;
 use std::fmt;
 use std::io;
 use std::path::PathBuf;
@@ -12,15 +12,16 @@ use std::sync::{Arc, Mutex};
 
 /// A cargo Executor to intercept all build tasks and store all ".rs" file
 /// paths for later scanning.
-///
-/// TODO: This is the place(?) to make rustc perform macro expansion to allow
-/// scanning of the the expanded code. (incl. code generated by build.rs).
-/// Seems to require nightly rust.
 #[derive(Debug)]
 pub struct CustomExecutor {
     /// Current work dir
     pub cwd: PathBuf,
 
+    /// Run rustc a second time for each unit with `-Zunpretty=expanded` to
+    /// capture the macro expanded source, including code generated by build
+    /// scripts. Requires a nightly toolchain.
+    pub expand_macros: bool,
+
     /// Needed since multiple rustc calls can be in flight at the same time.
     pub inner_ctx: Arc<Mutex<CustomExecutorInnerContext>>,
 }
@@ -29,8 +30,6 @@ pub struct CustomExecutor {
 enum CustomExecutorError {
     InnerContextMutex(String),
     Io(io::Error, PathBuf),
-    OutDirKeyMissing(String),
-    OutDirValueMissing(String),
 }
 
 impl Executor for CustomExecutor {
@@ -39,24 +38,13 @@ impl Executor for CustomExecutor {
     fn exec(
         &self,
         cmd: &ProcessBuilder,
-        _id: PackageId,
-        _target: &Target,
+        id: PackageId,
+        target: &Target,
         _mode: CompileMode,
         _on_stdout_line: &mut dyn FnMut(&str) -> CargoResult<()>,
         _on_stderr_line: &mut dyn FnMut(&str) -> CargoResult<()>,
     ) -> CargoResult<()> {
         let args = cmd.get_args();
-        let out_dir_key = OsString::from("--out-dir");
-        let out_dir_key_idx =
-            args.iter().position(|s| *s == out_dir_key).ok_or_else(|| {
-                CustomExecutorError::OutDirKeyMissing(cmd.to_string())
-            })?;
-        let out_dir = args
-            .get(out_dir_key_idx + 1)
-            .ok_or_else(|| {
-                CustomExecutorError::OutDirValueMissing(cmd.to_string())
-            })
-            .map(PathBuf::from)?;
 
         // This can be different from the cwd used to launch the wrapping cargo
         // plugin. Discovered while fixing
@@ -71,6 +59,10 @@ impl Executor for CustomExecutor {
             let mut ctx = self.inner_ctx.lock().map_err(|e| {
                 CustomExecutorError::InnerContextMutex(e.to_string())
             })?;
+            let rs_file_args = ctx
+                .unit_to_rs_file_args
+                .entry((id, target.clone()))
+                .or_default();
             for (arg_name, _) in args
                 .iter()
                 .map(|s| (s, s.to_string_lossy().to_lowercase()))
@@ -80,18 +72,35 @@ impl Executor for CustomExecutor {
                 let path = raw_path
                     .canonicalize()
                     .map_err(|e| CustomExecutorError::Io(e, raw_path))?;
-                ctx.rs_file_args.insert(path);
+                rs_file_args.insert(path);
             }
-            ctx.out_dir_args.insert(out_dir);
         }
         cmd.exec()?;
+
+        if self.expand_macros {
+            let mut expand_cmd = cmd.clone();
+            expand_cmd.arg("-Zunpretty=expanded");
+            let output = expand_cmd.exec_with_output().context(
+                "failed to expand macros, --expand-macros requires a nightly toolchain",
+            )?;
+            let mut ctx = self.inner_ctx.lock().map_err(|e| {
+                CustomExecutorError::InnerContextMutex(e.to_string())
+            })?;
+            ctx.unit_to_expanded_source.insert(
+                (id, target.clone()),
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+            );
+        }
+
         Ok(())
     }
 
     /// Queried when queuing each unit of work. If it returns true, then the
     /// unit will always be rebuilt, independent of whether it needs to be.
     fn force_rebuild(&self, _unit: &Unit) -> bool {
-        true // Overriding the default to force all units to be processed.
+        // Fresh units never reach `exec`, so the expanded source of every unit
+        // can only be captured by rebuilding all of them.
+        self.expand_macros
     }
 }
 
@@ -106,10 +115,11 @@ impl Error for CustomExecutorError {}
 
 #[derive(Debug, Default)]
 pub struct CustomExecutorInnerContext {
-    /// Stores all lib.rs, main.rs etc. passed to rustc during the build.
-    pub rs_file_args: HashSet<PathBuf>,
+    /// Stores all lib.rs, main.rs etc. passed to rustc during the build,
+    /// keyed by the package and target being compiled.
+    pub unit_to_rs_file_args: HashMap<(PackageId, Target), HashSet<PathBuf>>,
 
-    /// Investigate if this needs to be intercepted like this or if it can be
-    /// looked up in a nicer way.
-    pub out_dir_args: HashSet<PathBuf>,
+    /// The macro expanded source of each unit, only captured when expanding
+    /// macros.
+    pub unit_to_expanded_source: HashMap<(PackageId, Target), String>,
 }
@@ -133,7 +133,6 @@ mod tree_tests {
             direction: EdgeDirection::Outgoing,
             prefix,
             format: pattern,
-            allow_partial_results: false,
             include_tests: IncludeTests::Yes,
             output_format: OutputFormat::Ascii,
         }
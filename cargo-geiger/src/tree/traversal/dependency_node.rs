@@ -219,7 +219,6 @@ mod dependency_node_tests {
     fn create_print_config(edge_direction: EdgeDirection) -> PrintConfig {
         PrintConfig {
             all: false,
-            allow_partial_results: false,
             direction: edge_direction,
             format: Pattern(vec![]),
             include_tests: IncludeTests::Yes,
@@ -41,4 +41,9 @@ impl Context {
     pub fn crate_dir(&self, name: &str) -> PathBuf {
         self.path.join(name)
     }
+
+    /// Keeps the scan cache of the tests out of the cargo home.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.path.join("geiger-cache")
+    }
 }
@@ -1,9 +1,11 @@
 use crate::context::Context;
 use crate::integration_test::IntegrationTest;
-use crate::report::{merge_test_reports, single_entry_safety_report, to_set};
+use crate::report::{
+    merge_test_reports, single_entry_safety_report, to_set, unsafe_finding,
+};
 use cargo_geiger_serde::{
-    Count, CounterBlock, PackageId, PackageInfo, ReportEntry, SafetyReport,
-    Source, UnsafeInfo,
+    Count, CounterBlock, LintLevel, PackageId, PackageInfo, ReportEntry,
+    SafetyCommentCount, SafetyReport, Source, UnsafeInfo, UnsafeKind,
 };
 use semver::Version;
 use url::Url;
@@ -37,8 +39,34 @@ pub fn ref_slice_safety_report() -> SafetyReport {
                     safe: 10,
                     unsafe_: 2,
                 },
+                derefs_in_unsafe: Count {
+                    safe: 2,
+                    unsafe_: 0,
+                },
                 ..Default::default()
             },
+            safety_comments: Some(SafetyCommentCount {
+                documented: 0,
+                undocumented: 2,
+            }),
+            findings: vec![
+                unsafe_finding(
+                    "src/lib.rs",
+                    (8, 4),
+                    (10, 5),
+                    UnsafeKind::Block,
+                    "ref_slice",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "src/lib.rs",
+                    (15, 4),
+                    (17, 5),
+                    UnsafeKind::Block,
+                    "ref_slice_mut",
+                    Some(false),
+                ),
+            ],
             ..Default::default()
         },
     };
@@ -151,6 +179,7 @@ pub fn itertools_safety_report() -> SafetyReport {
                     safe: 180,
                     unsafe_: 0,
                 },
+                ..Default::default()
             },
             unused: CounterBlock {
                 functions: Count {
@@ -173,6 +202,7 @@ pub fn itertools_safety_report() -> SafetyReport {
                     safe: 29,
                     unsafe_: 3,
                 },
+                ..Default::default()
             },
             ..Default::default()
         },
@@ -193,7 +223,23 @@ pub fn cfg_if_package_id() -> PackageId {
 pub fn cfg_if_safety_report() -> SafetyReport {
     let entry = ReportEntry {
         package: PackageInfo::new(cfg_if_package_id()),
-        unsafety: Default::default(),
+        unsafety: UnsafeInfo {
+            used: CounterBlock {
+                macros: Count {
+                    safe: 1,
+                    unsafe_: 0,
+                },
+                ..Default::default()
+            },
+            unused: CounterBlock {
+                macros: Count {
+                    safe: 1,
+                    unsafe_: 0,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        },
     };
     single_entry_safety_report(entry)
 }
@@ -226,6 +272,18 @@ pub fn generational_arena_safety_report() -> SafetyReport {
                     safe: 39,
                     unsafe_: 0,
                 },
+                macros: Count {
+                    safe: 9,
+                    unsafe_: 0,
+                },
+                repr_packed: Count {
+                    safe: 6,
+                    unsafe_: 0,
+                },
+                derefs_in_unsafe: Count {
+                    safe: 5,
+                    unsafe_: 0,
+                },
                 ..Default::default()
             },
             unused: CounterBlock {
@@ -245,9 +303,23 @@ pub fn generational_arena_safety_report() -> SafetyReport {
                     safe: 8,
                     unsafe_: 0,
                 },
+                macros: Count {
+                    safe: 12,
+                    unsafe_: 0,
+                },
+                repr_packed: Count {
+                    safe: 4,
+                    unsafe_: 0,
+                },
+                derefs_in_unsafe: Count {
+                    safe: 13,
+                    unsafe_: 0,
+                },
                 ..Default::default()
             },
             forbids_unsafe: true,
+            unsafe_code_lint: LintLevel::Forbid,
+            ..Default::default()
         },
     };
     let mut report = single_entry_safety_report(entry);
@@ -283,6 +355,22 @@ pub fn idna_safety_report() -> SafetyReport {
                     safe: 13596,
                     unsafe_: 1,
                 },
+                macros: Count {
+                    safe: 7,
+                    unsafe_: 0,
+                },
+                statics: Count {
+                    safe: 13,
+                    unsafe_: 0,
+                },
+                repr_packed: Count {
+                    safe: 4,
+                    unsafe_: 0,
+                },
+                derefs_in_unsafe: Count {
+                    safe: 3,
+                    unsafe_: 0,
+                },
                 ..Default::default()
             },
             unused: CounterBlock {
@@ -294,8 +382,24 @@ pub fn idna_safety_report() -> SafetyReport {
                     safe: 185,
                     unsafe_: 0,
                 },
+                macros: Count {
+                    safe: 19,
+                    unsafe_: 0,
+                },
                 ..Default::default()
             },
+            safety_comments: Some(SafetyCommentCount {
+                documented: 0,
+                undocumented: 1,
+            }),
+            findings: vec![unsafe_finding(
+                "src/punycode.rs",
+                (148, 21),
+                (148, 73),
+                UnsafeKind::Block,
+                "punycode::encode",
+                Some(false),
+            )],
             ..Default::default()
         },
     };
@@ -317,7 +421,16 @@ pub fn matches_package_id() -> PackageId {
 pub fn matches_safety_report() -> SafetyReport {
     let entry = ReportEntry {
         package: PackageInfo::new(matches_package_id()),
-        unsafety: Default::default(),
+        unsafety: UnsafeInfo {
+            used: CounterBlock {
+                macros: Count {
+                    safe: 3,
+                    unsafe_: 0,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        },
     };
     single_entry_safety_report(entry)
 }
@@ -340,21 +453,38 @@ pub fn smallvec_safety_report() -> SafetyReport {
                     unsafe_: 2,
                 },
                 exprs: Count {
-                    safe: 291,
-                    unsafe_: 354,
+                    safe: 261,
+                    unsafe_: 334,
                 },
                 item_impls: Count {
-                    safe: 48,
-                    unsafe_: 4,
+                    safe: 42,
+                    unsafe_: 3,
                 },
                 item_traits: Count {
-                    safe: 3,
+                    safe: 2,
                     unsafe_: 1,
                 },
                 methods: Count {
-                    safe: 92,
-                    unsafe_: 13,
+                    safe: 87,
+                    unsafe_: 8,
+                },
+                macros: Count {
+                    safe: 25,
+                    unsafe_: 1,
+                },
+                repr_packed: Count {
+                    safe: 4,
+                    unsafe_: 0,
+                },
+                transmutes: Count {
+                    safe: 0,
+                    unsafe_: 1,
+                },
+                derefs_in_unsafe: Count {
+                    safe: 30,
+                    unsafe_: 4,
                 },
+                ..Default::default()
             },
             unused: CounterBlock {
                 functions: Count {
@@ -374,10 +504,408 @@ pub fn smallvec_safety_report() -> SafetyReport {
                     unsafe_: 0,
                 },
                 methods: Count {
-                    safe: 14,
+                    safe: 21,
+                    unsafe_: 0,
+                },
+                macros: Count {
+                    safe: 7,
                     unsafe_: 0,
                 },
+                ..Default::default()
             },
+            cfg_disabled: Some(CounterBlock {
+                exprs: Count {
+                    safe: 30,
+                    unsafe_: 20,
+                },
+                item_impls: Count {
+                    safe: 6,
+                    unsafe_: 1,
+                },
+                item_traits: Count {
+                    safe: 1,
+                    unsafe_: 0,
+                },
+                methods: Count {
+                    safe: 11,
+                    unsafe_: 5,
+                },
+                unions: Count {
+                    safe: 0,
+                    unsafe_: 1,
+                },
+                repr_packed: Count {
+                    safe: 2,
+                    unsafe_: 0,
+                },
+                ..Default::default()
+            }),
+            safety_comments: Some(SafetyCommentCount {
+                documented: 1,
+                undocumented: 36,
+            }),
+            findings: vec![
+                unsafe_finding(
+                    "lib.rs",
+                    (131, 0),
+                    (140, 1),
+                    UnsafeKind::Function,
+                    "unreachable",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (233, 0),
+                    (236, 1),
+                    UnsafeKind::Function,
+                    "deallocate",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (252, 41),
+                    (252, 72),
+                    UnsafeKind::Block,
+                    "<Drain as Iterator>::next",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (264, 46),
+                    (264, 77),
+                    UnsafeKind::Block,
+                    "<Drain as DoubleEndedIterator>::next_back",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (321, 4),
+                    (327, 5),
+                    UnsafeKind::Method,
+                    "SmallVecData::inline",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (328, 4),
+                    (334, 5),
+                    UnsafeKind::Method,
+                    "SmallVecData::inline_mut",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (339, 4),
+                    (345, 5),
+                    UnsafeKind::Method,
+                    "SmallVecData::into_inline",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (346, 4),
+                    (352, 5),
+                    UnsafeKind::Method,
+                    "SmallVecData::heap",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (353, 4),
+                    (359, 5),
+                    UnsafeKind::Method,
+                    "SmallVecData::heap_mut",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (366, 0),
+                    (366, 56),
+                    UnsafeKind::ItemImpl,
+                    "<SmallVecData as Send>",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (367, 0),
+                    (367, 56),
+                    UnsafeKind::ItemImpl,
+                    "<SmallVecData as Sync>",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (407, 8),
+                    (412, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::new",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (450, 12),
+                    (460, 13),
+                    UnsafeKind::Block,
+                    "SmallVec::from_vec",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (506, 8),
+                    (506, 65),
+                    UnsafeKind::Block,
+                    "SmallVec::from_buf_and_len",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (509, 4),
+                    (529, 5),
+                    UnsafeKind::Method,
+                    "SmallVec::from_buf_and_len_unchecked",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (532, 4),
+                    (540, 5),
+                    UnsafeKind::Method,
+                    "SmallVec::set_len",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (570, 8),
+                    (577, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::triple",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (583, 8),
+                    (590, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::triple_mut",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (601, 8),
+                    (612, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::drain",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (618, 8),
+                    (626, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::push",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (632, 8),
+                    (640, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::pop",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (647, 8),
+                    (669, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::grow",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (716, 12),
+                    (722, 13),
+                    UnsafeKind::Block,
+                    "SmallVec::shrink_to_fit",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (736, 8),
+                    (743, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::truncate",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (769, 37),
+                    (769, 61),
+                    UnsafeKind::Block,
+                    "SmallVec::swap_remove",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (783, 8),
+                    (792, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::remove",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (801, 8),
+                    (809, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::insert",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (825, 8),
+                    (855, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::insert_many",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (862, 12),
+                    (867, 13),
+                    UnsafeKind::Block,
+                    "SmallVec::into_vec",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (881, 12),
+                    (885, 13),
+                    UnsafeKind::Block,
+                    "SmallVec::into_inner",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (926, 8),
+                    (938, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::dedup_by",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (951, 4),
+                    (1029, 5),
+                    UnsafeKind::Method,
+                    "SmallVec::from_raw_parts",
+                    Some(true),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1041, 48),
+                    (1045, 17),
+                    UnsafeKind::Block,
+                    "SmallVec::from_slice",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1068, 8),
+                    (1074, 9),
+                    UnsafeKind::Block,
+                    "SmallVec::insert_from_slice",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1116, 12),
+                    (1124, 13),
+                    UnsafeKind::Block,
+                    "SmallVec::from_elem",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1134, 8),
+                    (1137, 9),
+                    UnsafeKind::Block,
+                    "<SmallVec as ops::Deref>::deref",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1144, 8),
+                    (1147, 9),
+                    UnsafeKind::Block,
+                    "<SmallVec as ops::DerefMut>::deref_mut",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1350, 8),
+                    (1361, 9),
+                    UnsafeKind::Block,
+                    "<SmallVec as Extend>::extend",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1399, 8),
+                    (1406, 9),
+                    UnsafeKind::Block,
+                    "<SmallVec as Drop>::drop",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1450, 0),
+                    (1450, 65),
+                    UnsafeKind::ItemImpl,
+                    "<SmallVec as Send>",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1478, 12),
+                    (1482, 13),
+                    UnsafeKind::Block,
+                    "<IntoIter as Iterator>::next",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1500, 12),
+                    (1503, 13),
+                    UnsafeKind::Block,
+                    "<IntoIter as DoubleEndedIterator>::next_back",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1514, 8),
+                    (1523, 9),
+                    UnsafeKind::Block,
+                    "<SmallVec as IntoIterator>::into_iter",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1543, 0),
+                    (1553, 1),
+                    UnsafeKind::ItemTrait,
+                    "Array",
+                    None,
+                ),
+                unsafe_finding(
+                    "lib.rs",
+                    (1587, 0),
+                    (1598, 1),
+                    UnsafeKind::Macro,
+                    "impl_array",
+                    None,
+                ),
+            ],
             ..Default::default()
         },
     };
@@ -405,7 +933,7 @@ pub fn unicode_bidi_safety_report() -> SafetyReport {
                     unsafe_: 0,
                 },
                 exprs: Count {
-                    safe: 2119,
+                    safe: 2115,
                     unsafe_: 0,
                 },
                 item_impls: Count {
@@ -416,9 +944,22 @@ pub fn unicode_bidi_safety_report() -> SafetyReport {
                     safe: 31,
                     unsafe_: 0,
                 },
+                macros: Count {
+                    safe: 19,
+                    unsafe_: 0,
+                },
+                repr_packed: Count {
+                    safe: 7,
+                    unsafe_: 0,
+                },
+                derefs_in_unsafe: Count {
+                    safe: 6,
+                    unsafe_: 0,
+                },
                 ..Default::default()
             },
             forbids_unsafe: true,
+            unsafe_code_lint: LintLevel::Forbid,
             ..Default::default()
         },
     };
@@ -460,9 +1001,14 @@ pub(super) fn unicode_normalization_safety_report() -> SafetyReport {
                     unsafe_: 0,
                 },
                 methods: Count {
-                    safe: 21,
+                    safe: 26,
                     unsafe_: 0,
                 },
+                repr_packed: Count {
+                    safe: 4,
+                    unsafe_: 0,
+                },
+                ..Default::default()
             },
             unused: CounterBlock {
                 functions: Count {
@@ -475,6 +1021,37 @@ pub(super) fn unicode_normalization_safety_report() -> SafetyReport {
                 },
                 ..Default::default()
             },
+            unsafe_code_lint: LintLevel::Deny,
+            safety_comments: Some(SafetyCommentCount {
+                documented: 0,
+                undocumented: 3,
+            }),
+            findings: vec![
+                unsafe_finding(
+                    "src/normalize.rs",
+                    (99, 4),
+                    (109, 5),
+                    UnsafeKind::Block,
+                    "normalize::decompose_hangul",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "src/normalize.rs",
+                    (131, 17),
+                    (131, 53),
+                    UnsafeKind::Block,
+                    "normalize::compose_hangul",
+                    Some(false),
+                ),
+                unsafe_finding(
+                    "src/normalize.rs",
+                    (135, 17),
+                    (135, 68),
+                    UnsafeKind::Block,
+                    "normalize::compose_hangul",
+                    Some(false),
+                ),
+            ],
             ..Default::default()
         },
     };
@@ -505,6 +1082,18 @@ pub fn num_cpus_safety_report(cx: &Context) -> SafetyReport {
                 },
                 ..Default::default()
             },
+            safety_comments: Some(SafetyCommentCount {
+                documented: 0,
+                undocumented: 1,
+            }),
+            findings: vec![unsafe_finding(
+                "src/lib.rs",
+                (4, 4),
+                (4, 16),
+                UnsafeKind::Block,
+                "get",
+                Some(false),
+            )],
             ..Default::default()
         },
     };
@@ -1,5 +1,5 @@
 use crate::context::Context;
-use crate::report::to_quick_report;
+use crate::report::{relative_finding_paths, to_quick_report};
 use crate::run::run_geiger_with;
 
 use cargo_geiger_serde::{QuickSafetyReport, ReportEntry, SafetyReport};
@@ -18,8 +18,9 @@ pub trait IntegrationTest {
     fn run(&self) {
         let (output, cx) = run_geiger_json(Self::NAME);
         assert!(output.status.success());
-        let actual =
+        let mut actual =
             serde_json::from_slice::<SafetyReport>(&output.stdout).unwrap();
+        relative_finding_paths(&mut actual);
         assert_eq!(actual, self.expected_report(&cx));
     }
 
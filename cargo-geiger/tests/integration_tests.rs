@@ -9,7 +9,6 @@ use self::run::run_geiger_with;
 use insta::assert_snapshot;
 use rstest::rstest;
 use std::env;
-use std::process::Output;
 
 #[rstest(
     name,
@@ -25,7 +24,7 @@ use std::process::Output;
 fn test_package(name: &str) {
     better_panic::install();
 
-    let result = run_geiger(name);
+    let (result, cx) = run_geiger_with(name, None::<&str>);
 
     let stderr_filename = format!("{}.stderr", name);
     let stderr = String::from_utf8(result.stderr)
@@ -40,13 +39,12 @@ fn test_package(name: &str) {
     let stdout_filename = format!("{}.stdout", name);
     let stdout = String::from_utf8(result.stdout)
         .expect("output should have been valid utf-8");
+    // The lines of the build targets name the packages by their full id,
+    // including the temporary directory the test crates are copied to.
+    let stdout = stdout.replace(cx.path.to_str().unwrap(), "{TEMP_DIR}");
     assert_snapshot!(stdout_filename, stdout);
 
     if stderr.is_empty() {
         assert!(result.status.success(), "`cargo-geiger` failed");
     }
-
-    fn run_geiger(test_name: &str) -> Output {
-        run_geiger_with(test_name, None::<&str>).0
-    }
 }
@@ -1,8 +1,10 @@
 use cargo_geiger_serde::{
-    PackageId, QuickReportEntry, QuickSafetyReport, ReportEntry, SafetyReport,
+    LineColumn, PackageId, QuickReportEntry, QuickSafetyReport, ReportEntry,
+    SafetyReport, Source, UnsafeFinding, UnsafeKind,
 };
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::path::{Path, PathBuf};
 
 fn report_entry_list_to_map<I>(entries: I) -> HashMap<PackageId, ReportEntry>
 where
@@ -42,19 +44,77 @@ pub fn to_quick_report(report: SafetyReport) -> QuickSafetyReport {
             let quick_entry = QuickReportEntry {
                 package: entry.package,
                 forbids_unsafe: entry.unsafety.forbids_unsafe,
+                unsafe_code_lint: entry.unsafety.unsafe_code_lint,
+                unsafe_op_in_unsafe_fn_lint: entry
+                    .unsafety
+                    .unsafe_op_in_unsafe_fn_lint,
             };
             (id, quick_entry)
         })
         .collect();
     QuickSafetyReport {
+        roots: report.roots,
         packages: entries,
         packages_without_metrics: report.packages_without_metrics,
+        files_with_errors: report.files_with_errors,
     }
 }
 
 pub fn single_entry_safety_report(entry: ReportEntry) -> SafetyReport {
     SafetyReport {
+        roots: vec![entry.package.id.clone()],
         packages: report_entry_list_to_map(vec![entry]),
         ..Default::default()
     }
 }
+
+pub fn unsafe_finding(
+    file: &str,
+    start: (usize, usize),
+    end: (usize, usize),
+    kind: UnsafeKind,
+    item_path: &str,
+    documented: Option<bool>,
+) -> UnsafeFinding {
+    UnsafeFinding {
+        file: PathBuf::from(file),
+        start: LineColumn {
+            line: start.0,
+            column: start.1,
+        },
+        end: LineColumn {
+            line: end.0,
+            column: end.1,
+        },
+        kind,
+        item_path: item_path.to_string(),
+        documented,
+    }
+}
+
+// The findings of registry packages point into the cargo home or a vendor
+// directory, so the paths of all findings are made relative to the root of
+// their package before comparing reports.
+pub fn relative_finding_paths(report: &mut SafetyReport) {
+    for entry in report.packages.values_mut() {
+        let id = &entry.package.id;
+        let package_dir_name = format!("{}-{}", id.name, id.version);
+        for finding in &mut entry.unsafety.findings {
+            let package_root = match &id.source {
+                Source::Path(url) => url.to_file_path().ok(),
+                _ => finding
+                    .file
+                    .ancestors()
+                    .find(|ancestor| {
+                        ancestor.file_name() == Some(package_dir_name.as_ref())
+                    })
+                    .map(Path::to_path_buf),
+            };
+            if let Some(relative_path) = package_root
+                .and_then(|root| finding.file.strip_prefix(root).ok())
+            {
+                finding.file = relative_path.to_path_buf();
+            }
+        }
+    }
+}
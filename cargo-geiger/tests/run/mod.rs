@@ -18,6 +18,7 @@ where
         .arg("--all-targets")
         .arg("--all-features")
         .args(extra_args)
+        .env("CARGO_GEIGER_CACHE_DIR", cx.cache_dir())
         .current_dir(cx.crate_dir(test_name))
         .output()
         .expect("failed to run `cargo-geiger`");
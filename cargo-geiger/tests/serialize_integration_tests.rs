@@ -10,11 +10,13 @@ mod run;
 use self::context::Context;
 use self::external_package_reports::make_package_id;
 use self::integration_test::IntegrationTest;
-use self::report::{merge_test_reports, single_entry_safety_report, to_set};
+use self::report::{
+    merge_test_reports, single_entry_safety_report, to_set, unsafe_finding,
+};
 
 use cargo_geiger_serde::{
-    Count, CounterBlock, PackageInfo, ReportEntry, SafetyReport, Source,
-    UnsafeInfo,
+    Count, CounterBlock, LintLevel, PackageInfo, ReportEntry,
+    SafetyCommentCount, SafetyReport, Source, UnsafeInfo, UnsafeKind,
 };
 use rstest::rstest;
 use std::path::PathBuf;
@@ -102,8 +104,34 @@ impl IntegrationTest for Test1 {
                         safe: 4,
                         unsafe_: 2,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
+                safety_comments: Some(SafetyCommentCount {
+                    documented: 0,
+                    undocumented: 2,
+                }),
+                findings: vec![
+                    unsafe_finding(
+                        "src/lib.rs",
+                        (3, 0),
+                        (5, 1),
+                        UnsafeKind::Function,
+                        "f",
+                        Some(false),
+                    ),
+                    unsafe_finding(
+                        "src/lib.rs",
+                        (8, 32),
+                        (10, 5),
+                        UnsafeKind::Block,
+                        "g",
+                        Some(false),
+                    ),
+                ],
                 ..Default::default()
             },
         }
@@ -145,8 +173,24 @@ impl IntegrationTest for Test2 {
                         safe: 0,
                         unsafe_: 4,
                     },
+                    statics: Count {
+                        safe: 0,
+                        unsafe_: 1,
+                    },
                     ..Default::default()
                 },
+                safety_comments: Some(SafetyCommentCount {
+                    documented: 0,
+                    undocumented: 1,
+                }),
+                findings: vec![unsafe_finding(
+                    "src/lib.rs",
+                    (3, 0),
+                    (8, 1),
+                    UnsafeKind::Function,
+                    "f",
+                    Some(false),
+                )],
                 ..Default::default()
             },
         }
@@ -195,6 +239,14 @@ impl IntegrationTest for Test3 {
                     },
                     ..Default::default()
                 },
+                findings: vec![unsafe_finding(
+                    "src/main.rs",
+                    (2, 4),
+                    (2, 51),
+                    UnsafeKind::Block,
+                    "main",
+                    Some(false),
+                )],
                 ..Default::default()
             },
         }
@@ -229,6 +281,10 @@ impl IntegrationTest for Test4 {
                         safe: 1,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 unused: CounterBlock {
@@ -240,6 +296,10 @@ impl IntegrationTest for Test4 {
                         safe: 1,
                         unsafe_: 1,
                     },
+                    derefs_in_unsafe: Count {
+                        safe: 0,
+                        unsafe_: 1,
+                    },
                     ..Default::default()
                 },
                 ..Default::default()
@@ -286,9 +346,14 @@ impl IntegrationTest for Test6 {
                         safe: 1,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 forbids_unsafe: true,
+                unsafe_code_lint: LintLevel::Forbid,
                 ..Default::default()
             },
         }
@@ -328,9 +393,14 @@ impl IntegrationTest for Test7 {
                         safe: 1,
                         unsafe_: 0,
                     },
+                    macros: Count {
+                        safe: 1,
+                        unsafe_: 0,
+                    },
                     ..Default::default()
                 },
                 forbids_unsafe: true,
+                unsafe_code_lint: LintLevel::Forbid,
                 ..Default::default()
             },
         }
@@ -1,34 +1,91 @@
-use super::{IncludeTests, RsFileMetrics, ScanFileError};
+use super::{syn_file_with_error, IncludeTests, RsFileMetrics, ScanFileError};
 
 use crate::geiger_syn_visitor::GeigerSynVisitor;
+use crate::token_fallback::find_unsafe_in_tokens;
 
+use cargo_platform::Cfg;
+use proc_macro2::TokenStream;
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Scan a single file for `unsafe` usage.
 pub fn find_unsafe_in_file(
     path: &Path,
     include_tests: IncludeTests,
 ) -> Result<RsFileMetrics, ScanFileError> {
+    find_unsafe_in_file_with_cfgs(path, include_tests, None)
+}
+
+/// Scan a single file for `unsafe` usage, only counting the code enabled by
+/// `cfgs`. Code disabled by `cfg` attributes is counted separately.
+pub fn find_unsafe_in_file_with_cfgs(
+    path: &Path,
+    include_tests: IncludeTests,
+    cfgs: Option<&[Cfg]>,
+) -> Result<RsFileMetrics, ScanFileError> {
+    let src = read_file_to_string(path)?;
+    let mut metrics =
+        find_unsafe_in_string_with_cfgs(&src, include_tests, cfgs)
+            .map_err(|e| ScanFileError::Syn(e, path.to_path_buf()))?;
+    for finding in &mut metrics.findings {
+        finding.file = path.to_path_buf();
+    }
+    for function in &mut metrics.functions {
+        function.file = path.to_path_buf();
+    }
+    if let Some(parse_error) = &mut metrics.parse_error {
+        parse_error.file = path.to_path_buf();
+    }
+    Ok(metrics)
+}
+
+pub(crate) fn read_file_to_string(
+    path: &Path,
+) -> Result<String, ScanFileError> {
     let mut file = File::open(path)
         .map_err(|e| ScanFileError::Io(e, path.to_path_buf()))?;
     let mut src = vec![];
     file.read_to_end(&mut src)
         .map_err(|e| ScanFileError::Io(e, path.to_path_buf()))?;
-    let src = String::from_utf8(src)
-        .map_err(|e| ScanFileError::Utf8(e, path.to_path_buf()))?;
-    find_unsafe_in_string(&src, include_tests)
-        .map_err(|e| ScanFileError::Syn(e, path.to_path_buf()))
+    String::from_utf8(src)
+        .map_err(|e| ScanFileError::Utf8(e, path.to_path_buf()))
 }
 
 pub fn find_unsafe_in_string(
     src: &str,
     include_tests: IncludeTests,
+) -> Result<RsFileMetrics, syn::Error> {
+    find_unsafe_in_string_with_cfgs(src, include_tests, None)
+}
+
+/// Sources that `syn` can not parse, e.g. because of newer syntax, are
+/// scanned token by token instead, see `RsFileMetrics::approximate`. The
+/// parse error is kept in `RsFileMetrics::parse_error`. Fails only if the
+/// source can not even be split into tokens.
+pub fn find_unsafe_in_string_with_cfgs(
+    src: &str,
+    include_tests: IncludeTests,
+    cfgs: Option<&[Cfg]>,
 ) -> Result<RsFileMetrics, syn::Error> {
     use syn::visit::Visit;
-    let syntax = syn::parse_file(src)?;
-    let mut vis = GeigerSynVisitor::new(include_tests);
+    let source_lines = src.lines().map(String::from).collect::<Vec<_>>();
+    let syntax = match syn::parse_file(src) {
+        Ok(syntax) => syntax,
+        Err(error) => {
+            let tokens = match src.parse::<TokenStream>() {
+                Ok(tokens) => tokens,
+                Err(_) => return Err(error),
+            };
+            let mut metrics =
+                find_unsafe_in_tokens(tokens, include_tests, &source_lines);
+            metrics.parse_error =
+                Some(syn_file_with_error(&error, PathBuf::new()));
+            return Ok(metrics);
+        }
+    };
+    let mut vis = GeigerSynVisitor::new(include_tests, cfgs);
+    vis.source_lines = source_lines;
     vis.visit_file(&syntax);
     Ok(vis.metrics)
 }
@@ -37,7 +94,10 @@ pub fn find_unsafe_in_string(
 mod find_tests {
     use super::*;
 
-    use cargo_geiger_serde::{Count, CounterBlock};
+    use cargo_geiger_serde::{
+        Count, CounterBlock, FileErrorKind, FileWithError, FunctionMetrics,
+        LineColumn, LintLevel, UnsafeFinding, UnsafeKind,
+    };
     use rstest::*;
     use std::io::Write;
     use tempfile::tempdir;
@@ -103,9 +163,21 @@ mod tests {
                 methods: Count {
                     safe: 0,
                     unsafe_: 0
-                }
+                },
+                macros: Count {
+                    safe: 4,
+                    unsafe_: 0
+                },
+                ..Default::default()
             },
-            forbids_unsafe: false
+            forbids_unsafe: false,
+            unsafe_code_lint: None,
+            unsafe_op_in_unsafe_fn_lint: None,
+            findings: file_content_findings(IncludeTests::Yes),
+            functions: file_content_functions(IncludeTests::Yes),
+            cfg_disabled: Default::default(),
+            approximate: false,
+            parse_error: None
         }
         ),
         case(
@@ -131,15 +203,27 @@ mod tests {
                     methods: Count {
                         safe: 0,
                         unsafe_: 0
-                    }
+                    },
+                    macros: Count {
+                        safe: 3,
+                        unsafe_: 0
+                    },
+                    ..Default::default()
                 },
-                forbids_unsafe: false
+                forbids_unsafe: false,
+                unsafe_code_lint: None,
+                unsafe_op_in_unsafe_fn_lint: None,
+                findings: file_content_findings(IncludeTests::No),
+                functions: file_content_functions(IncludeTests::No),
+                cfg_disabled: Default::default(),
+                approximate: false,
+                parse_error: None
             }
         )
     )]
     fn find_unsafe_in_file_test_no_errors(
         input_include_tests: IncludeTests,
-        expected_rs_file_metrics: RsFileMetrics,
+        mut expected_rs_file_metrics: RsFileMetrics,
     ) {
         let temp_dir = tempdir().unwrap();
         let lib_file_path = temp_dir.path().join("lib.rs");
@@ -154,9 +238,47 @@ mod tests {
 
         let unsafe_in_file = unsafe_in_file_result.unwrap();
 
+        for finding in &mut expected_rs_file_metrics.findings {
+            finding.file = lib_file_path.clone();
+        }
+        for function in &mut expected_rs_file_metrics.functions {
+            function.file = lib_file_path.clone();
+        }
         assert_eq!(unsafe_in_file, expected_rs_file_metrics);
     }
 
+    #[rstest(
+        input_content,
+        expected_kind,
+        expected_start,
+        case(
+            b"fn f() {\n    let x = ;\n}\n".to_vec(),
+            FileErrorKind::Parse,
+            Some(LineColumn { line: 2, column: 12 })
+        ),
+        case(vec![0xff, 0xfe], FileErrorKind::Utf8, None)
+    )]
+    fn find_unsafe_in_file_test_file_with_error(
+        input_content: Vec<u8>,
+        expected_kind: FileErrorKind,
+        expected_start: Option<LineColumn>,
+    ) {
+        let temp_dir = tempdir().unwrap();
+        let lib_file_path = temp_dir.path().join("lib.rs");
+        std::fs::write(&lib_file_path, input_content).unwrap();
+
+        // Parse errors are kept next to the metrics counted from the tokens.
+        let file_with_error =
+            match find_unsafe_in_file(&lib_file_path, IncludeTests::No) {
+                Ok(metrics) => metrics.parse_error.unwrap(),
+                Err(error) => FileWithError::from(&error),
+            };
+
+        assert_eq!(file_with_error.file, lib_file_path);
+        assert_eq!(file_with_error.kind, expected_kind);
+        assert_eq!(file_with_error.start, expected_start);
+    }
+
     #[rstest(
         input_include_tests,
         expected_rs_file_metrics,
@@ -183,9 +305,21 @@ mod tests {
                     methods: Count {
                         safe: 0,
                         unsafe_: 0
-                    }
+                    },
+                    macros: Count {
+                        safe: 4,
+                        unsafe_: 0
+                    },
+                    ..Default::default()
                 },
-                forbids_unsafe: false
+                forbids_unsafe: false,
+                unsafe_code_lint: None,
+                unsafe_op_in_unsafe_fn_lint: None,
+                findings: file_content_findings(IncludeTests::Yes),
+                functions: file_content_functions(IncludeTests::Yes),
+                cfg_disabled: Default::default(),
+                approximate: false,
+                parse_error: None
             }
         ),
         case(
@@ -211,9 +345,21 @@ mod tests {
                     methods: Count {
                         safe: 0,
                         unsafe_: 0
-                    }
+                    },
+                    macros: Count {
+                        safe: 3,
+                        unsafe_: 0
+                    },
+                    ..Default::default()
                 },
-                forbids_unsafe: false
+                forbids_unsafe: false,
+                unsafe_code_lint: None,
+                unsafe_op_in_unsafe_fn_lint: None,
+                findings: file_content_findings(IncludeTests::No),
+                functions: file_content_functions(IncludeTests::No),
+                cfg_disabled: Default::default(),
+                approximate: false,
+                parse_error: None
             }
         )
     )]
@@ -229,4 +375,807 @@ mod tests {
 
         assert_eq!(unsafe_in_string, expected_rs_file_metrics);
     }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_item_paths() {
+        let source = "mod outer {
+    unsafe trait Marker {}
+
+    struct S;
+
+    unsafe impl Marker for S {}
+
+    impl S {
+        unsafe fn method(&self) {}
+
+        fn safe_method(&self) {
+            unsafe { self.method() }
+        }
+    }
+}
+";
+        let metrics = find_unsafe_in_string(source, IncludeTests::Yes).unwrap();
+        let item_paths_and_kinds = metrics
+            .findings
+            .into_iter()
+            .map(|f| (f.item_path, f.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            item_paths_and_kinds,
+            vec![
+                (String::from("outer::Marker"), UnsafeKind::ItemTrait),
+                (String::from("outer::<S as Marker>"), UnsafeKind::ItemImpl),
+                (String::from("outer::S::method"), UnsafeKind::Method),
+                (String::from("outer::S::safe_method"), UnsafeKind::Block),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_trait_methods_and_exported_functions() {
+        let source = "trait T {
+    unsafe fn declared(&self);
+
+    unsafe fn defaulted(&self, p: *const u8) -> u8 {
+        *p
+    }
+
+    fn safe_default(&self, p: *const u8) -> u8 {
+        unsafe { *p }
+    }
+}
+
+#[no_mangle]
+pub extern \"C\" fn exported(x: u8) -> u8 {
+    x + 1
+}
+
+fn after_exported(x: u8) -> u8 {
+    x + 1
+}
+
+#[no_mangle]
+pub static EXPORTED_STATIC: u8 = 0;
+
+struct S;
+
+impl S {
+    #[no_mangle]
+    pub extern \"C\" fn exported_method(&self, x: u8) -> u8 {
+        x + 1
+    }
+
+    fn after_exported_method(&self, x: u8) -> u8 {
+        x + 1
+    }
+}
+";
+        let metrics = find_unsafe_in_string(source, IncludeTests::Yes).unwrap();
+        let item_paths_and_kinds = metrics
+            .findings
+            .into_iter()
+            .map(|f| (f.item_path, f.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            item_paths_and_kinds,
+            vec![
+                (String::from("T::declared"), UnsafeKind::Method),
+                (String::from("T::defaulted"), UnsafeKind::Method),
+                (String::from("T::safe_default"), UnsafeKind::Block),
+                (String::from("exported"), UnsafeKind::ExportedFunction),
+                (
+                    String::from("S::exported_method"),
+                    UnsafeKind::ExportedFunction
+                ),
+            ]
+        );
+        assert_eq!(
+            metrics.counters.methods,
+            Count {
+                safe: 2,
+                unsafe_: 3
+            }
+        );
+        assert_eq!(
+            metrics.counters.functions,
+            Count {
+                safe: 1,
+                unsafe_: 1
+            }
+        );
+        // The functions following exported ones are safe scopes again.
+        assert_eq!(
+            metrics.counters.exprs,
+            Count {
+                safe: 2,
+                unsafe_: 4
+            }
+        );
+        assert_eq!(
+            metrics.counters.derefs_in_unsafe,
+            Count {
+                safe: 0,
+                unsafe_: 2
+            }
+        );
+        assert_eq!(
+            metrics.counters.statics,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_functions() {
+        let source = "pub fn hot(p: *const u8) -> u8 {
+    let a = unsafe { *p };
+    let b = unsafe { *p.add(1) };
+    fn nested(p: *const u8) -> u8 {
+        unsafe { *p }
+    }
+    a + b + nested(p)
+}
+
+pub unsafe fn declared(p: *const u8) -> u8 {
+    *p
+}
+
+struct S;
+
+impl S {
+    fn method(&self) {}
+}
+";
+        let metrics = find_unsafe_in_string(source, IncludeTests::Yes).unwrap();
+        let functions = metrics
+            .functions
+            .into_iter()
+            .map(|f| (f.item_path, f.start.line, f.unsafe_fn, f.unsafe_exprs))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            functions,
+            vec![
+                (String::from("hot"), 1, false, 3),
+                (String::from("hot::nested"), 4, false, 1),
+                (String::from("declared"), 10, true, 1),
+                (String::from("S::method"), 17, false, 0),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_macros() {
+        let source = "macro_rules! deref {
+    ($p:expr) => {
+        unsafe { *$p }
+    };
+}
+
+fn f(p: *const u8) {
+    println!(\"{}\", unsafe { *p });
+    assert!(true);
+    custom!(unsafe { *p });
+    let _v = vec![unsafe { *p }; 2];
+}
+";
+        let metrics = find_unsafe_in_string(source, IncludeTests::Yes).unwrap();
+        let item_paths_and_kinds = metrics
+            .findings
+            .into_iter()
+            .map(|f| (f.item_path, f.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            metrics.counters.macros,
+            Count {
+                safe: 1,
+                unsafe_: 4
+            }
+        );
+        assert_eq!(metrics.counters.exprs.unsafe_, 0);
+        assert_eq!(
+            item_paths_and_kinds,
+            vec![
+                (String::from("deref"), UnsafeKind::Macro),
+                (String::from("f"), UnsafeKind::Macro),
+                (String::from("f"), UnsafeKind::Macro),
+                (String::from("f"), UnsafeKind::Macro),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_unsafe_adjacent_constructs() {
+        let source = "extern \"C\" {
+    fn abs(input: i32) -> i32;
+    static errno: i32;
+}
+
+static COUNTER: u32 = 0;
+static mut BUFFER: [u8; 4] = [0; 4];
+
+#[repr(C, packed)]
+struct Packed(u8, u32);
+
+struct Plain(u8);
+
+union IntOrFloat {
+    i: u32,
+    f: f32,
+}
+
+#[link_section = \".init_array\"]
+static INIT: u8 = 0;
+
+fn f(p: *const u32, r: &u32) -> u32 {
+    let _x = *r;
+    unsafe {
+        core::arch::asm!(\"nop\");
+        std::mem::transmute::<u32, f32>(*p);
+        *p
+    }
+}
+";
+        let counters = find_unsafe_in_string(source, IncludeTests::Yes)
+            .unwrap()
+            .counters;
+
+        assert_eq!(
+            counters.foreign_items,
+            Count {
+                safe: 0,
+                unsafe_: 2
+            }
+        );
+        assert_eq!(
+            counters.statics,
+            Count {
+                safe: 2,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            counters.unions,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            counters.repr_packed,
+            Count {
+                safe: 2,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            counters.asm,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            counters.link_sections,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            counters.transmutes,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            counters.derefs_in_unsafe,
+            Count {
+                safe: 1,
+                unsafe_: 2
+            }
+        );
+    }
+
+    #[rstest(
+        input_body,
+        expected_unsafe_derefs,
+        case("unsafe { *r }", 0),
+        case("unsafe { *p }", 1),
+        case("unsafe { *(r as *const u32) }", 1),
+        case("unsafe { *core::ptr::null::<u32>() }", 1),
+        case("unsafe { *[1u32].as_ptr() }", 1),
+        case("unsafe { *p.add(1) }", 1),
+        case("unsafe { *r.add(1) }", 0),
+        case("let q = p; unsafe { *q }", 1),
+        case("let q: *const u32 = r; unsafe { *q }", 1),
+        case("*r", 0)
+    )]
+    fn find_unsafe_in_string_test_raw_pointer_derefs(
+        input_body: &str,
+        expected_unsafe_derefs: u64,
+    ) {
+        let source = format!(
+            "fn f(p: *const u32, r: &u32) -> u32 {{ {} }}\n\
+             fn g(q: &u32) -> u32 {{ unsafe {{ *q }} }}",
+            input_body
+        );
+        let counters = find_unsafe_in_string(&source, IncludeTests::Yes)
+            .unwrap()
+            .counters;
+
+        assert_eq!(counters.derefs_in_unsafe.unsafe_, expected_unsafe_derefs);
+    }
+
+    #[rstest(
+        input_source,
+        expected_unsafe_code_lint,
+        expected_unsafe_op_in_unsafe_fn_lint,
+        case("", None, None),
+        case("#![forbid(unsafe_code)]", Some(LintLevel::Forbid), None),
+        case(
+            "#![deny(unsafe_code, unsafe_op_in_unsafe_fn)]",
+            Some(LintLevel::Deny),
+            Some(LintLevel::Deny)
+        ),
+        case(
+            "#![forbid(unsafe_code)]\n#![allow(unsafe_code)]",
+            Some(LintLevel::Forbid),
+            None
+        ),
+        case(
+            "#![deny(unsafe_code)]\n#![warn(unsafe_code)]",
+            Some(LintLevel::Warn),
+            None
+        ),
+        case(
+            "#![cfg_attr(feature = \"std\", forbid(unsafe_code))]",
+            Some(LintLevel::Forbid),
+            None
+        ),
+        case(
+            "#![cfg_attr(not(feature = \"std\"), forbid(unsafe_code))]",
+            None,
+            None
+        )
+    )]
+    fn find_unsafe_in_string_with_cfgs_test_lint_levels(
+        input_source: &str,
+        expected_unsafe_code_lint: Option<LintLevel>,
+        expected_unsafe_op_in_unsafe_fn_lint: Option<LintLevel>,
+    ) {
+        let cfgs =
+            vec![Cfg::KeyPair(String::from("feature"), String::from("std"))];
+        let metrics = find_unsafe_in_string_with_cfgs(
+            input_source,
+            IncludeTests::No,
+            Some(&cfgs),
+        )
+        .unwrap();
+
+        assert_eq!(metrics.unsafe_code_lint, expected_unsafe_code_lint);
+        assert_eq!(
+            metrics.unsafe_op_in_unsafe_fn_lint,
+            expected_unsafe_op_in_unsafe_fn_lint
+        );
+        assert_eq!(
+            metrics.forbids_unsafe,
+            expected_unsafe_code_lint == Some(LintLevel::Forbid)
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_safety_comments() {
+        let source = "/// Reads the value.
+///
+/// # Safety
+///
+/// `p` must be valid.
+pub unsafe fn documented(p: *const u8) -> u8 {
+    // SAFETY: guaranteed by the caller.
+    unsafe { *p }
+}
+
+pub unsafe fn undocumented(p: *const u8) -> u8 {
+    *p
+}
+
+unsafe fn private(p: *const u8) -> u8 {
+    // SAFETY: guaranteed by the caller.
+    let value =
+        unsafe { *p };
+    let other = /* SAFETY: also guaranteed. */ unsafe { *p };
+    value + other + unsafe { *p }
+}
+
+struct S;
+
+// SAFETY: S has no fields.
+#[allow(dead_code)]
+unsafe impl Send for S {}
+
+unsafe impl Sync for S {}
+";
+        let metrics = find_unsafe_in_string(source, IncludeTests::Yes).unwrap();
+        let documented = metrics
+            .findings
+            .into_iter()
+            .map(|f| (f.item_path, f.kind, f.documented))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            documented,
+            vec![
+                (String::from("documented"), UnsafeKind::Function, Some(true)),
+                (String::from("documented"), UnsafeKind::Block, Some(true)),
+                (
+                    String::from("undocumented"),
+                    UnsafeKind::Function,
+                    Some(false)
+                ),
+                (String::from("private"), UnsafeKind::Function, None),
+                (String::from("private"), UnsafeKind::Block, Some(true)),
+                (String::from("private"), UnsafeKind::Block, Some(true)),
+                (String::from("private"), UnsafeKind::Block, Some(false)),
+                (
+                    String::from("<S as Send>"),
+                    UnsafeKind::ItemImpl,
+                    Some(true)
+                ),
+                (
+                    String::from("<S as Sync>"),
+                    UnsafeKind::ItemImpl,
+                    Some(false)
+                ),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_rust_2024_unsafe_attributes() {
+        let source = "unsafe extern \"C\" {
+    pub safe fn sqrt(x: f64) -> f64;
+    pub unsafe fn strlen(p: *const u8) -> usize;
+    fn abs(x: i32) -> i32;
+    pub safe static TAU: f64;
+}
+
+#[unsafe(no_mangle)]
+pub fn exported() {}
+
+#[unsafe(export_name = \"exported_static\")]
+pub static EXPORTED: u8 = 0;
+
+#[unsafe(link_section = \".data\")]
+static PLACED: u8 = 0;
+
+struct S;
+
+impl S {
+    #[unsafe(no_mangle)]
+    pub fn exported_method(&self) {}
+}
+";
+        let metrics = find_unsafe_in_string(source, IncludeTests::Yes).unwrap();
+        let item_paths_and_kinds = metrics
+            .findings
+            .into_iter()
+            .map(|f| (f.item_path, f.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            item_paths_and_kinds,
+            vec![
+                (String::from("exported"), UnsafeKind::ExportedFunction),
+                (
+                    String::from("S::exported_method"),
+                    UnsafeKind::ExportedFunction
+                ),
+            ]
+        );
+        assert_eq!(
+            metrics.counters.foreign_items,
+            Count {
+                safe: 2,
+                unsafe_: 2
+            }
+        );
+        assert_eq!(
+            metrics.counters.statics,
+            Count {
+                safe: 1,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            metrics.counters.link_sections,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(
+            metrics.counters.methods,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_test_token_fallback() {
+        let source = "#![deny(unsafe_op_in_unsafe_fn)]
+
+pub unsafe fn f() {}
+
+pub fn g(p: *const u8) -> u8 {
+    let _ = ;
+    // SAFETY: p is valid
+    unsafe { *p }
+}
+
+struct S;
+unsafe impl Send for S {}
+impl S {
+    unsafe fn m(&self) {}
+    fn n(&self) -> impl Iterator<Item = u8> {
+        std::iter::empty()
+    }
+}
+
+unsafe trait T {
+    fn t(&self);
+}
+
+static mut X: u8 = 0;
+static Y: &'static str = \"\";
+
+unsafe extern \"C\" {
+    fn abs(x: i32) -> i32;
+}
+
+fn h() {
+    println!(\"{}\", unsafe { X });
+    assert!(1 != 2);
+}
+
+fn k(c: bool, p: *const u8) -> u8 {
+    if !c {
+        return unsafe { *p };
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    unsafe fn in_test() {}
+}
+
+#[cfg(all(test, feature = \"x\"))]
+unsafe fn in_feature_test() {}
+
+#[tokio::test]
+async fn in_async_test() {
+    unsafe { in_feature_test() }
+}
+";
+        let count = |safe, unsafe_| Count { safe, unsafe_ };
+
+        let metrics = find_unsafe_in_string(source, IncludeTests::No).unwrap();
+
+        assert!(metrics.approximate);
+        assert_eq!(metrics.counters.functions, count(3, 1));
+        assert_eq!(metrics.counters.methods, count(2, 1));
+        assert_eq!(metrics.counters.exprs, count(0, 2));
+        assert_eq!(metrics.counters.item_impls, count(1, 1));
+        assert_eq!(metrics.counters.item_traits, count(0, 1));
+        assert_eq!(metrics.counters.macros, count(1, 1));
+        assert_eq!(metrics.counters.statics, count(1, 1));
+        assert_eq!(metrics.counters.foreign_items, count(0, 1));
+        assert_eq!(metrics.unsafe_op_in_unsafe_fn_lint, Some(LintLevel::Deny));
+        assert_eq!(
+            metrics
+                .findings
+                .iter()
+                .map(|f| (f.kind, f.item_path.as_str(), f.documented))
+                .collect::<Vec<_>>(),
+            vec![
+                (UnsafeKind::Function, "f", None),
+                (UnsafeKind::Block, "g", Some(true)),
+                (UnsafeKind::ItemImpl, "<S as Send>", Some(false)),
+                (UnsafeKind::Method, "S::m", None),
+                (UnsafeKind::ItemTrait, "T", None),
+                (UnsafeKind::Macro, "h", None),
+                (UnsafeKind::Block, "k", Some(false)),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn find_unsafe_in_string_with_cfgs_test() {
+        let source = "#[cfg(windows)]
+extern \"system\" {
+    fn GetLastError() -> u32;
+}
+
+#[cfg(all(unix, feature = \"ffi\"))]
+pub unsafe fn enabled() {}
+
+#[cfg_attr(not(feature = \"ffi\"), cfg(any()))]
+pub unsafe fn also_enabled() {}
+
+pub fn f(p: *const u8) -> u8 {
+    #[cfg(target_os = \"windows\")]
+    let _ = unsafe { *p };
+    0
+}
+
+#[cfg(all(test, feature = \"ffi\"))]
+mod tests {
+    unsafe fn in_tests() {}
+}
+";
+        let cfgs = vec![
+            Cfg::Name(String::from("unix")),
+            Cfg::KeyPair(String::from("target_os"), String::from("linux")),
+            Cfg::KeyPair(String::from("feature"), String::from("ffi")),
+        ];
+
+        let metrics = find_unsafe_in_string_with_cfgs(
+            source,
+            IncludeTests::Yes,
+            Some(&cfgs),
+        )
+        .unwrap();
+
+        assert_eq!(
+            metrics.counters.functions,
+            Count {
+                safe: 1,
+                unsafe_: 3
+            }
+        );
+        assert_eq!(metrics.counters.foreign_items, Count::default());
+        assert_eq!(metrics.counters.exprs.unsafe_, 0);
+        assert_eq!(
+            metrics.cfg_disabled.foreign_items,
+            Count {
+                safe: 0,
+                unsafe_: 1
+            }
+        );
+        assert_eq!(metrics.cfg_disabled.exprs.unsafe_, 1);
+
+        let metrics_without_tests = find_unsafe_in_string_with_cfgs(
+            source,
+            IncludeTests::No,
+            Some(&cfgs),
+        )
+        .unwrap();
+
+        assert_eq!(
+            metrics_without_tests.cfg_disabled.functions,
+            Count::default()
+        );
+        assert_eq!(
+            metrics_without_tests.counters.functions,
+            Count {
+                safe: 1,
+                unsafe_: 2
+            }
+        );
+    }
+
+    #[rstest(
+        input_include_tests,
+        expected_unsafe_functions,
+        case(IncludeTests::Yes, 2),
+        case(IncludeTests::No, 1)
+    )]
+    fn find_unsafe_in_string_with_cfgs_test_not_test(
+        input_include_tests: IncludeTests,
+        expected_unsafe_functions: u64,
+    ) {
+        let source = "#[cfg(not(test))]
+pub unsafe fn in_production() {}
+
+#[cfg(test)]
+unsafe fn in_tests() {}
+";
+        let cfgs = vec![Cfg::Name(String::from("unix"))];
+
+        let metrics = find_unsafe_in_string_with_cfgs(
+            source,
+            input_include_tests,
+            Some(&cfgs),
+        )
+        .unwrap();
+
+        assert_eq!(
+            metrics.counters.functions,
+            Count {
+                safe: 0,
+                unsafe_: expected_unsafe_functions
+            }
+        );
+        assert_eq!(metrics.cfg_disabled, CounterBlock::default());
+    }
+
+    fn file_content_findings(
+        include_tests: IncludeTests,
+    ) -> Vec<UnsafeFinding> {
+        let finding = |kind,
+                       start: (usize, usize),
+                       end: (usize, usize),
+                       item_path: &str| {
+            UnsafeFinding {
+                file: Default::default(),
+                start: LineColumn {
+                    line: start.0,
+                    column: start.1,
+                },
+                end: LineColumn {
+                    line: end.0,
+                    column: end.1,
+                },
+                // None of the unsafe usages in the file are documented.
+                documented: match kind {
+                    UnsafeKind::Function | UnsafeKind::Block => Some(false),
+                    _ => None,
+                },
+                kind,
+                item_path: String::from(item_path),
+            }
+        };
+        let mut findings = vec![
+            finding(UnsafeKind::Function, (3, 0), (5, 1), "f"),
+            finding(UnsafeKind::Block, (8, 32), (10, 5), "g"),
+            finding(UnsafeKind::ExportedFunction, (13, 0), (16, 1), "h"),
+            finding(UnsafeKind::ExportedFunction, (18, 0), (21, 1), "g"),
+        ];
+        if include_tests == IncludeTests::Yes {
+            findings.push(finding(
+                UnsafeKind::Block,
+                (29, 8),
+                (31, 9),
+                "tests::test_1",
+            ));
+        }
+        findings
+    }
+
+    fn file_content_functions(
+        include_tests: IncludeTests,
+    ) -> Vec<FunctionMetrics> {
+        // Every function has a single unsafe expression.
+        let function = |start: (usize, usize), item_path: &str, unsafe_fn| {
+            FunctionMetrics {
+                file: Default::default(),
+                start: LineColumn {
+                    line: start.0,
+                    column: start.1,
+                },
+                item_path: String::from(item_path),
+                unsafe_fn,
+                unsafe_exprs: 1,
+            }
+        };
+        let mut functions = vec![
+            function((3, 0), "f", true),
+            function((7, 0), "g", false),
+            function((13, 0), "h", true),
+            function((18, 0), "g", true),
+        ];
+        if include_tests == IncludeTests::Yes {
+            functions.push(function((27, 4), "tests::test_1", false));
+        }
+        functions
+    }
 }
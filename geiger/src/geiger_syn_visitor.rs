@@ -1,14 +1,34 @@
 use super::{
-    file_forbids_unsafe, has_unsafe_attributes, is_test_fn, is_test_mod,
-    IncludeTests, RsFileMetrics,
+    file_lint_level, foreign_item_attrs, has_repr_packed, has_safety_comment,
+    has_safety_section, has_unsafe_attributes, impl_item_attrs,
+    impl_path_segment, is_asm_macro, is_cfg_enabled, is_raw_pointer_expr,
+    is_safe_foreign_item, is_test_fn, is_test_mod, is_transmute_call,
+    item_attrs, parse_macro_body, stmt_attrs, tokens_contain_unsafe,
+    trait_item_attrs, IncludeTests, MacroBody, RsFileMetrics,
 };
 
-use syn::{visit, Expr, ImplItemMethod, ItemFn, ItemImpl, ItemMod, ItemTrait};
+use cargo_geiger_serde::{
+    FunctionMetrics, LineColumn, LintLevel, UnsafeFinding, UnsafeKind,
+};
+use cargo_platform::Cfg;
+use proc_macro2::Span;
+use std::path::PathBuf;
+use syn::spanned::Spanned;
+use syn::{
+    visit, Attribute, Expr, ForeignItem, ImplItem, ImplItemFn, Item, ItemFn,
+    ItemImpl, ItemMacro, ItemMod, ItemStatic, ItemStruct, ItemTrait, ItemUnion,
+    Local, Macro, Meta, Pat, PatType, StaticMutability, Stmt, StmtMacro,
+    TraitItem, TraitItemFn, Type, UnOp, Visibility,
+};
 
 pub struct GeigerSynVisitor {
     /// Count unsafe usage inside tests
     include_tests: IncludeTests,
 
+    /// The active cfg set to evaluate `cfg` attributes against. Without it
+    /// all code is scanned.
+    cfgs: Option<Vec<Cfg>>,
+
     /// The resulting data from a single file scan.
     pub metrics: RsFileMetrics,
 
@@ -20,14 +40,41 @@ pub struct GeigerSynVisitor {
     /// This is needed since unsafe scopes can be nested and we need to know
     /// when we leave the outmost unsafe scope and get back into a safe scope.
     unsafe_scopes: u32,
+
+    /// Names of the modules, impls, traits and functions enclosing the node
+    /// that is currently being visited. Used to tell where a finding is.
+    item_path: Vec<String>,
+
+    /// Lines of the scanned source, used to look for `// SAFETY:` comments.
+    /// Comments are not part of the syntax tree.
+    pub source_lines: Vec<String>,
+
+    /// Start of the statement that is currently being visited, a `// SAFETY:`
+    /// comment above it also documents the unsafe blocks inside it.
+    stmt_start: Option<LineColumn>,
+
+    /// Indices into `metrics.functions` of the functions enclosing the node
+    /// that is currently being visited, the innermost one last.
+    function_stack: Vec<usize>,
+
+    /// Names of the bindings of the enclosing functions that are known to be
+    /// raw pointers, from their type or from the expression they are bound
+    /// to. Shadowing is not tracked.
+    raw_pointers: Vec<String>,
 }
 
 impl GeigerSynVisitor {
-    pub fn new(include_tests: IncludeTests) -> Self {
+    pub fn new(include_tests: IncludeTests, cfgs: Option<&[Cfg]>) -> Self {
         GeigerSynVisitor {
             include_tests,
+            cfgs: cfgs.map(<[Cfg]>::to_vec),
             metrics: Default::default(),
             unsafe_scopes: 0,
+            item_path: vec![],
+            source_lines: vec![],
+            stmt_start: None,
+            function_stack: vec![],
+            raw_pointers: vec![],
         }
     }
 
@@ -38,11 +85,132 @@ impl GeigerSynVisitor {
     pub fn exit_unsafe_scope(&mut self) {
         self.unsafe_scopes -= 1;
     }
+
+    /// Will return true if `cfg` attributes disable the node, after handling
+    /// it. `test` is never part of the active cfgs, so code under
+    /// `#[cfg(not(test))]` is always counted. Code only enabled for tests is
+    /// counted when counting tests and skipped otherwise, like `#[test]`
+    /// functions. Other disabled code is scanned with a separate visitor and
+    /// only added to the `cfg_disabled` metrics.
+    fn handle_cfg_disabled<F>(
+        &mut self,
+        attrs: Option<&[Attribute]>,
+        visit_node: F,
+    ) -> bool
+    where
+        F: FnOnce(&mut GeigerSynVisitor),
+    {
+        let (cfgs, attrs) = match (&self.cfgs, attrs) {
+            (Some(cfgs), Some(attrs)) => (cfgs, attrs),
+            _ => return false,
+        };
+        if is_cfg_enabled(attrs, cfgs) {
+            return false;
+        }
+        let mut test_cfgs = cfgs.clone();
+        test_cfgs.push(Cfg::Name(String::from("test")));
+        if is_cfg_enabled(attrs, &test_cfgs) {
+            return IncludeTests::No == self.include_tests;
+        }
+        let mut disabled_visitor =
+            GeigerSynVisitor::new(self.include_tests, None);
+        visit_node(&mut disabled_visitor);
+        self.metrics.cfg_disabled += disabled_visitor.metrics.counters;
+        true
+    }
+
+    /// Starts collecting the metrics of a function, the item path of the
+    /// function must already be pushed.
+    fn enter_function(&mut self, span: Span, unsafe_fn: bool) {
+        self.function_stack.push(self.metrics.functions.len());
+        self.metrics.functions.push(FunctionMetrics {
+            file: PathBuf::new(),
+            start: line_column(span),
+            item_path: self.item_path.join("::"),
+            unsafe_fn,
+            unsafe_exprs: 0,
+        });
+    }
+
+    fn exit_function(&mut self) {
+        self.function_stack.pop();
+    }
+
+    /// Counts an expression, unsafe ones are also added to the innermost
+    /// enclosing function.
+    fn count_expr(&mut self) {
+        let is_unsafe = self.unsafe_scopes > 0;
+        self.metrics.counters.exprs.count(is_unsafe);
+        if let (true, Some(&index)) = (is_unsafe, self.function_stack.last()) {
+            self.metrics.functions[index].unsafe_exprs += 1;
+        }
+    }
+
+    /// Will return true if a `// SAFETY:` comment precedes `span` or the
+    /// statement containing it.
+    fn has_safety_comment(&self, span: Span) -> bool {
+        let start = line_column(span);
+        has_safety_comment(&self.source_lines, &start)
+            || match &self.stmt_start {
+                Some(stmt_start) => {
+                    has_safety_comment(&self.source_lines, stmt_start)
+                }
+                None => false,
+            }
+    }
+
+    fn add_finding(
+        &mut self,
+        kind: UnsafeKind,
+        span: Span,
+        documented: Option<bool>,
+    ) {
+        let (start, end) = (span.start(), span.end());
+        self.metrics.findings.push(UnsafeFinding {
+            file: PathBuf::new(),
+            start: LineColumn {
+                line: start.line,
+                column: start.column,
+            },
+            end: LineColumn {
+                line: end.line,
+                column: end.column,
+            },
+            kind,
+            item_path: self.item_path.join("::"),
+            documented,
+        });
+    }
+}
+
+fn line_column(span: Span) -> LineColumn {
+    let start = span.start();
+    LineColumn {
+        line: start.line,
+        column: start.column,
+    }
+}
+
+/// Public `unsafe fn`s are expected to document their safety requirements in
+/// a `# Safety` section, other functions are not checked.
+fn safety_section_documented(
+    vis: &Visibility,
+    attrs: &[Attribute],
+) -> Option<bool> {
+    match vis {
+        Visibility::Public(_) => Some(has_safety_section(attrs)),
+        _ => None,
+    }
 }
 
 impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
     fn visit_file(&mut self, i: &'ast syn::File) {
-        self.metrics.forbids_unsafe = file_forbids_unsafe(i);
+        let cfgs = self.cfgs.as_deref();
+        self.metrics.unsafe_code_lint = file_lint_level(i, "unsafe_code", cfgs);
+        self.metrics.unsafe_op_in_unsafe_fn_lint =
+            file_lint_level(i, "unsafe_op_in_unsafe_fn", cfgs);
+        self.metrics.forbids_unsafe =
+            self.metrics.unsafe_code_lint == Some(LintLevel::Forbid);
         syn::visit::visit_file(self, i);
     }
 
@@ -51,14 +219,31 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
         if IncludeTests::No == self.include_tests && is_test_fn(item_fn) {
             return;
         }
-        let unsafe_fn =
-            item_fn.sig.unsafety.is_some() || has_unsafe_attributes(item_fn);
+        let unsafe_fn = item_fn.sig.unsafety.is_some()
+            || has_unsafe_attributes(&item_fn.attrs);
         if unsafe_fn {
             self.enter_unsafe_scope()
         }
+        self.item_path.push(item_fn.sig.ident.to_string());
+        if item_fn.sig.unsafety.is_some() {
+            let documented =
+                safety_section_documented(&item_fn.vis, &item_fn.attrs);
+            self.add_finding(UnsafeKind::Function, item_fn.span(), documented);
+        } else if unsafe_fn {
+            self.add_finding(
+                UnsafeKind::ExportedFunction,
+                item_fn.span(),
+                None,
+            );
+        }
         self.metrics.counters.functions.count(unsafe_fn);
+        self.enter_function(item_fn.span(), unsafe_fn);
+        let raw_pointers = self.raw_pointers.len();
         visit::visit_item_fn(self, item_fn);
-        if item_fn.sig.unsafety.is_some() {
+        self.raw_pointers.truncate(raw_pointers);
+        self.exit_function();
+        self.item_path.pop();
+        if unsafe_fn {
             self.exit_unsafe_scope()
         }
     }
@@ -67,6 +252,8 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
         // Total number of expressions of any type
         match i {
             Expr::Unsafe(i) => {
+                let documented = self.has_safety_comment(i.unsafe_token.span);
+                self.add_finding(UnsafeKind::Block, i.span(), Some(documented));
                 self.enter_unsafe_scope();
                 visit::visit_expr_unsafe(self, i);
                 self.exit_unsafe_scope();
@@ -76,55 +263,290 @@ impl<'ast> visit::Visit<'ast> for GeigerSynVisitor {
                 // expression, not three.
             }
             other => {
+                match other {
+                    Expr::Call(expr_call) if is_transmute_call(expr_call) => {
+                        self.metrics.counters.transmutes.count(true)
+                    }
+                    Expr::Unary(expr_unary)
+                        if matches!(expr_unary.op, UnOp::Deref(_)) =>
+                    {
+                        self.metrics.counters.derefs_in_unsafe.count(
+                            self.unsafe_scopes > 0
+                                && is_raw_pointer_expr(
+                                    &expr_unary.expr,
+                                    &self.raw_pointers,
+                                ),
+                        )
+                    }
+                    _ => {}
+                }
                 // TODO: Print something pretty here or gather the data for later
                 // printing.
                 // if self.verbosity == Verbosity::Verbose && self.unsafe_scopes > 0 {
                 //     println!("{:#?}", other);
                 // }
-                self.metrics.counters.exprs.count(self.unsafe_scopes > 0);
+                self.count_expr();
                 visit::visit_expr(self, other);
             }
         }
     }
 
+    fn visit_local(&mut self, i: &Local) {
+        visit::visit_local(self, i);
+        if let (Pat::Ident(pat_ident), Some(init)) = (&i.pat, &i.init) {
+            if is_raw_pointer_expr(&init.expr, &self.raw_pointers) {
+                self.raw_pointers.push(pat_ident.ident.to_string());
+            }
+        }
+    }
+
+    /// Function arguments and `let` bindings with a type
+    fn visit_pat_type(&mut self, i: &PatType) {
+        if let (Pat::Ident(pat_ident), Type::Ptr(_)) = (&*i.pat, &*i.ty) {
+            self.raw_pointers.push(pat_ident.ident.to_string());
+        }
+        visit::visit_pat_type(self, i);
+    }
+
+    fn visit_stmt_macro(&mut self, i: &StmtMacro) {
+        // Macros in statement position, e.g. `println!(..);`, are expressions
+        // as well.
+        self.count_expr();
+        visit::visit_stmt_macro(self, i);
+    }
+
     fn visit_item_mod(&mut self, i: &ItemMod) {
         if IncludeTests::No == self.include_tests && is_test_mod(i) {
             return;
         }
+        self.item_path.push(i.ident.to_string());
         visit::visit_item_mod(self, i);
+        self.item_path.pop();
     }
 
     fn visit_item_impl(&mut self, i: &ItemImpl) {
         // unsafe trait impl's
+        self.item_path.push(impl_path_segment(i));
+        if let Some(unsafety) = &i.unsafety {
+            let documented = self.has_safety_comment(unsafety.span);
+            self.add_finding(UnsafeKind::ItemImpl, i.span(), Some(documented));
+        }
         self.metrics.counters.item_impls.count(i.unsafety.is_some());
         visit::visit_item_impl(self, i);
+        self.item_path.pop();
     }
 
     fn visit_item_trait(&mut self, i: &ItemTrait) {
         // Unsafe traits
+        self.item_path.push(i.ident.to_string());
+        if i.unsafety.is_some() {
+            self.add_finding(UnsafeKind::ItemTrait, i.span(), None);
+        }
         self.metrics
             .counters
             .item_traits
             .count(i.unsafety.is_some());
         visit::visit_item_trait(self, i);
+        self.item_path.pop();
     }
 
-    fn visit_impl_item_method(&mut self, i: &ImplItemMethod) {
+    /// Methods in `impl` blocks, exported methods are unsafe like exported
+    /// free-standing functions
+    fn visit_impl_item_fn(&mut self, i: &ImplItemFn) {
+        let unsafe_method =
+            i.sig.unsafety.is_some() || has_unsafe_attributes(&i.attrs);
+        if unsafe_method {
+            self.enter_unsafe_scope();
+        }
+        self.item_path.push(i.sig.ident.to_string());
         if i.sig.unsafety.is_some() {
-            self.enter_unsafe_scope()
+            let documented = safety_section_documented(&i.vis, &i.attrs);
+            self.add_finding(UnsafeKind::Method, i.span(), documented);
+        } else if unsafe_method {
+            self.add_finding(UnsafeKind::ExportedFunction, i.span(), None);
+        }
+        self.metrics.counters.methods.count(unsafe_method);
+        self.enter_function(i.span(), unsafe_method);
+        let raw_pointers = self.raw_pointers.len();
+        visit::visit_impl_item_fn(self, i);
+        self.raw_pointers.truncate(raw_pointers);
+        self.exit_function();
+        self.item_path.pop();
+        if unsafe_method {
+            self.exit_unsafe_scope()
+        }
+    }
+
+    /// Methods declared in traits, with or without a default body
+    fn visit_trait_item_fn(&mut self, i: &TraitItemFn) {
+        let unsafe_method = i.sig.unsafety.is_some();
+        if unsafe_method {
+            self.enter_unsafe_scope();
+        }
+        self.item_path.push(i.sig.ident.to_string());
+        if unsafe_method {
+            self.add_finding(UnsafeKind::Method, i.span(), None);
+        }
+        self.metrics.counters.methods.count(unsafe_method);
+        let has_default_body = i.default.is_some();
+        if has_default_body {
+            self.enter_function(i.span(), unsafe_method);
+        }
+        let raw_pointers = self.raw_pointers.len();
+        visit::visit_trait_item_fn(self, i);
+        self.raw_pointers.truncate(raw_pointers);
+        if has_default_body {
+            self.exit_function();
+        }
+        self.item_path.pop();
+        if unsafe_method {
+            self.exit_unsafe_scope()
+        }
+    }
+
+    fn visit_item(&mut self, i: &Item) {
+        if let Item::Mod(item_mod) = i {
+            // Skipped entirely by `visit_item_mod`, rather than counted as
+            // disabled code.
+            if IncludeTests::No == self.include_tests && is_test_mod(item_mod) {
+                return;
+            }
+        }
+        if self.handle_cfg_disabled(item_attrs(i), |v| v.visit_item(i)) {
+            return;
+        }
+        visit::visit_item(self, i);
+    }
+
+    fn visit_impl_item(&mut self, i: &ImplItem) {
+        if self
+            .handle_cfg_disabled(impl_item_attrs(i), |v| v.visit_impl_item(i))
+        {
+            return;
+        }
+        visit::visit_impl_item(self, i);
+    }
+
+    fn visit_trait_item(&mut self, i: &TraitItem) {
+        if self
+            .handle_cfg_disabled(trait_item_attrs(i), |v| v.visit_trait_item(i))
+        {
+            return;
         }
+        visit::visit_trait_item(self, i);
+    }
+
+    fn visit_stmt(&mut self, i: &Stmt) {
+        if self.handle_cfg_disabled(stmt_attrs(i), |v| v.visit_stmt(i)) {
+            return;
+        }
+        let outer_stmt_start = self.stmt_start.replace(line_column(i.span()));
+        visit::visit_stmt(self, i);
+        self.stmt_start = outer_stmt_start;
+    }
+
+    fn visit_foreign_item(&mut self, i: &ForeignItem) {
+        if self.handle_cfg_disabled(foreign_item_attrs(i), |v| {
+            v.visit_foreign_item(i)
+        }) {
+            return;
+        }
+        // Everything declared in an `extern` block is unsafe to use, except
+        // for items marked `safe` inside an `unsafe extern` block.
         self.metrics
             .counters
-            .methods
-            .count(i.sig.unsafety.is_some());
-        visit::visit_impl_item_method(self, i);
-        if i.sig.unsafety.is_some() {
-            self.exit_unsafe_scope()
+            .foreign_items
+            .count(!is_safe_foreign_item(i));
+        visit::visit_foreign_item(self, i);
+    }
+
+    fn visit_item_static(&mut self, i: &ItemStatic) {
+        let unsafe_static = matches!(i.mutability, StaticMutability::Mut(_))
+            || has_unsafe_attributes(&i.attrs);
+        self.metrics.counters.statics.count(unsafe_static);
+        visit::visit_item_static(self, i);
+    }
+
+    fn visit_item_struct(&mut self, i: &ItemStruct) {
+        self.metrics
+            .counters
+            .repr_packed
+            .count(has_repr_packed(&i.attrs));
+        visit::visit_item_struct(self, i);
+    }
+
+    fn visit_item_union(&mut self, i: &ItemUnion) {
+        self.metrics.counters.unions.count(true);
+        self.metrics
+            .counters
+            .repr_packed
+            .count(has_repr_packed(&i.attrs));
+        visit::visit_item_union(self, i);
+    }
+
+    fn visit_attribute(&mut self, i: &Attribute) {
+        let is_link_section = match &i.meta {
+            Meta::List(ml) if ml.path.is_ident("unsafe") => ml
+                .parse_args::<Meta>()
+                .map(|meta| meta.path().is_ident("link_section"))
+                .unwrap_or(false),
+            meta => meta.path().is_ident("link_section"),
+        };
+        if is_link_section {
+            self.metrics.counters.link_sections.count(true);
+        }
+        visit::visit_attribute(self, i);
+    }
+
+    fn visit_item_macro(&mut self, i: &ItemMacro) {
+        // `macro_rules!` definitions
+        let has_ident = i.ident.is_some();
+        if let Some(ident) = &i.ident {
+            self.item_path.push(ident.to_string());
+        }
+        visit::visit_item_macro(self, i);
+        if has_ident {
+            self.item_path.pop();
+        }
+    }
+
+    /// Macro input is opaque to `syn`. The input of well-known macros like
+    /// `println!` is parsed and scanned separately, for all other macros the
+    /// tokens are searched for the `unsafe` keyword. Either way, unsafe usage
+    /// inside macros is only counted by the `macros` counter.
+    fn visit_macro(&mut self, mac: &Macro) {
+        if is_asm_macro(mac) {
+            self.metrics.counters.asm.count(true);
+        }
+        let is_unsafe = match parse_macro_body(mac) {
+            Some(body) => {
+                let mut body_visitor = GeigerSynVisitor::new(
+                    self.include_tests,
+                    self.cfgs.as_deref(),
+                );
+                match &body {
+                    MacroBody::Exprs(exprs) => {
+                        exprs.iter().for_each(|e| body_visitor.visit_expr(e))
+                    }
+                    MacroBody::Block(block) => body_visitor.visit_block(block),
+                    MacroBody::Items(items) => {
+                        items.iter().for_each(|i| body_visitor.visit_item(i))
+                    }
+                }
+                // Nested macro invocations are counted as well.
+                let body_counters = body_visitor.metrics.counters;
+                self.metrics.counters.macros += body_counters.macros.clone();
+                body_counters.has_unsafe()
+            }
+            None => tokens_contain_unsafe(mac.tokens.clone()),
+        };
+        if is_unsafe {
+            self.add_finding(UnsafeKind::Macro, mac.span(), None);
         }
+        self.metrics.counters.macros.count(is_unsafe);
+        visit::visit_macro(self, mac);
     }
 
-    // TODO: Visit macros.
-    //
     // TODO: Figure out if there are other visit methods that should be
     // implemented here.
 }
@@ -11,14 +11,28 @@ pub mod find;
 pub use find::*; // preserve APIs
 
 mod geiger_syn_visitor;
+mod token_fallback;
 
-use cargo_geiger_serde::CounterBlock;
+/// Resolve the `.rs` files reachable from a crate entry point
+pub mod module_tree;
+
+use cargo_geiger_serde::{
+    CounterBlock, FileErrorKind, FileWithError, FunctionMetrics, LineColumn,
+    LintLevel, UnsafeFinding,
+};
+use cargo_platform::{Cfg, CfgExpr};
+use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 use std::io;
 use std::path::PathBuf;
 use std::string::FromUtf8Error;
-use syn::{ItemFn, ItemMod};
+use syn::{Attribute, Block, Expr, Item, ItemFn, ItemImpl, ItemMod, Meta};
+
+/// Version of this crate, scan results of different versions may differ.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum IncludeTests {
@@ -27,13 +41,41 @@ pub enum IncludeTests {
 }
 
 /// Scan result for a single `.rs` file.
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct RsFileMetrics {
     /// Metrics storage.
     pub counters: CounterBlock,
 
     /// This file is decorated with `#![forbid(unsafe_code)]`
     pub forbids_unsafe: bool,
+
+    /// Level of the `unsafe_code` lint set by the inner attributes of this
+    /// file, `None` when not set.
+    pub unsafe_code_lint: Option<LintLevel>,
+
+    /// Level of the `unsafe_op_in_unsafe_fn` lint set by the inner attributes
+    /// of this file, `None` when not set.
+    pub unsafe_op_in_unsafe_fn_lint: Option<LintLevel>,
+
+    /// Location of every unsafe function, block, impl, trait and method.
+    pub findings: Vec<UnsafeFinding>,
+
+    /// Unsafe usage of every function and method with a body.
+    pub functions: Vec<FunctionMetrics>,
+
+    /// Metrics of the code disabled by `cfg` attributes, only collected when
+    /// scanning with the active cfg set. Not included in `counters`.
+    pub cfg_disabled: CounterBlock,
+
+    /// This file could not be parsed, the metrics were counted from its
+    /// tokens and are approximate.
+    #[serde(default)]
+    pub approximate: bool,
+
+    /// The error `syn` failed to parse this file with, when the metrics are
+    /// approximate.
+    #[serde(default)]
+    pub parse_error: Option<FileWithError>,
 }
 
 #[derive(Debug)]
@@ -53,56 +95,526 @@ impl fmt::Display for ScanFileError {
     }
 }
 
-fn file_forbids_unsafe(f: &syn::File) -> bool {
+impl From<&ScanFileError> for FileWithError {
+    fn from(error: &ScanFileError) -> Self {
+        match error {
+            ScanFileError::Io(error, path) => FileWithError {
+                file: path.clone(),
+                kind: FileErrorKind::Io,
+                message: error.to_string(),
+                start: None,
+            },
+            ScanFileError::Utf8(error, path) => FileWithError {
+                file: path.clone(),
+                kind: FileErrorKind::Utf8,
+                message: error.to_string(),
+                start: None,
+            },
+            ScanFileError::Syn(error, path) => {
+                syn_file_with_error(error, path.clone())
+            }
+        }
+    }
+}
+
+/// A parse error of the file at `path`, with the position it occurred at.
+fn syn_file_with_error(error: &syn::Error, path: PathBuf) -> FileWithError {
+    let start = error.span().start();
+    FileWithError {
+        file: path,
+        kind: FileErrorKind::Parse,
+        message: error.to_string(),
+        start: Some(LineColumn {
+            line: start.line,
+            column: start.column,
+        }),
+    }
+}
+
+/// The level of `lint` set by the inner attributes of the file, e.g.
+/// `#![deny(unsafe_code)]`, `None` when it is not set. Lint attributes inside
+/// `#![cfg_attr(..)]` only count when the predicate holds for `cfgs`, they are
+/// ignored when scanning without the active cfg set.
+fn file_lint_level(
+    f: &syn::File,
+    lint: &str,
+    cfgs: Option<&[Cfg]>,
+) -> Option<LintLevel> {
     use syn::AttrStyle;
-    use syn::Meta;
-    use syn::MetaList;
-    use syn::NestedMeta;
-    f.attrs
+    let inner_attrs = f
+        .attrs
         .iter()
         .filter(|a| matches!(a.style, AttrStyle::Inner(_)))
-        .filter_map(|a| a.parse_meta().ok())
-        .filter(|meta| match meta {
-            Meta::List(MetaList {
-                path,
-                paren_token: _paren,
-                nested,
-            }) => {
-                if !path.is_ident("forbid") {
-                    return false;
+        .cloned()
+        .collect::<Vec<Attribute>>();
+    let mut lint_level = None;
+    for meta in attribute_metas(&inner_attrs) {
+        for level in meta_lint_levels(&meta, lint, cfgs) {
+            // Later attributes override earlier ones, except for `forbid`.
+            if lint_level != Some(LintLevel::Forbid) {
+                lint_level = Some(level);
+            }
+        }
+    }
+    lint_level
+}
+
+fn meta_lint_levels(
+    meta: &Meta,
+    lint: &str,
+    cfgs: Option<&[Cfg]>,
+) -> Vec<LintLevel> {
+    let meta_list = match meta {
+        Meta::List(meta_list) => meta_list,
+        _ => return vec![],
+    };
+    if meta_list.path.is_ident("cfg_attr") {
+        let nested = nested_metas(meta_list);
+        return match (nested.split_first(), cfgs) {
+            (Some((predicate, attrs)), Some(cfgs))
+                if cfg_expr_matches(
+                    &predicate.to_token_stream().to_string(),
+                    cfgs,
+                ) =>
+            {
+                attrs
+                    .iter()
+                    .flat_map(|meta| meta_lint_levels(meta, lint, Some(cfgs)))
+                    .collect()
+            }
+            _ => vec![],
+        };
+    }
+    let level = match meta_list.path.get_ident() {
+        Some(ident) if ident == "allow" => LintLevel::Allow,
+        Some(ident) if ident == "warn" => LintLevel::Warn,
+        Some(ident) if ident == "deny" => LintLevel::Deny,
+        Some(ident) if ident == "forbid" => LintLevel::Forbid,
+        _ => return vec![],
+    };
+    match nested_metas(meta_list)
+        .iter()
+        .any(|n| meta_contains_ident(n, lint))
+    {
+        true => vec![level],
+        false => vec![],
+    }
+}
+
+/// Will return true if a `// SAFETY:` comment precedes `start`, either
+/// earlier on the same line or on the lines directly above it, which may
+/// only contain other comments and attributes.
+fn has_safety_comment(source_lines: &[String], start: &LineColumn) -> bool {
+    let is_safety_comment =
+        |text: &str| text.to_uppercase().contains("SAFETY:");
+    let line_index = match start.line.checked_sub(1) {
+        Some(line_index) if line_index < source_lines.len() => line_index,
+        _ => return false,
+    };
+    let preceding = source_lines[line_index]
+        .chars()
+        .take(start.column)
+        .collect::<String>();
+    if preceding.contains("/*") && is_safety_comment(&preceding) {
+        return true;
+    }
+    for line in source_lines[..line_index].iter().rev() {
+        let line = line.trim();
+        if line.starts_with("//")
+            || line.starts_with("/*")
+            || line.starts_with('*')
+        {
+            if is_safety_comment(line) {
+                return true;
+            }
+        } else if !line.starts_with("#[") {
+            return false;
+        }
+    }
+    false
+}
+
+/// Will return true if the doc comments contain a `# Safety` section.
+fn has_safety_section(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(name_value) if name_value.path.is_ident("doc") => {
+                match &name_value.value {
+                    Expr::Lit(expr_lit) => match &expr_lit.lit {
+                        syn::Lit::Str(lit_str) => Some(lit_str.value()),
+                        _ => None,
+                    },
+                    _ => None,
                 }
-                nested.iter().any(|n| match n {
-                    NestedMeta::Meta(Meta::Path(p)) => {
-                        p.is_ident("unsafe_code")
-                    }
-                    _ => false,
-                })
             }
-            _ => false,
+            _ => None,
+        })
+        .any(|doc| {
+            doc.lines()
+                .any(|line| line.trim_start().starts_with("# Safety"))
         })
-        .count()
-        > 0
 }
 
 fn is_test_fn(item_fn: &ItemFn) -> bool {
-    use syn::Attribute;
-    item_fn
-        .attrs
-        .iter()
-        .flat_map(Attribute::parse_meta)
-        .any(|m| meta_contains_ident(&m, "test"))
+    attribute_metas(&item_fn.attrs).any(|m| meta_contains_ident(&m, "test"))
 }
 
-fn has_unsafe_attributes(item_fn: &ItemFn) -> bool {
-    use syn::Attribute;
-    item_fn
-        .attrs
+/// Will return true for `#[no_mangle]` and `#[export_name = ".."]`, also
+/// when spelled as Rust 2024 unsafe attributes, e.g. `#[unsafe(no_mangle)]`.
+fn has_unsafe_attributes(attrs: &[Attribute]) -> bool {
+    attribute_metas(attrs).any(|m| {
+        meta_contains_ident(&m, "no_mangle")
+            || meta_contains_attribute(&m, "export_name")
+    })
+}
+
+/// The meta of every attribute, with Rust 2024 unsafe attributes like
+/// `#[unsafe(no_mangle)]` unwrapped to `no_mangle`.
+fn attribute_metas(attrs: &[Attribute]) -> impl Iterator<Item = Meta> + '_ {
+    attrs.iter().map(|a| match &a.meta {
+        Meta::List(ml) if ml.path.is_ident("unsafe") => {
+            ml.parse_args::<Meta>().unwrap_or_else(|_| a.meta.clone())
+        }
+        meta => meta.clone(),
+    })
+}
+
+/// The comma separated metas of a meta list, e.g. `C, packed` for
+/// `repr(C, packed)`. Lists not made of metas are treated as empty.
+fn nested_metas(meta_list: &syn::MetaList) -> Vec<Meta> {
+    use syn::punctuated::Punctuated;
+    use syn::Token;
+    meta_list
+        .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+        .map(|nested| nested.into_iter().collect())
+        .unwrap_or_default()
+}
+
+/// The name used for an `impl` block in item paths, e.g. `Type` or
+/// `<Type as Trait>`.
+fn impl_path_segment(item_impl: &ItemImpl) -> String {
+    let self_ty = type_name(&item_impl.self_ty);
+    match &item_impl.trait_ {
+        Some((_, trait_path, _)) => {
+            format!("<{} as {}>", self_ty, path_name(trait_path))
+        }
+        None => self_ty,
+    }
+}
+
+fn path_name(path: &syn::Path) -> String {
+    path.segments
         .iter()
-        .flat_map(Attribute::parse_meta)
-        .any(|m| {
-            meta_contains_ident(&m, "no_mangle")
-                || meta_contains_attribute(&m, "export_name")
-        })
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<String>>()
+        .join("::")
+}
+
+fn type_name(ty: &syn::Type) -> String {
+    use syn::Type;
+    match ty {
+        Type::Path(type_path) => path_name(&type_path.path),
+        other => other.to_token_stream().to_string(),
+    }
+}
+
+/// Will return false if any `cfg` attribute, including the ones added by an
+/// active `cfg_attr`, does not match `cfgs`. Predicates that fail to parse
+/// count as matching, to err on the side of scanning too much.
+fn is_cfg_enabled(attrs: &[Attribute], cfgs: &[Cfg]) -> bool {
+    attrs.iter().all(|a| meta_is_cfg_enabled(&a.meta, cfgs))
+}
+
+fn meta_is_cfg_enabled(meta: &Meta, cfgs: &[Cfg]) -> bool {
+    let meta_list = match meta {
+        Meta::List(meta_list) => meta_list,
+        _ => return true,
+    };
+    if meta_list.path.is_ident("cfg") {
+        return cfg_expr_matches(&meta_list.tokens.to_string(), cfgs);
+    }
+    if meta_list.path.is_ident("cfg_attr") {
+        let nested = nested_metas(meta_list);
+        let (predicate, attrs) = match nested.split_first() {
+            Some(split) => split,
+            None => return true,
+        };
+        let predicate = predicate.to_token_stream().to_string();
+        return !cfg_expr_matches(&predicate, cfgs)
+            || attrs.iter().all(|meta| meta_is_cfg_enabled(meta, cfgs));
+    }
+    true
+}
+
+fn cfg_expr_matches(predicate: &str, cfgs: &[Cfg]) -> bool {
+    predicate
+        .parse::<CfgExpr>()
+        .map(|cfg_expr| cfg_expr.matches(cfgs))
+        .unwrap_or(true)
+}
+
+/// The attributes of items that can be disabled by `cfg`, `None` for the
+/// ones `syn` does not parse.
+fn item_attrs(item: &Item) -> Option<&[Attribute]> {
+    let attrs = match item {
+        Item::Const(i) => &i.attrs,
+        Item::Enum(i) => &i.attrs,
+        Item::ExternCrate(i) => &i.attrs,
+        Item::Fn(i) => &i.attrs,
+        Item::ForeignMod(i) => &i.attrs,
+        Item::Impl(i) => &i.attrs,
+        Item::Macro(i) => &i.attrs,
+        Item::Mod(i) => &i.attrs,
+        Item::Static(i) => &i.attrs,
+        Item::Struct(i) => &i.attrs,
+        Item::Trait(i) => &i.attrs,
+        Item::TraitAlias(i) => &i.attrs,
+        Item::Type(i) => &i.attrs,
+        Item::Union(i) => &i.attrs,
+        Item::Use(i) => &i.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+fn impl_item_attrs(impl_item: &syn::ImplItem) -> Option<&[Attribute]> {
+    use syn::ImplItem;
+    let attrs = match impl_item {
+        ImplItem::Const(i) => &i.attrs,
+        ImplItem::Fn(i) => &i.attrs,
+        ImplItem::Macro(i) => &i.attrs,
+        ImplItem::Type(i) => &i.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+fn trait_item_attrs(trait_item: &syn::TraitItem) -> Option<&[Attribute]> {
+    use syn::TraitItem;
+    let attrs = match trait_item {
+        TraitItem::Const(i) => &i.attrs,
+        TraitItem::Fn(i) => &i.attrs,
+        TraitItem::Macro(i) => &i.attrs,
+        TraitItem::Type(i) => &i.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+fn foreign_item_attrs(foreign_item: &syn::ForeignItem) -> Option<&[Attribute]> {
+    use syn::ForeignItem;
+    let attrs = match foreign_item {
+        ForeignItem::Fn(i) => &i.attrs,
+        ForeignItem::Macro(i) => &i.attrs,
+        ForeignItem::Static(i) => &i.attrs,
+        ForeignItem::Type(i) => &i.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+/// The attributes of statements that can be disabled by `cfg`. Items are
+/// handled separately, and only the expressions commonly found in statement
+/// position are covered.
+fn stmt_attrs(stmt: &syn::Stmt) -> Option<&[Attribute]> {
+    use syn::Stmt;
+    let attrs = match stmt {
+        Stmt::Local(local) => &local.attrs,
+        Stmt::Macro(stmt_macro) => &stmt_macro.attrs,
+        Stmt::Expr(Expr::Assign(e), _) => &e.attrs,
+        Stmt::Expr(Expr::Block(e), _) => &e.attrs,
+        Stmt::Expr(Expr::Call(e), _) => &e.attrs,
+        Stmt::Expr(Expr::If(e), _) => &e.attrs,
+        Stmt::Expr(Expr::Macro(e), _) => &e.attrs,
+        Stmt::Expr(Expr::MethodCall(e), _) => &e.attrs,
+        Stmt::Expr(Expr::Unsafe(e), _) => &e.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+/// Will return true for `#[repr(packed)]`, including `#[repr(C, packed(2))]`.
+fn has_repr_packed(attrs: &[Attribute]) -> bool {
+    attribute_metas(attrs).any(|m| match m {
+        Meta::List(ml) if ml.path.is_ident("repr") => {
+            nested_metas(&ml).iter().any(|n| match n {
+                Meta::Path(p) => p.is_ident("packed"),
+                Meta::List(l) => l.path.is_ident("packed"),
+                _ => false,
+            })
+        }
+        _ => false,
+    })
+}
+
+/// Will return true for `safe fn` and `safe static` declarations, allowed
+/// inside Rust 2024 `unsafe extern` blocks. `syn` keeps these as verbatim
+/// tokens.
+fn is_safe_foreign_item(foreign_item: &syn::ForeignItem) -> bool {
+    match foreign_item {
+        syn::ForeignItem::Verbatim(tokens) => tokens
+            .clone()
+            .into_iter()
+            .take_while(|token_tree| match token_tree {
+                TokenTree::Ident(ident) => ident != "fn" && ident != "static",
+                _ => true,
+            })
+            .any(|token_tree| match token_tree {
+                TokenTree::Ident(ident) => ident == "safe",
+                _ => false,
+            }),
+        _ => false,
+    }
+}
+
+fn is_asm_macro(mac: &syn::Macro) -> bool {
+    mac.path
+        .segments
+        .last()
+        .map(|segment| segment.ident == "asm" || segment.ident == "global_asm")
+        .unwrap_or(false)
+}
+
+/// Will return true for calls like `transmute(x)` and
+/// `std::mem::transmute::<A, B>(x)`.
+fn is_transmute_call(expr_call: &syn::ExprCall) -> bool {
+    match &*expr_call.func {
+        Expr::Path(expr_path) => expr_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "transmute")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Methods of raw pointers that return a raw pointer again.
+const RAW_POINTER_METHODS: [&str; 10] = [
+    "add",
+    "byte_add",
+    "byte_sub",
+    "cast",
+    "cast_const",
+    "cast_mut",
+    "offset",
+    "sub",
+    "wrapping_add",
+    "wrapping_sub",
+];
+
+/// Will return true if `expr` is known to be a raw pointer without type
+/// information: a cast to a raw pointer type, one of the `raw_pointers`
+/// bindings, the result of a function from a `ptr` module, of `as_ptr()` or
+/// `as_mut_ptr()`, or of pointer arithmetic on a raw pointer.
+fn is_raw_pointer_expr(expr: &Expr, raw_pointers: &[String]) -> bool {
+    match expr {
+        Expr::Paren(expr_paren) => {
+            is_raw_pointer_expr(&expr_paren.expr, raw_pointers)
+        }
+        Expr::Group(expr_group) => {
+            is_raw_pointer_expr(&expr_group.expr, raw_pointers)
+        }
+        Expr::Cast(expr_cast) => matches!(*expr_cast.ty, syn::Type::Ptr(_)),
+        Expr::Path(expr_path) => expr_path
+            .path
+            .get_ident()
+            .map(|ident| raw_pointers.iter().any(|name| ident == name))
+            .unwrap_or(false),
+        Expr::Call(expr_call) => match &*expr_call.func {
+            Expr::Path(expr_path) => {
+                let segments = &expr_path.path.segments;
+                segments.len() > 1
+                    && segments
+                        .iter()
+                        .take(segments.len() - 1)
+                        .any(|segment| segment.ident == "ptr")
+            }
+            _ => false,
+        },
+        Expr::MethodCall(expr_method_call) => {
+            let method = &expr_method_call.method;
+            method == "as_ptr"
+                || method == "as_mut_ptr"
+                || (RAW_POINTER_METHODS.iter().any(|name| method == name)
+                    && is_raw_pointer_expr(
+                        &expr_method_call.receiver,
+                        raw_pointers,
+                    ))
+        }
+        _ => false,
+    }
+}
+
+/// Standard library macros taking expressions as arguments, the only macros
+/// that get their input parsed.
+const EXPR_MACROS: [&str; 21] = [
+    "assert",
+    "assert_eq",
+    "assert_ne",
+    "dbg",
+    "debug_assert",
+    "debug_assert_eq",
+    "debug_assert_ne",
+    "eprint",
+    "eprintln",
+    "format",
+    "format_args",
+    "matches",
+    "panic",
+    "print",
+    "println",
+    "todo",
+    "unimplemented",
+    "unreachable",
+    "vec",
+    "write",
+    "writeln",
+];
+
+/// The input of a well-known macro, parsed as regular Rust syntax.
+enum MacroBody {
+    Exprs(Vec<Expr>),
+    Block(Block),
+    Items(Vec<Item>),
+}
+
+/// Attempts to parse the input of a well-known macro as comma separated
+/// expressions, a block (e.g. `vec![x; n]`) or items. Returns `None` for
+/// other macros, their input can be any token soup.
+fn parse_macro_body(mac: &syn::Macro) -> Option<MacroBody> {
+    use syn::parse::Parser;
+    use syn::punctuated::Punctuated;
+    use syn::Token;
+
+    let name = mac.path.segments.last()?.ident.to_string();
+    if !EXPR_MACROS.contains(&name.as_str()) {
+        return None;
+    }
+    if let Ok(exprs) = Punctuated::<Expr, Token![,]>::parse_terminated
+        .parse2(mac.tokens.clone())
+    {
+        return Some(MacroBody::Exprs(exprs.into_iter().collect()));
+    }
+    let braced =
+        TokenTree::Group(Group::new(Delimiter::Brace, mac.tokens.clone()));
+    if let Ok(block) = syn::parse2::<Block>(braced.into()) {
+        return Some(MacroBody::Block(block));
+    }
+    syn::parse2::<syn::File>(mac.tokens.clone())
+        .ok()
+        .map(|file| MacroBody::Items(file.items))
+}
+
+/// Will return true if the `unsafe` keyword appears anywhere in the tokens,
+/// including nested groups.
+fn tokens_contain_unsafe(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token_tree| match token_tree {
+        TokenTree::Ident(ident) => ident == "unsafe",
+        TokenTree::Group(group) => tokens_contain_unsafe(group.stream()),
+        _ => false,
+    })
 }
 
 /// Will return true for #[cfg(test)] decorated modules.
@@ -113,27 +625,20 @@ fn has_unsafe_attributes(item_fn: &ItemFn) -> bool {
 /// as a general filter for included code.
 /// TODO: Investigate if the needed information can be emitted by rustc today.
 fn is_test_mod(i: &ItemMod) -> bool {
-    use syn::Attribute;
-    use syn::Meta;
-    i.attrs
-        .iter()
-        .flat_map(Attribute::parse_meta)
-        .any(|m| match m {
-            Meta::List(ml) => meta_list_is_cfg_test(&ml),
-            _ => false,
-        })
+    attribute_metas(&i.attrs).any(|m| match m {
+        Meta::List(ml) => meta_list_is_cfg_test(&ml),
+        _ => false,
+    })
 }
 
-fn meta_contains_ident(m: &syn::Meta, ident: &str) -> bool {
-    use syn::Meta;
+fn meta_contains_ident(m: &Meta, ident: &str) -> bool {
     match m {
         Meta::Path(p) => p.is_ident(ident),
         _ => false,
     }
 }
 
-fn meta_contains_attribute(m: &syn::Meta, ident: &str) -> bool {
-    use syn::Meta;
+fn meta_contains_attribute(m: &Meta, ident: &str) -> bool {
     match m {
         Meta::NameValue(nv) => nv.path.is_ident(ident),
         _ => false,
@@ -141,27 +646,27 @@ fn meta_contains_attribute(m: &syn::Meta, ident: &str) -> bool {
 }
 
 // MetaList {
-//     ident: Ident(
-//         cfg
-//     ),
-//     paren_token: Paren,
-//     nested: [
-//         Meta(
-//             Word(
-//                 Ident(
-//                     test
-//                 )
-//             )
-//         )
-//     ]
+//     path: Path {
+//         segments: [
+//             PathSegment {
+//                 ident: Ident(
+//                     cfg
+//                 ),
+//             },
+//         ],
+//     },
+//     delimiter: Paren,
+//     tokens: TokenStream [
+//         Ident {
+//             sym: test,
+//         },
+//     ],
 // }
 fn meta_list_is_cfg_test(meta_list: &syn::MetaList) -> bool {
-    use syn::NestedMeta;
     if !meta_list.path.is_ident("cfg") {
         return false;
     }
-    meta_list.nested.iter().any(|n| match n {
-        NestedMeta::Meta(meta) => meta_contains_ident(meta, "test"),
-        _ => false,
-    })
+    nested_metas(meta_list)
+        .iter()
+        .any(|meta| meta_contains_ident(meta, "test"))
 }
@@ -0,0 +1,276 @@
+use super::ScanFileError;
+
+use crate::find::read_file_to_string;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use syn::{Expr, Item, ItemMacro, ItemMod, Lit, Meta};
+
+/// The `.rs` files reachable from a crate entry point by following `mod`
+/// declarations, `#[path = ".."]` attributes and `include!(..)` invocations.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleTree {
+    /// The module path of every reachable file, e.g. `foo::bar`. The entry
+    /// point has an empty module path and an `include!`d file shares the
+    /// module path of the module including it.
+    pub file_to_module_path: HashMap<PathBuf, String>,
+}
+
+impl ModuleTree {
+    /// Whether `path` is reachable from the entry point.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.file_to_module_path.contains_key(path)
+    }
+}
+
+/// Where the `mod` declarations of a file are resolved from.
+struct ModuleFile {
+    path: PathBuf,
+    module_path: Vec<String>,
+
+    /// The directory of `mod foo;` declared directly in this file, i.e. the
+    /// directory of the file for `mod.rs` files, crate roots and files
+    /// loaded with `#[path]`, otherwise a subdirectory named after the file.
+    mod_dir: PathBuf,
+}
+
+/// Resolves the module tree of the crate with the given entry point, e.g. the
+/// `src_path` of a build target. Declared modules without a matching file are
+/// skipped, as are files that fail to parse, since both are commonly only
+/// present for some `cfg` or generated by a build script. Only failing to
+/// read or parse the entry point itself is an error.
+pub fn resolve_module_tree(
+    entry_point: &Path,
+) -> Result<ModuleTree, ScanFileError> {
+    let entry_point = canonical_path(entry_point);
+    let src = read_file_to_string(&entry_point)?;
+    let file = syn::parse_file(&src)
+        .map_err(|e| ScanFileError::Syn(e, entry_point.clone()))?;
+
+    let mut module_tree = ModuleTree::default();
+    let module_file = ModuleFile {
+        mod_dir: parent_dir(&entry_point),
+        path: entry_point,
+        module_path: vec![],
+    };
+    resolve_file(&module_file, &file.items, &mut module_tree);
+    Ok(module_tree)
+}
+
+fn resolve_file(
+    module_file: &ModuleFile,
+    items: &[Item],
+    module_tree: &mut ModuleTree,
+) {
+    module_tree
+        .file_to_module_path
+        .insert(module_file.path.clone(), module_file.module_path.join("::"));
+    resolve_items(module_file, &[], items, module_tree);
+}
+
+fn resolve_items(
+    module_file: &ModuleFile,
+    inline_mods: &[String],
+    items: &[Item],
+    module_tree: &mut ModuleTree,
+) {
+    for item in items {
+        match item {
+            Item::Mod(item_mod) => resolve_item_mod(
+                module_file,
+                inline_mods,
+                item_mod,
+                module_tree,
+            ),
+            Item::Macro(item_macro) => resolve_include(
+                module_file,
+                inline_mods,
+                item_macro,
+                module_tree,
+            ),
+            _ => {}
+        }
+    }
+}
+
+fn resolve_item_mod(
+    module_file: &ModuleFile,
+    inline_mods: &[String],
+    item_mod: &ItemMod,
+    module_tree: &mut ModuleTree,
+) {
+    let name = item_mod.ident.to_string();
+    if let Some((_, items)) = &item_mod.content {
+        let mut inline_mods = inline_mods.to_vec();
+        inline_mods.push(name);
+        resolve_items(module_file, &inline_mods, items, module_tree);
+        return;
+    }
+
+    let inline_dir = inline_mods
+        .iter()
+        .fold(module_file.mod_dir.clone(), |dir, m| dir.join(m));
+    let (path, mod_dir) = match path_attribute(item_mod) {
+        Some(path_attr) => {
+            // Outside of inline modules `#[path]` is relative to the
+            // directory of the declaring file, even for non-`mod.rs` files.
+            let base_dir = match inline_mods.is_empty() {
+                true => parent_dir(&module_file.path),
+                false => inline_dir,
+            };
+            let path = base_dir.join(path_attr);
+            let mod_dir = parent_dir(&path);
+            (path, mod_dir)
+        }
+        None => {
+            let file_path = inline_dir.join(format!("{}.rs", name));
+            let mod_rs_path = inline_dir.join(&name).join("mod.rs");
+            if file_path.is_file() {
+                (file_path, inline_dir.join(&name))
+            } else {
+                (mod_rs_path, inline_dir.join(&name))
+            }
+        }
+    };
+    let path = canonical_path(&path);
+    if module_tree.contains(&path) {
+        return;
+    }
+    let file = match read_file_to_string(&path)
+        .ok()
+        .and_then(|src| syn::parse_file(&src).ok())
+    {
+        Some(file) => file,
+        None => return,
+    };
+
+    let mut module_path = module_file.module_path.clone();
+    module_path.extend(inline_mods.iter().cloned());
+    module_path.push(name);
+    let child = ModuleFile {
+        path,
+        module_path,
+        mod_dir,
+    };
+    resolve_file(&child, &file.items, module_tree);
+}
+
+fn resolve_include(
+    module_file: &ModuleFile,
+    inline_mods: &[String],
+    item_macro: &ItemMacro,
+    module_tree: &mut ModuleTree,
+) {
+    if !item_macro.mac.path.is_ident("include") {
+        return;
+    }
+    // Only string literals can be resolved, `include!(concat!(env!(..)))`
+    // is typically build script output outside of the package.
+    let included = match item_macro.mac.parse_body::<Expr>() {
+        Ok(Expr::Lit(expr_lit)) => match expr_lit.lit {
+            Lit::Str(lit_str) => lit_str.value(),
+            _ => return,
+        },
+        _ => return,
+    };
+    let path = canonical_path(&parent_dir(&module_file.path).join(included));
+    if module_tree.contains(&path) || !path.is_file() {
+        return;
+    }
+    let mut module_path = module_file.module_path.clone();
+    module_path.extend(inline_mods.iter().cloned());
+    module_tree
+        .file_to_module_path
+        .insert(path, module_path.join("::"));
+}
+
+/// The value of a `#[path = ".."]` attribute.
+fn path_attribute(item_mod: &ItemMod) -> Option<String> {
+    item_mod.attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::NameValue(name_value) if name_value.path.is_ident("path") => {
+            match &name_value.value {
+                Expr::Lit(expr_lit) => match &expr_lit.lit {
+                    Lit::Str(lit_str) => Some(lit_str.value()),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// Canonicalizes `path` when it exists, so that the resolved paths match the
+/// canonical paths used by `cargo-geiger`.
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod module_tree_tests {
+    use super::*;
+
+    use rstest::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[rstest]
+    fn resolve_module_tree_test() {
+        let temp_dir = tempdir().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        let files = [
+            (
+                "lib.rs",
+                "mod a;\nmod b;\nmod inline { mod c; }\n\
+                 #[path = \"other/d.rs\"] mod d;\ninclude!(\"e.in\");\n\
+                 include!(concat!(env!(\"OUT_DIR\"), \"/f.rs\"));\nmod missing;",
+            ),
+            ("a.rs", "mod nested;"),
+            ("a/nested.rs", ""),
+            ("b/mod.rs", "mod nested;"),
+            ("b/nested.rs", ""),
+            ("inline/c.rs", ""),
+            ("other/d.rs", "mod nested;"),
+            ("other/nested.rs", ""),
+            ("e.in", ""),
+            ("unused.rs", ""),
+        ];
+        for (path, content) in files.iter() {
+            let path = src_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let module_tree = resolve_module_tree(&src_dir.join("lib.rs")).unwrap();
+
+        let src_dir = src_dir.canonicalize().unwrap();
+        let expected = [
+            ("lib.rs", ""),
+            ("a.rs", "a"),
+            ("a/nested.rs", "a::nested"),
+            ("b/mod.rs", "b"),
+            ("b/nested.rs", "b::nested"),
+            ("inline/c.rs", "inline::c"),
+            ("other/d.rs", "d"),
+            ("other/nested.rs", "d::nested"),
+            ("e.in", ""),
+        ]
+        .iter()
+        .map(|(path, module_path)| {
+            (src_dir.join(path), String::from(*module_path))
+        })
+        .collect::<HashMap<PathBuf, String>>();
+        assert_eq!(module_tree.file_to_module_path, expected);
+    }
+
+    #[rstest]
+    fn resolve_module_tree_test_missing_entry_point() {
+        let temp_dir = tempdir().unwrap();
+        let result = resolve_module_tree(&temp_dir.path().join("lib.rs"));
+        assert!(matches!(result, Err(ScanFileError::Io(_, _))));
+    }
+}
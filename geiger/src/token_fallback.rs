@@ -0,0 +1,498 @@
+//! A rough scan of the tokens of a file that `syn` can not parse, e.g. because
+//! it uses syntax newer than the bundled `syn` understands. Items are
+//! recognized by their leading keywords instead of a syntax tree, so `unsafe`
+//! usage is still counted by its context, but the counts are approximate:
+//! unsafe blocks count as a single unsafe expression, safe expressions and
+//! `cfg` attributes that do not require `test` are ignored.
+
+use super::{
+    has_safety_comment, tokens_contain_unsafe, IncludeTests, RsFileMetrics,
+};
+
+use cargo_geiger_serde::{LineColumn, LintLevel, UnsafeFinding, UnsafeKind};
+use proc_macro2::{Delimiter, Spacing, Span, TokenStream, TokenTree};
+use std::path::PathBuf;
+
+/// Keywords that may be followed by a `!` negating an expression, e.g.
+/// `if !done { .. }`, which must not be mistaken for a macro invocation.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "do", "dyn",
+    "else", "for", "if", "in", "let", "loop", "match", "move", "mut", "ref",
+    "return", "static", "unsafe", "where", "while", "yield",
+];
+
+/// Kind of the items that a brace delimited group contains
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    /// Module level items, or statements of a function body
+    Items,
+    /// Associated items of an `impl` or `trait`
+    Associated,
+    /// Items of an `extern` block
+    Foreign,
+}
+
+pub fn find_unsafe_in_tokens(
+    tokens: TokenStream,
+    include_tests: IncludeTests,
+    source_lines: &[String],
+) -> RsFileMetrics {
+    let mut scanner = TokenScanner {
+        include_tests,
+        metrics: RsFileMetrics {
+            approximate: true,
+            ..Default::default()
+        },
+        item_path: vec![],
+        source_lines,
+    };
+    scanner.scan(tokens, Scope::Items, true);
+    scanner.metrics
+}
+
+struct TokenScanner<'a> {
+    include_tests: IncludeTests,
+    metrics: RsFileMetrics,
+    item_path: Vec<String>,
+    source_lines: &'a [String],
+}
+
+impl TokenScanner<'_> {
+    fn scan(&mut self, tokens: TokenStream, scope: Scope, is_file: bool) {
+        let tokens = tokens.into_iter().collect::<Vec<TokenTree>>();
+        // Name and scope of the item whose body is the next brace group.
+        let mut pending_body: Option<(Option<String>, Scope)> = None;
+        let mut skip_item = false;
+        let mut i = 0;
+        while i < tokens.len() {
+            let is_item_start = is_item_start(&tokens, i);
+            match &tokens[i] {
+                TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                    let is_inner = is_punct(tokens.get(i + 1), '!');
+                    let attr_index = if is_inner { i + 2 } else { i + 1 };
+                    if let Some(TokenTree::Group(attr)) = tokens.get(attr_index)
+                    {
+                        if is_inner && is_file {
+                            self.handle_inner_attribute(attr.stream());
+                        }
+                        if self.include_tests == IncludeTests::No
+                            && is_test_attribute(attr.stream())
+                        {
+                            skip_item = true;
+                        }
+                        i = attr_index;
+                    }
+                }
+                TokenTree::Punct(punct) if punct.as_char() == ';' => {
+                    pending_body = None;
+                    skip_item = false;
+                }
+                TokenTree::Group(group)
+                    if group.delimiter() == Delimiter::Brace =>
+                {
+                    let body = pending_body.take();
+                    if skip_item {
+                        skip_item = false;
+                    } else if let Some((name, body_scope)) = body {
+                        let has_name = name.is_some();
+                        self.item_path.extend(name);
+                        self.scan(group.stream(), body_scope, false);
+                        if has_name {
+                            self.item_path.pop();
+                        }
+                    } else {
+                        self.scan(group.stream(), Scope::Items, false);
+                    }
+                }
+                TokenTree::Group(group) if !skip_item => {
+                    self.scan(group.stream(), Scope::Items, false);
+                }
+                _ if skip_item => {}
+                TokenTree::Ident(ident) if ident == "unsafe" => {
+                    match tokens.get(i + 1) {
+                        Some(TokenTree::Group(group))
+                            if group.delimiter() == Delimiter::Brace =>
+                        {
+                            self.metrics.counters.exprs.count(true);
+                            self.add_finding(
+                                UnsafeKind::Block,
+                                ident.span(),
+                                group.span_close(),
+                                true,
+                            );
+                        }
+                        Some(TokenTree::Ident(next)) if next == "impl" => {
+                            self.metrics.counters.item_impls.count(true);
+                            let name = impl_path_segment(&tokens, i + 1);
+                            self.item_path.extend(name.clone());
+                            self.add_finding(
+                                UnsafeKind::ItemImpl,
+                                ident.span(),
+                                next.span(),
+                                true,
+                            );
+                            if name.is_some() {
+                                self.item_path.pop();
+                            }
+                            pending_body = Some((name, Scope::Associated));
+                            i += 1;
+                        }
+                        Some(TokenTree::Ident(next)) if next == "trait" => {
+                            self.metrics.counters.item_traits.count(true);
+                            let name = match tokens.get(i + 2) {
+                                Some(TokenTree::Ident(name)) => {
+                                    Some(name.to_string())
+                                }
+                                _ => None,
+                            };
+                            self.item_path.extend(name.clone());
+                            self.add_finding(
+                                UnsafeKind::ItemTrait,
+                                ident.span(),
+                                next.span(),
+                                false,
+                            );
+                            if name.is_some() {
+                                self.item_path.pop();
+                            }
+                            pending_body = Some((name, Scope::Associated));
+                            i += 1;
+                        }
+                        _ => {
+                            // `unsafe fn` and `unsafe extern` are handled by
+                            // the `fn` and `extern` keywords.
+                        }
+                    }
+                }
+                TokenTree::Ident(ident) if ident == "fn" => {
+                    if let Some(TokenTree::Ident(name)) = tokens.get(i + 1) {
+                        let unsafe_fn =
+                            is_ident(preceding_qualifier(&tokens, i), "unsafe");
+                        let counter = match scope {
+                            Scope::Associated => {
+                                &mut self.metrics.counters.methods
+                            }
+                            Scope::Foreign => {
+                                &mut self.metrics.counters.foreign_items
+                            }
+                            Scope::Items => {
+                                &mut self.metrics.counters.functions
+                            }
+                        };
+                        counter.count(unsafe_fn || scope == Scope::Foreign);
+                        self.item_path.push(name.to_string());
+                        if unsafe_fn {
+                            let kind = match scope {
+                                Scope::Associated => UnsafeKind::Method,
+                                _ => UnsafeKind::Function,
+                            };
+                            self.add_finding(
+                                kind,
+                                ident.span(),
+                                name.span(),
+                                false,
+                            );
+                        }
+                        self.item_path.pop();
+                        pending_body =
+                            Some((Some(name.to_string()), Scope::Items));
+                        i += 1;
+                    }
+                }
+                // Not the `'static` lifetime.
+                TokenTree::Ident(ident)
+                    if ident == "static"
+                        && !(i > 0 && is_punct(tokens.get(i - 1), '\'')) =>
+                {
+                    if scope == Scope::Foreign {
+                        self.metrics.counters.foreign_items.count(true);
+                    } else {
+                        let is_mut = is_ident(tokens.get(i + 1), "mut");
+                        self.metrics.counters.statics.count(is_mut);
+                    }
+                }
+                TokenTree::Ident(ident) if ident == "extern" => {
+                    let mut next = i + 1;
+                    if let Some(TokenTree::Literal(_)) = tokens.get(next) {
+                        next += 1;
+                    }
+                    if let Some(TokenTree::Group(group)) = tokens.get(next) {
+                        if group.delimiter() == Delimiter::Brace {
+                            pending_body = Some((None, Scope::Foreign));
+                            i = next - 1;
+                        }
+                    }
+                }
+                TokenTree::Ident(ident) if ident == "impl" && is_item_start => {
+                    self.metrics.counters.item_impls.count(false);
+                    pending_body = Some((
+                        impl_path_segment(&tokens, i),
+                        Scope::Associated,
+                    ));
+                }
+                TokenTree::Ident(ident)
+                    if ident == "trait" && is_item_start =>
+                {
+                    self.metrics.counters.item_traits.count(false);
+                    let name = match tokens.get(i + 1) {
+                        Some(TokenTree::Ident(name)) => Some(name.to_string()),
+                        _ => None,
+                    };
+                    pending_body = Some((name, Scope::Associated));
+                }
+                TokenTree::Ident(ident) if ident == "mod" => {
+                    if let Some(TokenTree::Ident(name)) = tokens.get(i + 1) {
+                        pending_body =
+                            Some((Some(name.to_string()), Scope::Items));
+                        i += 1;
+                    }
+                }
+                TokenTree::Ident(ident)
+                    if !KEYWORDS.contains(&ident.to_string().as_str())
+                        && is_macro_bang(tokens.get(i + 1)) =>
+                {
+                    // A macro invocation or `macro_rules!` definition, its
+                    // input is opaque.
+                    let mut group_index = i + 2;
+                    if let Some(TokenTree::Ident(_)) = tokens.get(group_index) {
+                        group_index += 1;
+                    }
+                    if let Some(TokenTree::Group(group)) =
+                        tokens.get(group_index)
+                    {
+                        let is_unsafe = tokens_contain_unsafe(group.stream());
+                        self.metrics.counters.macros.count(is_unsafe);
+                        if is_unsafe {
+                            self.add_finding(
+                                UnsafeKind::Macro,
+                                tokens[i].span(),
+                                group.span_close(),
+                                false,
+                            );
+                        }
+                        i = group_index;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Reads the level of the `unsafe_code` and `unsafe_op_in_unsafe_fn`
+    /// lints from an inner attribute like `#![forbid(unsafe_code)]`.
+    fn handle_inner_attribute(&mut self, attr: TokenStream) {
+        let mut attr = attr.into_iter();
+        let level = match attr.next() {
+            Some(TokenTree::Ident(level)) => match level.to_string().as_str() {
+                "allow" => LintLevel::Allow,
+                "warn" => LintLevel::Warn,
+                "deny" => LintLevel::Deny,
+                "forbid" => LintLevel::Forbid,
+                _ => return,
+            },
+            _ => return,
+        };
+        let lints = match attr.next() {
+            Some(TokenTree::Group(lints)) => lints.stream(),
+            _ => return,
+        };
+        for lint in lints {
+            match lint {
+                TokenTree::Ident(lint) if lint == "unsafe_code" => {
+                    self.metrics.unsafe_code_lint = Some(level);
+                    self.metrics.forbids_unsafe = level == LintLevel::Forbid;
+                }
+                TokenTree::Ident(lint) if lint == "unsafe_op_in_unsafe_fn" => {
+                    self.metrics.unsafe_op_in_unsafe_fn_lint = Some(level);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn add_finding(
+        &mut self,
+        kind: UnsafeKind,
+        start: Span,
+        end: Span,
+        check_safety_comment: bool,
+    ) {
+        let (start, end) = (start.start(), end.end());
+        let start = LineColumn {
+            line: start.line,
+            column: start.column,
+        };
+        let documented = match check_safety_comment {
+            true => Some(has_safety_comment(self.source_lines, &start)),
+            false => None,
+        };
+        self.metrics.findings.push(UnsafeFinding {
+            file: PathBuf::new(),
+            start,
+            end: LineColumn {
+                line: end.line,
+                column: end.column,
+            },
+            kind,
+            item_path: self.item_path.join("::"),
+            documented,
+        });
+    }
+}
+
+/// Will return true if the token at `index` can start an item, i.e. it does
+/// not continue a type or an expression.
+fn is_item_start(tokens: &[TokenTree], index: usize) -> bool {
+    let previous = match index.checked_sub(1) {
+        Some(previous) => &tokens[previous],
+        None => return true,
+    };
+    match previous {
+        TokenTree::Punct(punct) => punct.as_char() == ';',
+        TokenTree::Group(group) => match group.delimiter() {
+            Delimiter::Brace | Delimiter::Bracket => true,
+            // `pub(crate)`
+            _ => index >= 2 && is_ident(tokens.get(index - 2), "pub"),
+        },
+        TokenTree::Ident(ident) => ident == "pub" || ident == "default",
+        TokenTree::Literal(_) => false,
+    }
+}
+
+/// The item path segment of the `impl` at `impl_index`, like the one of the
+/// syntax tree visitor: the self type, or `<Self as Trait>` for trait impls.
+/// Generic arguments are left out, `None` if the self type is not a path.
+fn impl_path_segment(
+    tokens: &[TokenTree],
+    impl_index: usize,
+) -> Option<String> {
+    let mut trait_path: Option<Vec<String>> = None;
+    let mut path = vec![];
+    let mut angle_depth = 0usize;
+    for (index, token) in tokens.iter().enumerate().skip(impl_index + 1) {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == '<' => {
+                angle_depth += 1
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '>' => {
+                angle_depth = angle_depth.saturating_sub(1)
+            }
+            TokenTree::Group(group)
+                if group.delimiter() == Delimiter::Brace =>
+            {
+                break
+            }
+            _ if angle_depth > 0 => {}
+            TokenTree::Ident(ident) if ident == "where" => break,
+            TokenTree::Ident(ident) if ident == "for" => {
+                trait_path = Some(path);
+                path = vec![];
+            }
+            // Not the name of a lifetime.
+            TokenTree::Ident(ident)
+                if !is_punct(tokens.get(index - 1), '\'')
+                    && ident != "dyn"
+                    && ident != "mut" =>
+            {
+                path.push(ident.to_string())
+            }
+            _ => {}
+        }
+    }
+    if path.is_empty() {
+        return None;
+    }
+    let self_type = path.join("::");
+    Some(match trait_path {
+        Some(trait_path) => {
+            format!("<{} as {}>", self_type, trait_path.join("::"))
+        }
+        None => self_type,
+    })
+}
+
+/// Will return true for the attributes of test items, e.g. `#[test]`,
+/// `#[tokio::test]` or `#[cfg(all(test, feature = "x"))]`.
+fn is_test_attribute(attr: TokenStream) -> bool {
+    let tokens = attr.into_iter().collect::<Vec<TokenTree>>();
+    match tokens.as_slice() {
+        [TokenTree::Ident(cfg), TokenTree::Group(predicate)]
+            if cfg == "cfg" =>
+        {
+            cfg_requires_test(predicate.stream())
+        }
+        _ => {
+            // A path ending in `test`, optionally followed by arguments.
+            let path = match tokens.last() {
+                Some(TokenTree::Group(_)) => &tokens[..tokens.len() - 1],
+                _ => &tokens[..],
+            };
+            is_ident(path.last(), "test")
+                && path.iter().all(|token| match token {
+                    TokenTree::Ident(_) => true,
+                    TokenTree::Punct(punct) => punct.as_char() == ':',
+                    _ => false,
+                })
+        }
+    }
+}
+
+/// Will return true if the `cfg` predicate can only hold when compiling
+/// tests, i.e. it is `test` or an `all(..)` requiring `test`.
+fn cfg_requires_test(predicate: TokenStream) -> bool {
+    let tokens = predicate.into_iter().collect::<Vec<TokenTree>>();
+    match tokens.as_slice() {
+        [TokenTree::Ident(test)] => test == "test",
+        [TokenTree::Ident(all), TokenTree::Group(predicates)]
+            if all == "all" =>
+        {
+            predicates
+                .stream()
+                .into_iter()
+                .collect::<Vec<TokenTree>>()
+                .split(|token| is_punct(Some(token), ','))
+                .any(|predicate| {
+                    cfg_requires_test(predicate.iter().cloned().collect())
+                })
+        }
+        _ => false,
+    }
+}
+
+/// The token before `fn`, skipping an `extern "abi"` and `const` or `async`.
+fn preceding_qualifier(
+    tokens: &[TokenTree],
+    fn_index: usize,
+) -> Option<&TokenTree> {
+    let mut index = fn_index;
+    while index > 0 {
+        index -= 1;
+        match &tokens[index] {
+            TokenTree::Literal(_) => {}
+            TokenTree::Ident(ident)
+                if ident == "extern"
+                    || ident == "const"
+                    || ident == "async" => {}
+            token => return Some(token),
+        }
+    }
+    None
+}
+
+/// The `!` of a macro invocation, not of a `!=` operator.
+fn is_macro_bang(token: Option<&TokenTree>) -> bool {
+    matches!(
+        token,
+        Some(TokenTree::Punct(punct))
+            if punct.as_char() == '!' && punct.spacing() == Spacing::Alone
+    )
+}
+
+fn is_ident(token: Option<&TokenTree>, name: &str) -> bool {
+    matches!(token, Some(TokenTree::Ident(ident)) if ident == name)
+}
+
+fn is_punct(token: Option<&TokenTree>, ch: char) -> bool {
+    matches!(token, Some(TokenTree::Punct(punct)) if punct.as_char() == ch)
+}
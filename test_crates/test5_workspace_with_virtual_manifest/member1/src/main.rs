@@ -1,5 +1,5 @@
 fn main() {
     let bytes = b"string";
     let ptr = bytes as *const u8;
-    unsafe { *ptr };
+    let _ = unsafe { *ptr };
 }